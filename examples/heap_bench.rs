@@ -0,0 +1,34 @@
+use std::time::Instant;
+
+use playground_rs::data_structure::{
+    binary_heap::BinaryHeap, dary_heap::DaryHeap, leftist_heap::LeftistHeap,
+    priority_queue::PriorityQueue, skew_heap::SkewHeap,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Interleave pushes and pops, then drain, returning a checksum so the work isn't optimized away.
+fn bench<Q: PriorityQueue<u64>>(name: &str, mut heap: Q, data: &[u64]) {
+    let start = Instant::now();
+    let mut sum = 0u64;
+    for (i, &x) in data.iter().enumerate() {
+        heap.push(x);
+        if i % 3 == 0 {
+            sum = sum.wrapping_add(heap.pop().unwrap());
+        }
+    }
+    while let Some(x) = heap.pop() {
+        sum = sum.wrapping_add(x);
+    }
+    println!("{name:>12}: {:?} (checksum {sum})", start.elapsed());
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let data: Vec<u64> = (0..1_000_000).map(|_| rng.gen()).collect();
+
+    bench("binary", BinaryHeap::new(), &data);
+    bench("4-ary", DaryHeap::<_, 4>::new(), &data);
+    bench("8-ary", DaryHeap::<_, 8>::new(), &data);
+    bench("leftist", LeftistHeap::new(), &data);
+    bench("skew", SkewHeap::new(), &data);
+}
@@ -0,0 +1,62 @@
+use std::time::Instant;
+
+use playground_rs::data_structure::trie::{RadixTrie, Trie, TrieImpl};
+
+fn trie_node_count<S>(node: &TrieImpl<(), S>) -> usize {
+    1 + node.next.values().map(|n| trie_node_count(n)).sum::<usize>()
+}
+
+fn trie_get<'a, S>(root: &'a Trie<S>, key: &str) -> Option<&'a S> {
+    let mut cur = root;
+    for c in key.chars() {
+        cur = cur.next.get(&c)?;
+    }
+    cur.attached_info.as_ref()
+}
+
+fn main() {
+    // URL-like paths sharing long prefixes.
+    let paths: Vec<String> = (0..20_000)
+        .map(|i| {
+            format!(
+                "/api/v1/organizations/{}/projects/{}/resources/{}",
+                i % 50,
+                i % 400,
+                i
+            )
+        })
+        .collect();
+
+    let mut trie = Trie::new_root();
+    let mut radix = RadixTrie::new();
+    for (i, p) in paths.iter().enumerate() {
+        trie.insert(p.chars(), i);
+        radix.insert(p, i);
+    }
+
+    println!("Trie nodes:      {}", trie_node_count(&trie));
+    println!("RadixTrie nodes: {}", radix.node_count());
+
+    let start = Instant::now();
+    let mut sum = 0;
+    for p in &paths {
+        sum += trie_get(&trie, p).unwrap();
+    }
+    println!("Trie lookups:      {:?} ({sum})", start.elapsed());
+
+    let start = Instant::now();
+    let mut sum = 0;
+    for p in &paths {
+        sum += radix.get(p).unwrap();
+    }
+    println!("RadixTrie lookups: {:?} ({sum})", start.elapsed());
+
+    let start = Instant::now();
+    let converted = RadixTrie::from(trie);
+    println!(
+        "Converted {} keys into {} nodes in {:?}",
+        converted.len(),
+        converted.node_count(),
+        start.elapsed()
+    );
+}
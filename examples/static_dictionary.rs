@@ -0,0 +1,50 @@
+use std::time::Instant;
+
+use playground_rs::data_structure::trie::{Dawg, DoubleArrayTrie, Trie, TrieImpl};
+
+fn trie_node_count<S>(node: &TrieImpl<(), S>) -> usize {
+    1 + node.next.values().map(|n| trie_node_count(n)).sum::<usize>()
+}
+
+fn main() {
+    // Inflected forms share both prefixes and suffixes.
+    let stems: Vec<String> = (0..20_000).map(|i| format!("w{i:x}")).collect();
+    let suffixes = ["", "s", "ed", "ing", "er", "ers"];
+    let mut words: Vec<String> = stems
+        .iter()
+        .flat_map(|s| suffixes.iter().map(move |x| format!("{s}{x}")))
+        .collect();
+    words.sort();
+    words.dedup();
+
+    let mut trie = Trie::new_root();
+    for (i, w) in words.iter().enumerate() {
+        trie.insert(w.chars(), i);
+    }
+    println!("{} words, Trie nodes: {}", words.len(), trie_node_count(&trie));
+
+    let start = Instant::now();
+    let da = DoubleArrayTrie::build(words.iter().enumerate().map(|(i, w)| (w, i)));
+    println!(
+        "DoubleArrayTrie: {} slots, built in {:?}",
+        da.array_len(),
+        start.elapsed()
+    );
+
+    let start = Instant::now();
+    let dawg = Dawg::from_sorted(words.iter().enumerate().map(|(i, w)| (w, i)));
+    println!(
+        "Dawg: {} states, {} edges, built in {:?}",
+        dawg.state_count(),
+        dawg.edge_count(),
+        start.elapsed()
+    );
+
+    let start = Instant::now();
+    assert!(words.iter().all(|w| da.contains(w)));
+    println!("DoubleArrayTrie lookups: {:?}", start.elapsed());
+
+    let start = Instant::now();
+    assert!(words.iter().all(|w| dawg.contains(w)));
+    println!("Dawg lookups: {:?}", start.elapsed());
+}
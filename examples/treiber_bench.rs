@@ -0,0 +1,47 @@
+use std::{
+    sync::{Arc, Barrier},
+    thread,
+    time::Instant,
+};
+
+use playground_rs::data_structure::treiberstack::TreiberStack;
+
+const OPS_PER_THREAD: usize = 200_000;
+
+/// Every thread alternates push and pop; returns millions of operations per second.
+fn throughput(stack: TreiberStack<usize>, threads: usize) -> f64 {
+    let stack = Arc::new(stack);
+    let barrier = Arc::new(Barrier::new(threads + 1));
+    let handles: Vec<_> = (0..threads)
+        .map(|t| {
+            let (stack, barrier) = (stack.clone(), barrier.clone());
+            thread::spawn(move || {
+                barrier.wait();
+                for i in 0..OPS_PER_THREAD / 2 {
+                    stack.push(t + i);
+                    stack.pop();
+                }
+            })
+        })
+        .collect();
+
+    barrier.wait();
+    let start = Instant::now();
+    for h in handles {
+        h.join().unwrap();
+    }
+    (threads * OPS_PER_THREAD) as f64 / start.elapsed().as_secs_f64() / 1e6
+}
+
+fn main() {
+    let max_threads = thread::available_parallelism().map_or(8, |n| n.get()) * 2;
+    println!("threads  backoff (Mops/s)  elimination (Mops/s)");
+
+    let mut threads = 1;
+    while threads <= max_threads {
+        let plain = throughput(TreiberStack::new(), threads);
+        let elim = throughput(TreiberStack::with_elimination(threads.div_ceil(2)), threads);
+        println!("{threads:>7}  {plain:>16.2}  {elim:>20.2}");
+        threads *= 2;
+    }
+}
@@ -1,6 +1,23 @@
 pub mod trie;
+pub mod suffix_automaton;
+pub mod suffix_array;
+pub mod priority_queue;
 pub mod binary_heap;
+pub mod dary_heap;
+pub mod indexed_heap;
+pub mod pairing_heap;
+pub mod mergeable_heap;
+pub mod leftist_heap;
+pub mod skew_heap;
+pub(crate) mod skip_list;
+pub mod skip_queue;
+pub mod skip_map;
 pub mod treiberstack;
 pub mod queue;
+pub mod array_queue;
+pub mod spsc_queue;
+pub mod work_stealing;
+pub mod thread_pool;
+pub mod concurrent_map;
 pub mod linked_list;
 pub mod tripod_list;
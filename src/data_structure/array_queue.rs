@@ -0,0 +1,254 @@
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crossbeam::utils::{Backoff, CachePadded};
+
+struct Slot<T> {
+    /// `2 * pos` when the slot is free for the push at `pos`, `2 * pos + 1` once that value is
+    /// written. Popping it makes the slot free for the push at `pos + capacity`. Doubling keeps
+    /// a written slot apart from a free one even when `capacity` is 1.
+    seq: AtomicUsize,
+    val: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// A bounded lock-free MPMC queue over a ring buffer, after Dmitry Vyukov's design. Unlike
+/// [`Queue`](super::queue::Queue) it never allocates after construction, and a full queue pushes
+/// back on producers instead of growing.
+pub struct ArrayQueue<T> {
+    buffer: Box<[Slot<T>]>,
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+}
+
+unsafe impl<T: Send> Send for ArrayQueue<T> {}
+unsafe impl<T: Send> Sync for ArrayQueue<T> {}
+
+/// The sequence number of a slot free for the push at `pos`.
+fn stamp(pos: usize) -> usize {
+    pos.wrapping_mul(2)
+}
+
+impl<T> ArrayQueue<T> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "ArrayQueue needs a positive capacity");
+        Self {
+            buffer: (0..capacity)
+                .map(|i| Slot {
+                    seq: AtomicUsize::new(stamp(i)),
+                    val: UnsafeCell::new(MaybeUninit::uninit()),
+                })
+                .collect(),
+            head: CachePadded::new(AtomicUsize::new(0)),
+            tail: CachePadded::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Number of elements. Only exact when no other thread is modifying the queue.
+    pub fn len(&self) -> usize {
+        loop {
+            let tail = self.tail.load(Ordering::SeqCst);
+            let head = self.head.load(Ordering::SeqCst);
+            if self.tail.load(Ordering::SeqCst) == tail {
+                return tail.wrapping_sub(head).min(self.capacity());
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// Push `val`, or hand it back if the queue is full.
+    pub fn try_push(&self, val: T) -> Result<(), T> {
+        let backoff = Backoff::new();
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.buffer[pos % self.capacity()];
+            let seq = slot.seq.load(Ordering::Acquire);
+
+            match (seq.wrapping_sub(stamp(pos)) as isize).cmp(&0) {
+                std::cmp::Ordering::Equal => {
+                    match self.tail.compare_exchange_weak(
+                        pos,
+                        pos.wrapping_add(1),
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    ) {
+                        Ok(_) => {
+                            unsafe { (*slot.val.get()).write(val) };
+                            slot.seq.store(stamp(pos) + 1, Ordering::Release);
+                            return Ok(());
+                        }
+                        Err(cur) => {
+                            pos = cur;
+                            backoff.spin();
+                        }
+                    }
+                }
+                // The slot still holds the value pushed one lap ago.
+                std::cmp::Ordering::Less => return Err(val),
+                std::cmp::Ordering::Greater => pos = self.tail.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    pub fn try_pop(&self) -> Option<T> {
+        let backoff = Backoff::new();
+        let mut pos = self.head.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.buffer[pos % self.capacity()];
+            let seq = slot.seq.load(Ordering::Acquire);
+
+            match (seq.wrapping_sub(stamp(pos) + 1) as isize).cmp(&0) {
+                std::cmp::Ordering::Equal => {
+                    match self.head.compare_exchange_weak(
+                        pos,
+                        pos.wrapping_add(1),
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    ) {
+                        Ok(_) => {
+                            let val = unsafe { (*slot.val.get()).assume_init_read() };
+                            let next = pos.wrapping_add(self.capacity());
+                            slot.seq.store(stamp(next), Ordering::Release);
+                            return Some(val);
+                        }
+                        Err(cur) => {
+                            pos = cur;
+                            backoff.spin();
+                        }
+                    }
+                }
+                // Nothing written at `pos` yet.
+                std::cmp::Ordering::Less => return None,
+                std::cmp::Ordering::Greater => pos = self.head.load(Ordering::Relaxed),
+            }
+        }
+    }
+}
+
+impl<T> Drop for ArrayQueue<T> {
+    fn drop(&mut self) {
+        let (head, tail) = (*self.head.get_mut(), *self.tail.get_mut());
+        let mut pos = head;
+        while pos != tail {
+            let slot = &mut self.buffer[pos % self.capacity()];
+            unsafe { slot.val.get_mut().assume_init_drop() };
+            pos = pos.wrapping_add(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        rc::Rc,
+        sync::{Arc, Barrier},
+        thread,
+    };
+
+    use super::ArrayQueue;
+
+    #[test]
+    fn push_until_full() {
+        let queue = ArrayQueue::new(3);
+        assert_eq!(queue.capacity(), 3);
+        for i in 0..3 {
+            assert_eq!(queue.try_push(i), Ok(()));
+        }
+        assert!(queue.is_full());
+        assert_eq!(queue.try_push(3), Err(3));
+
+        assert_eq!(queue.try_pop(), Some(0));
+        assert_eq!(queue.try_push(3), Ok(()));
+        let popped: Vec<_> = std::iter::from_fn(|| queue.try_pop()).collect();
+        assert_eq!(popped, [1, 2, 3]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn capacity_one() {
+        let queue = ArrayQueue::new(1);
+        assert_eq!(queue.try_push(1), Ok(()));
+        assert_eq!(queue.try_push(2), Err(2));
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.try_pop(), Some(1));
+        assert_eq!(queue.try_pop(), None);
+        for i in 0..5 {
+            assert_eq!(queue.try_push(i), Ok(()));
+            assert_eq!(queue.try_push(i), Err(i));
+            assert_eq!(queue.try_pop(), Some(i));
+        }
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn drops_remaining() {
+        let rc = Rc::new(());
+        let queue = ArrayQueue::new(4);
+        for _ in 0..6 {
+            queue.try_pop();
+            let _ = queue.try_push(rc.clone());
+            let _ = queue.try_push(rc.clone());
+        }
+        assert_eq!(Rc::strong_count(&rc), 5);
+        drop(queue);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn mpmc() {
+        const THREADS: usize = 4;
+        const PER_THREAD: usize = 20_000;
+        let queue = Arc::new(ArrayQueue::new(64));
+        let barrier = Arc::new(Barrier::new(2 * THREADS));
+
+        let producers: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let (queue, barrier) = (queue.clone(), barrier.clone());
+                thread::spawn(move || {
+                    barrier.wait();
+                    for i in 0..PER_THREAD {
+                        let mut val = t * PER_THREAD + i;
+                        while let Err(v) = queue.try_push(val) {
+                            val = v;
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let (queue, barrier) = (queue.clone(), barrier.clone());
+                thread::spawn(move || {
+                    barrier.wait();
+                    let mut popped = vec![];
+                    while popped.len() < PER_THREAD {
+                        match queue.try_pop() {
+                            Some(v) => popped.push(v),
+                            None => thread::yield_now(),
+                        }
+                    }
+                    popped
+                })
+            })
+            .collect();
+
+        producers.into_iter().for_each(|h| h.join().unwrap());
+        let mut all: Vec<_> = consumers.into_iter().flat_map(|h| h.join().unwrap()).collect();
+        all.sort();
+        assert_eq!(all, (0..THREADS * PER_THREAD).collect::<Vec<_>>());
+    }
+}
@@ -1,12 +1,91 @@
-use std::mem::swap;
+use std::{
+    cmp::Ordering,
+    ops::{Deref, DerefMut},
+};
 
-pub struct BinaryHeap<T> {
-    data: Vec<T>
+/// Decides which element comes out of a heap first: the greater one.
+pub trait Compare<T> {
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+}
+
+/// Greatest element first, by `Ord`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MaxComparator;
+
+impl<T: Ord> Compare<T> for MaxComparator {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+/// Least element first, by `Ord`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MinComparator;
+
+impl<T: Ord> Compare<T> for MinComparator {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        b.cmp(a)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FnComparator<F>(pub F);
+
+impl<T, F: Fn(&T, &T) -> Ordering> Compare<T> for FnComparator<F> {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        (self.0)(a, b)
+    }
+}
+
+/// Greatest key first.
+#[derive(Debug, Clone, Copy)]
+pub struct KeyComparator<F>(pub F);
+
+impl<T, K: Ord, F: Fn(&T) -> K> Compare<T> for KeyComparator<F> {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        (self.0)(a).cmp(&(self.0)(b))
+    }
+}
+
+pub struct BinaryHeap<T, C = MaxComparator> {
+    data: Vec<T>,
+    cmp: C,
 }
 
 impl<T> BinaryHeap<T> {
     pub fn new() -> Self {
-        Self { data: vec![] }
+        Self::with_comparator(MaxComparator)
+    }
+}
+
+impl<T: Ord> BinaryHeap<T> {
+    /// Heapify `data` in O(n).
+    pub fn from_vec(data: Vec<T>) -> Self {
+        Self::from_vec_cmp(data, MaxComparator)
+    }
+}
+
+impl<T: Ord> BinaryHeap<T, MinComparator> {
+    pub fn new_min() -> Self {
+        Self::with_comparator(MinComparator)
+    }
+}
+
+impl<T, F: Fn(&T, &T) -> Ordering> BinaryHeap<T, FnComparator<F>> {
+    pub fn new_by(f: F) -> Self {
+        Self::with_comparator(FnComparator(f))
+    }
+}
+
+impl<T, K: Ord, F: Fn(&T) -> K> BinaryHeap<T, KeyComparator<F>> {
+    pub fn new_by_key(f: F) -> Self {
+        Self::with_comparator(KeyComparator(f))
+    }
+}
+
+impl<T, C> BinaryHeap<T, C> {
+    pub fn with_comparator(cmp: C) -> Self {
+        Self { data: vec![], cmp }
     }
 
     pub fn len(&self) -> usize {
@@ -16,15 +95,42 @@ impl<T> BinaryHeap<T> {
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    pub fn top(&self) -> Option<&T> {
+        self.data.first()
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.top()
+    }
+
+    /// Iterate in arbitrary order.
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    /// Remove all elements, yielding them in arbitrary order.
+    pub fn drain(&mut self) -> std::vec::Drain<'_, T> {
+        self.data.drain(..)
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    /// The underlying vector, in heap order.
+    pub fn into_vec(self) -> Vec<T> {
+        self.data
+    }
 }
 
-impl<T> Default for BinaryHeap<T> {
+impl<T, C: Default> Default for BinaryHeap<T, C> {
     fn default() -> Self {
-        Self::new()
+        Self::with_comparator(C::default())
     }
 }
 
-impl<T: Ord> BinaryHeap<T> {
+impl<T, C: Compare<T>> BinaryHeap<T, C> {
     #[inline]
     fn parent(id: usize) -> usize {
         (id - 1) >> 1
@@ -40,48 +146,169 @@ impl<T: Ord> BinaryHeap<T> {
         (id << 1) + 2
     }
 
-    pub fn push(&mut self, elem: T) {
-        let mut pos = self.data.len();
-        self.data.push(elem);
+    #[inline]
+    fn greater(&self, a: usize, b: usize) -> bool {
+        self.cmp.compare(&self.data[a], &self.data[b]) == Ordering::Greater
+    }
 
-        while pos != 0 && self.data[pos] > self.data[Self::parent(pos)] {
+    fn sift_up(&mut self, mut pos: usize) {
+        while pos != 0 && self.greater(pos, Self::parent(pos)) {
             self.data.swap(pos, Self::parent(pos));
             pos = Self::parent(pos);
         }
     }
 
+    /// Sift down within `data[..end]`.
+    fn sift_down(&mut self, mut pos: usize, end: usize) {
+        while Self::left_son(pos) < end {
+            let (left, right) = (Self::left_son(pos), Self::right_son(pos));
+
+            let mut t = if self.greater(left, pos) { left } else { pos };
+            if right < end && self.greater(right, t) {
+                t = right;
+            }
+
+            if t == pos {
+                break;
+            }
+            self.data.swap(t, pos);
+            pos = t;
+        }
+    }
+
+    /// Heapify `data` in O(n).
+    pub fn from_vec_cmp(data: Vec<T>, cmp: C) -> Self {
+        let mut heap = Self { data, cmp };
+        heap.rebuild();
+        heap
+    }
+
+    fn rebuild(&mut self) {
+        let end = self.data.len();
+        for pos in (0..end / 2).rev() {
+            self.sift_down(pos, end);
+        }
+    }
+
+    pub fn push(&mut self, elem: T) {
+        self.data.push(elem);
+        self.sift_up(self.data.len() - 1);
+    }
+
     pub fn pop(&mut self) -> Option<T> {
         self.data.pop().map(|mut item| {
             if !self.data.is_empty() {
-                swap(&mut item, &mut self.data[0]);
-                let mut pos = 0;
-                let end = self.data.len();
-                while Self::left_son(pos) < end {
-                    let (left, right) = (Self::left_son(pos), Self::right_son(pos));
-
-                    let mut t = if item > self.data[left] { pos } else { left };
-                    if right < end {
-                        t = if self.data[t] > self.data[right] { t } else { right };
-                    }
-
-                    if t == pos { break; }
-                    self.data.swap(t, pos);
-                    pos = t;
-                }
+                std::mem::swap(&mut item, &mut self.data[0]);
+                self.sift_down(0, self.data.len());
             }
 
             item
         })
     }
 
-    pub fn top(&self) -> Option<&T> {
-        self.data.first()
+    /// Mutable access to the top element. The heap is repaired when the guard is dropped.
+    pub fn peek_mut(&mut self) -> Option<PeekMut<'_, T, C>> {
+        (!self.is_empty()).then_some(PeekMut { heap: self })
+    }
+
+    /// Move all elements of `other` into `self`, leaving `other` empty.
+    pub fn append(&mut self, other: &mut Self) {
+        if other.len() > self.len() {
+            std::mem::swap(&mut self.data, &mut other.data);
+        }
+
+        let (n, m) = (self.len(), other.len());
+        self.data.append(&mut other.data);
+        // Rebuilding is O(n + m), pushing one by one is O(m log(n + m)).
+        if m > n / 4 {
+            self.rebuild();
+        } else {
+            for pos in n..n + m {
+                self.sift_up(pos);
+            }
+        }
+    }
+
+    /// Elements in ascending order, i.e. the one that would be popped first is the last.
+    pub fn into_sorted_vec(mut self) -> Vec<T> {
+        let mut end = self.data.len();
+        while end > 1 {
+            end -= 1;
+            self.data.swap(0, end);
+            self.sift_down(0, end);
+        }
+        self.data
+    }
+}
+
+pub struct PeekMut<'a, T, C: Compare<T>> {
+    heap: &'a mut BinaryHeap<T, C>,
+}
+
+impl<T, C: Compare<T>> PeekMut<'_, T, C> {
+    /// Remove the top element. The last element takes its place and is sifted down on drop.
+    pub fn pop(this: Self) -> T {
+        this.heap.data.swap_remove(0)
+    }
+}
+
+impl<T, C: Compare<T>> Deref for PeekMut<'_, T, C> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.heap.data[0]
+    }
+}
+
+impl<T, C: Compare<T>> DerefMut for PeekMut<'_, T, C> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.heap.data[0]
+    }
+}
+
+impl<T, C: Compare<T>> Drop for PeekMut<'_, T, C> {
+    fn drop(&mut self) {
+        let end = self.heap.len();
+        self.heap.sift_down(0, end);
+    }
+}
+
+impl<T, C: Compare<T>> Extend<T> for BinaryHeap<T, C> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T: Ord> FromIterator<T> for BinaryHeap<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from_vec(iter.into_iter().collect())
+    }
+}
+
+impl<T, C> IntoIterator for BinaryHeap<T, C> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    /// Iterate in arbitrary order.
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.into_iter()
+    }
+}
+
+impl<'a, T, C> IntoIterator for &'a BinaryHeap<T, C> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::BinaryHeap;
+    use super::{BinaryHeap, PeekMut};
 
     #[test]
     fn push_and_pop() {
@@ -99,4 +326,60 @@ mod test {
 
         assert_eq!(heap.pop(), Some(100));
     }
+
+    #[test]
+    fn comparators() {
+        let mut min = BinaryHeap::new_min();
+        let mut by = BinaryHeap::new_by(|a: &i32, b: &i32| (a % 10).cmp(&(b % 10)));
+        let mut by_key = BinaryHeap::new_by_key(|s: &&str| s.len());
+        for i in [15, 3, 29, 41] {
+            min.push(i);
+            by.push(i);
+        }
+        by_key.extend(["aa", "a", "aaaa", "aaa"]);
+
+        assert_eq!(min.pop(), Some(3));
+        assert_eq!(by.pop(), Some(29));
+        assert_eq!(by_key.pop(), Some("aaaa"));
+    }
+
+    #[test]
+    fn heapify_and_sort() {
+        let heap = BinaryHeap::from_vec(vec![5, 1, 8, 3, 9, 2, 7]);
+        assert_eq!(heap.top(), Some(&9));
+        assert_eq!(heap.into_sorted_vec(), [1, 2, 3, 5, 7, 8, 9]);
+
+        let heap: BinaryHeap<_> = (0..50).rev().collect();
+        assert_eq!(heap.into_sorted_vec(), (0..50).collect::<Vec<_>>());
+
+        let mut min = BinaryHeap::new_min();
+        min.extend([4, 1, 3]);
+        assert_eq!(min.into_sorted_vec(), [4, 3, 1]);
+    }
+
+    #[test]
+    fn peek_mut() {
+        let mut heap = BinaryHeap::from_vec(vec![1, 5, 3]);
+        *heap.peek_mut().unwrap() = 0;
+        assert_eq!(heap.top(), Some(&3));
+
+        let top = heap.peek_mut().unwrap();
+        assert_eq!(PeekMut::pop(top), 3);
+        assert_eq!(heap.len(), 2);
+        assert_eq!(heap.top(), Some(&1));
+    }
+
+    #[test]
+    fn append() {
+        let mut a = BinaryHeap::from_vec(vec![1, 7]);
+        let mut b = BinaryHeap::from_vec(vec![3, 9, 4]);
+        a.append(&mut b);
+
+        assert!(b.is_empty());
+        assert_eq!(a.len(), 5);
+        let mut all: Vec<_> = a.iter().copied().collect();
+        all.sort();
+        assert_eq!(all, [1, 3, 4, 7, 9]);
+        assert_eq!(a.into_sorted_vec(), [1, 3, 4, 7, 9]);
+    }
 }
@@ -0,0 +1,623 @@
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crossbeam::epoch::{self, Atomic, Guard, Owned, Shared};
+
+/// Average number of entries per bucket before the table doubles.
+const LOAD_FACTOR: usize = 2;
+/// Segment `s > 0` holds buckets `2^(s-1)..2^s`, segment 0 holds bucket 0.
+const SEGMENTS: usize = 33;
+
+struct Node<K, V> {
+    /// Bit-reversed hash: odd for entries, even for bucket sentinels.
+    so_key: u64,
+    /// `None` for bucket sentinels.
+    key: Option<K>,
+    /// Tagged with 1 once the entry is removed, which is when the removal takes effect. The node
+    /// is then marked in `next` and unlinked like in a Harris–Michael list.
+    value: Atomic<V>,
+    next: Atomic<Node<K, V>>,
+}
+
+impl<K, V> Node<K, V> {
+    fn is_removed(&self, guard: &Guard) -> bool {
+        self.key.is_some() && self.value.load(Ordering::Acquire, guard).tag() == 1
+    }
+}
+
+impl<K, V> Drop for Node<K, V> {
+    fn drop(&mut self) {
+        unsafe {
+            let value = self.value.load(Ordering::Relaxed, epoch::unprotected());
+            if !value.is_null() {
+                drop(value.into_owned());
+            }
+        }
+    }
+}
+
+struct Segment<K, V> {
+    buckets: Box<[Atomic<Node<K, V>>]>,
+}
+
+/// Where a key is, or would be inserted.
+struct Position<'g, K, V> {
+    pred: &'g Atomic<Node<K, V>>,
+    curr: Shared<'g, Node<K, V>>,
+    found: Option<&'g Node<K, V>>,
+}
+
+/// A lock-free hash map on a split-ordered list (Shalev and Shavit). All entries live in one
+/// list sorted by bit-reversed hash, and buckets are shortcuts into it, so doubling the table
+/// never moves an entry: new buckets are initialized lazily by splitting their parent.
+///
+/// References returned by the map stay valid for the lifetime of the guard they were obtained
+/// under, even if the entry is removed or replaced meanwhile.
+pub struct ConcurrentHashMap<K, V, S = RandomState> {
+    segments: [Atomic<Segment<K, V>>; SEGMENTS],
+    /// Number of buckets in use, a power of two.
+    buckets: AtomicUsize,
+    len: AtomicUsize,
+    hasher: S,
+}
+
+fn segment_of(bucket: usize) -> (usize, usize) {
+    if bucket == 0 {
+        (0, 0)
+    } else {
+        let segment = usize::BITS as usize - bucket.leading_zeros() as usize;
+        (segment, bucket - (1 << (segment - 1)))
+    }
+}
+
+impl<K, V> ConcurrentHashMap<K, V> {
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<K, V> Default for ConcurrentHashMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, S> ConcurrentHashMap<K, V, S> {
+    pub fn with_hasher(hasher: S) -> Self {
+        let map = Self {
+            segments: std::array::from_fn(|_| Atomic::null()),
+            buckets: AtomicUsize::new(2),
+            len: AtomicUsize::new(0),
+            hasher,
+        };
+
+        let head = Owned::new(Node {
+            so_key: 0,
+            key: None,
+            value: Atomic::null(),
+            next: Atomic::null(),
+        });
+        let guard = unsafe { epoch::unprotected() };
+        map.slot(0, guard).store(head, Ordering::Relaxed);
+        map
+    }
+
+    /// Number of entries. Only exact when no other thread is modifying the map.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The bucket slot, allocating its segment if needed.
+    fn slot<'g>(&'g self, bucket: usize, guard: &'g Guard) -> &'g Atomic<Node<K, V>> {
+        let (index, offset) = segment_of(bucket);
+        let atomic = &self.segments[index];
+        let mut segment = atomic.load(Ordering::Acquire, guard);
+        if segment.is_null() {
+            let size = if index == 0 { 1 } else { 1 << (index - 1) };
+            let new = Owned::new(Segment {
+                buckets: (0..size).map(|_| Atomic::null()).collect(),
+            });
+            segment = match atomic.compare_exchange(
+                Shared::null(),
+                new,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+                guard,
+            ) {
+                Ok(new) => new,
+                Err(e) => e.current,
+            };
+        }
+        unsafe { &segment.deref().buckets[offset] }
+    }
+}
+
+// Unlinked nodes and replaced values are destroyed by crossbeam's collector, possibly on
+// another thread.
+impl<K: Eq + Send + 'static, V: Send + 'static, S> ConcurrentHashMap<K, V, S> {
+    /// Harris–Michael search from a bucket sentinel, unlinking removed nodes on the way.
+    fn find<'g, Q>(
+        &self,
+        start: &'g Node<K, V>,
+        so_key: u64,
+        key: Option<&Q>,
+        guard: &'g Guard,
+    ) -> Position<'g, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        'retry: loop {
+            let mut pred = &start.next;
+            let mut curr = pred.load(Ordering::Acquire, guard);
+
+            while let Some(c) = unsafe { curr.as_ref() } {
+                let succ = c.next.load(Ordering::Acquire, guard);
+                if succ.tag() == 1 {
+                    match pred.compare_exchange(
+                        curr,
+                        succ.with_tag(0),
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                        guard,
+                    ) {
+                        Ok(_) => {
+                            unsafe { guard.defer_destroy(curr) };
+                            curr = succ.with_tag(0);
+                            continue;
+                        }
+                        Err(_) => continue 'retry,
+                    }
+                }
+                if c.is_removed(guard) {
+                    // Finish the removal, then unlink it on the next round.
+                    c.next.fetch_or(1, Ordering::AcqRel, guard);
+                    continue;
+                }
+
+                if c.so_key > so_key {
+                    break;
+                }
+                if c.so_key == so_key && c.key.as_ref().map(Borrow::borrow) == key {
+                    return Position {
+                        pred,
+                        curr,
+                        found: Some(c),
+                    };
+                }
+
+                pred = &c.next;
+                curr = succ;
+            }
+
+            return Position {
+                pred,
+                curr,
+                found: None,
+            };
+        }
+    }
+
+    /// The sentinel of `bucket`, splitting it off its parent bucket if needed.
+    fn bucket<'g>(&'g self, bucket: usize, guard: &'g Guard) -> &'g Node<K, V> {
+        let slot = self.slot(bucket, guard);
+        if let Some(node) = unsafe { slot.load(Ordering::Acquire, guard).as_ref() } {
+            return node;
+        }
+
+        let parent = bucket & !(1 << (usize::BITS - 1 - bucket.leading_zeros()));
+        let parent = self.bucket(parent, guard);
+        let so_key = (bucket as u64).reverse_bits();
+        let mut sentinel = Owned::new(Node {
+            so_key,
+            key: None,
+            value: Atomic::null(),
+            next: Atomic::null(),
+        });
+
+        let node = loop {
+            let pos = self.find::<K>(parent, so_key, None, guard);
+            if let Some(found) = pos.found {
+                break Shared::from(found as *const _);
+            }
+
+            sentinel.next.store(pos.curr, Ordering::Relaxed);
+            match pos.pred.compare_exchange(
+                pos.curr,
+                sentinel,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+                guard,
+            ) {
+                Ok(node) => break node,
+                Err(e) => sentinel = e.new,
+            }
+        };
+
+        // Whoever loses stores the same sentinel.
+        let _ = slot.compare_exchange(
+            Shared::null(),
+            node,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+            guard,
+        );
+        unsafe { node.deref() }
+    }
+
+    /// Count an entry about to be published, so a removal never brings `len` below zero.
+    /// Returns the new length.
+    fn count_insert(&self) -> usize {
+        self.len.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Double the table if `len` entries overload it.
+    fn grow(&self, len: usize) {
+        let buckets = self.buckets.load(Ordering::Relaxed);
+        if len > buckets * LOAD_FACTOR && buckets < 1 << (SEGMENTS - 1) {
+            let _ = self.buckets.compare_exchange(
+                buckets,
+                buckets * 2,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            );
+        }
+    }
+
+    /// Claim `node`'s value, which removes the entry, then unlink it.
+    fn remove_node<'g, Q>(
+        &self,
+        node: &'g Node<K, V>,
+        value: Shared<'g, V>,
+        start: &'g Node<K, V>,
+        key: &Q,
+        guard: &'g Guard,
+    ) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        if node
+            .value
+            .compare_exchange(
+                value,
+                Shared::null().with_tag(1),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+                guard,
+            )
+            .is_err()
+        {
+            return false;
+        }
+
+        self.len.fetch_sub(1, Ordering::Relaxed);
+        unsafe { guard.defer_destroy(value) };
+        node.next.fetch_or(1, Ordering::AcqRel, guard);
+        self.find(start, node.so_key, Some(key), guard);
+        true
+    }
+}
+
+impl<K, V, S> ConcurrentHashMap<K, V, S>
+where
+    K: Hash + Eq + Send + 'static,
+    V: Send + 'static,
+    S: BuildHasher,
+{
+    /// The bucket sentinel and split-order key of an entry.
+    fn locate<'g, Q>(&'g self, key: &Q, guard: &'g Guard) -> (&'g Node<K, V>, u64)
+    where
+        Q: Hash + ?Sized,
+    {
+        let hash = self.hasher.hash_one(key);
+        let buckets = self.buckets.load(Ordering::Relaxed);
+        let start = self.bucket(hash as usize & (buckets - 1), guard);
+        (start, (hash | 1 << 63).reverse_bits())
+    }
+
+    pub fn get<'g, Q>(&'g self, key: &Q, guard: &'g Guard) -> Option<&'g V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (start, so_key) = self.locate(key, guard);
+        let node = self.find(start, so_key, Some(key), guard).found?;
+        unsafe { node.value.load(Ordering::Acquire, guard).as_ref() }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key, &epoch::pin()).is_some()
+    }
+
+    fn new_node(so_key: u64, key: K) -> Owned<Node<K, V>> {
+        Owned::new(Node {
+            so_key,
+            key: Some(key),
+            value: Atomic::null(),
+            next: Atomic::null(),
+        })
+    }
+
+    /// Insert or replace, returning the previous value.
+    pub fn insert<'g>(&'g self, key: K, value: V, guard: &'g Guard) -> Option<&'g V> {
+        let (start, so_key) = self.locate(&key, guard);
+        let mut new = Self::new_node(so_key, key);
+        new.value = Atomic::new(value);
+
+        loop {
+            let pos = self.find(start, so_key, new.key.as_ref(), guard);
+            if let Some(found) = pos.found {
+                let old = found.value.load(Ordering::Acquire, guard);
+                let value = new.value.load(Ordering::Relaxed, guard);
+                if old.tag() == 0
+                    && found
+                        .value
+                        .compare_exchange(old, value, Ordering::AcqRel, Ordering::Acquire, guard)
+                        .is_ok()
+                {
+                    // The value moved over; drop only the key with the unused node.
+                    new.value = Atomic::null();
+                    unsafe { guard.defer_destroy(old) };
+                    return unsafe { old.as_ref() };
+                }
+                continue;
+            }
+
+            new.next.store(pos.curr, Ordering::Relaxed);
+            let len = self.count_insert();
+            match pos.pred.compare_exchange(
+                pos.curr,
+                new,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+                guard,
+            ) {
+                Ok(_) => {
+                    self.grow(len);
+                    return None;
+                }
+                Err(e) => {
+                    self.len.fetch_sub(1, Ordering::Relaxed);
+                    new = e.new;
+                }
+            }
+        }
+    }
+
+    /// Atomically update the entry of `key`: `f` gets the current value and returns the new one,
+    /// or `None` to remove the entry. Returns the new value.
+    ///
+    /// `f` is called again whenever another thread changes the entry first.
+    pub fn compute<'g, F>(&'g self, key: K, mut f: F, guard: &'g Guard) -> Option<&'g V>
+    where
+        F: FnMut(Option<&V>) -> Option<V>,
+    {
+        let (start, so_key) = self.locate(&key, guard);
+        let mut new = Self::new_node(so_key, key);
+
+        loop {
+            let pos = self.find(start, so_key, new.key.as_ref(), guard);
+            if let Some(found) = pos.found {
+                let old = found.value.load(Ordering::Acquire, guard);
+                if old.tag() == 1 {
+                    continue;
+                }
+
+                match f(unsafe { old.as_ref() }) {
+                    Some(value) => {
+                        if let Ok(value) = found.value.compare_exchange(
+                            old,
+                            Owned::new(value),
+                            Ordering::AcqRel,
+                            Ordering::Acquire,
+                            guard,
+                        ) {
+                            unsafe { guard.defer_destroy(old) };
+                            return unsafe { value.as_ref() };
+                        }
+                    }
+                    None => {
+                        let key = new.key.as_ref().unwrap();
+                        if self.remove_node(found, old, start, key, guard) {
+                            return None;
+                        }
+                    }
+                }
+                continue;
+            }
+
+            let value = Owned::new(f(None)?).into_shared(guard);
+            let prev = new.value.swap(value, Ordering::Relaxed, guard);
+            if !prev.is_null() {
+                // From a lost race in an earlier round; never published.
+                drop(unsafe { prev.into_owned() });
+            }
+
+            new.next.store(pos.curr, Ordering::Relaxed);
+            let len = self.count_insert();
+            match pos.pred.compare_exchange(
+                pos.curr,
+                new,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+                guard,
+            ) {
+                Ok(_) => {
+                    self.grow(len);
+                    return unsafe { value.as_ref() };
+                }
+                Err(e) => {
+                    self.len.fetch_sub(1, Ordering::Relaxed);
+                    new = e.new;
+                }
+            }
+        }
+    }
+
+    /// Remove the entry of `key`, returning its value.
+    pub fn remove<'g, Q>(&'g self, key: &Q, guard: &'g Guard) -> Option<&'g V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (start, so_key) = self.locate(key, guard);
+        loop {
+            let node = self.find(start, so_key, Some(key), guard).found?;
+            let value = node.value.load(Ordering::Acquire, guard);
+            if value.tag() == 0 && self.remove_node(node, value, start, key, guard) {
+                return unsafe { value.as_ref() };
+            }
+        }
+    }
+}
+
+impl<K, V, S> Drop for ConcurrentHashMap<K, V, S> {
+    fn drop(&mut self) {
+        unsafe {
+            let guard = epoch::unprotected();
+            // Every node hangs off bucket 0's sentinel.
+            let mut node = self.slot(0, guard).load(Ordering::Relaxed, guard);
+            while !node.is_null() {
+                let owned = node.into_owned();
+                node = owned.next.load(Ordering::Relaxed, guard).with_tag(0);
+            }
+
+            for segment in &self.segments {
+                let segment = segment.load(Ordering::Relaxed, guard);
+                if !segment.is_null() {
+                    drop(segment.into_owned());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    use crossbeam::epoch;
+
+    use super::ConcurrentHashMap;
+
+    #[test]
+    fn insert_get_remove() {
+        let map = ConcurrentHashMap::new();
+        let guard = epoch::pin();
+        for i in 0..1000 {
+            assert_eq!(map.insert(i, i * 10, &guard), None);
+        }
+        assert_eq!(map.len(), 1000);
+        assert_eq!(map.insert(7, 0, &guard), Some(&70));
+
+        let old = map.get(&8, &guard).unwrap();
+        assert_eq!(map.remove(&8, &guard), Some(&80));
+        // Still valid under the same guard.
+        assert_eq!(*old, 80);
+        assert_eq!(map.get(&8, &guard), None);
+        assert_eq!(map.remove(&8, &guard), None);
+        assert!(!map.contains_key(&8));
+
+        for i in (0..1000).filter(|&i| i != 8) {
+            let expected = if i == 7 { 0 } else { i * 10 };
+            assert_eq!(map.get(&i, &guard), Some(&expected));
+        }
+        assert_eq!(map.len(), 999);
+    }
+
+    #[test]
+    fn compute() {
+        let map = ConcurrentHashMap::new();
+        let guard = epoch::pin();
+        assert_eq!(map.compute("a", |v| v.map(|v| v + 1), &guard), None);
+        assert_eq!(map.compute("a", |v| Some(v.map_or(1, |v| v + 1)), &guard), Some(&1));
+        assert_eq!(map.compute("a", |v| Some(v.map_or(1, |v| v + 1)), &guard), Some(&2));
+        assert_eq!(map.compute("a", |_| None, &guard), None);
+        assert!(map.is_empty());
+    }
+
+    struct Counted(Arc<AtomicUsize>);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn drops_everything() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        {
+            let map = ConcurrentHashMap::new();
+            let guard = epoch::pin();
+            for i in 0..100 {
+                map.insert(i, Counted(drops.clone()), &guard);
+            }
+            map.insert(0, Counted(drops.clone()), &guard);
+            map.remove(&1, &guard);
+        }
+        assert!(drops.load(Ordering::Relaxed) >= 99);
+
+        // Replaced and removed values wait for the collector, which other tests' pins can hold
+        // up for a while.
+        for _ in 0..1000 {
+            if drops.load(Ordering::Relaxed) == 101 {
+                break;
+            }
+            epoch::pin().flush();
+            thread::yield_now();
+        }
+        assert_eq!(drops.load(Ordering::Relaxed), 101);
+    }
+
+    #[test]
+    fn concurrent_counts_with_resizing() {
+        const THREADS: usize = 4;
+        const KEYS: usize = 2000;
+        let map = Arc::new(ConcurrentHashMap::new());
+
+        let handles: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let map = map.clone();
+                thread::spawn(move || {
+                    let guard = epoch::pin();
+                    for i in 0..KEYS {
+                        map.compute(i, |v| Some(v.map_or(1, |v| v + 1)), &guard);
+                        // Churn on keys private to this thread.
+                        let private = KEYS * (t + 1) + i;
+                        map.insert(private, 0, &guard);
+                        if i % 2 == 0 {
+                            assert_eq!(map.remove(&private, &guard), Some(&0));
+                        }
+                    }
+                })
+            })
+            .collect();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+
+        let guard = epoch::pin();
+        for i in 0..KEYS {
+            assert_eq!(map.get(&i, &guard), Some(&THREADS));
+        }
+        assert_eq!(map.len(), KEYS + THREADS * KEYS / 2);
+    }
+}
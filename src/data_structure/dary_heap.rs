@@ -0,0 +1,181 @@
+use std::cmp::Ordering;
+
+use super::{
+    binary_heap::{Compare, MaxComparator, MinComparator},
+    priority_queue::PriorityQueue,
+};
+
+/// An implicit heap where every node has `D` children. A wider node makes the tree shallower and
+/// keeps siblings in one cache line, at the cost of more comparisons per level when popping.
+pub struct DaryHeap<T, const D: usize, C = MaxComparator> {
+    data: Vec<T>,
+    cmp: C,
+}
+
+impl<T, const D: usize> DaryHeap<T, D> {
+    pub fn new() -> Self {
+        Self::with_comparator(MaxComparator)
+    }
+}
+
+impl<T: Ord, const D: usize> DaryHeap<T, D> {
+    pub fn from_vec(data: Vec<T>) -> Self {
+        Self::from_vec_cmp(data, MaxComparator)
+    }
+}
+
+impl<T: Ord, const D: usize> DaryHeap<T, D, MinComparator> {
+    pub fn new_min() -> Self {
+        Self::with_comparator(MinComparator)
+    }
+}
+
+impl<T, const D: usize, C> DaryHeap<T, D, C> {
+    pub fn with_comparator(cmp: C) -> Self {
+        assert!(D >= 2, "A d-ary heap needs at least two children per node");
+        Self { data: vec![], cmp }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.data.first()
+    }
+
+    /// Iterate in arbitrary order.
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        self.data
+    }
+}
+
+impl<T, const D: usize, C: Default> Default for DaryHeap<T, D, C> {
+    fn default() -> Self {
+        Self::with_comparator(C::default())
+    }
+}
+
+impl<T, const D: usize, C: Compare<T>> DaryHeap<T, D, C> {
+    #[inline]
+    fn greater(&self, a: usize, b: usize) -> bool {
+        self.cmp.compare(&self.data[a], &self.data[b]) == Ordering::Greater
+    }
+
+    fn sift_up(&mut self, mut pos: usize) {
+        while pos != 0 && self.greater(pos, (pos - 1) / D) {
+            self.data.swap(pos, (pos - 1) / D);
+            pos = (pos - 1) / D;
+        }
+    }
+
+    fn sift_down(&mut self, mut pos: usize) {
+        let end = self.data.len();
+        loop {
+            let first = pos * D + 1;
+            let mut t = pos;
+            for child in first..(first + D).min(end) {
+                if self.greater(child, t) {
+                    t = child;
+                }
+            }
+
+            if t == pos {
+                break;
+            }
+            self.data.swap(t, pos);
+            pos = t;
+        }
+    }
+
+    /// Heapify `data` in O(n).
+    pub fn from_vec_cmp(data: Vec<T>, cmp: C) -> Self {
+        let mut heap = Self::with_comparator(cmp);
+        heap.data = data;
+        let end = heap.data.len();
+        for pos in (0..end.div_ceil(D)).rev() {
+            heap.sift_down(pos);
+        }
+        heap
+    }
+
+    pub fn push(&mut self, elem: T) {
+        self.data.push(elem);
+        self.sift_up(self.data.len() - 1);
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let res = self.data.swap_remove(0);
+        self.sift_down(0);
+        Some(res)
+    }
+}
+
+impl<T, const D: usize, C: Compare<T>> PriorityQueue<T> for DaryHeap<T, D, C> {
+    fn push(&mut self, elem: T) {
+        DaryHeap::push(self, elem)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        DaryHeap::pop(self)
+    }
+
+    fn peek(&self) -> Option<&T> {
+        DaryHeap::peek(self)
+    }
+
+    fn len(&self) -> usize {
+        DaryHeap::len(self)
+    }
+}
+
+impl<T, const D: usize, C: Compare<T>> Extend<T> for DaryHeap<T, D, C> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DaryHeap;
+
+    fn drain<const D: usize>(mut heap: DaryHeap<i32, D>) -> Vec<i32> {
+        std::iter::from_fn(|| heap.pop()).collect()
+    }
+
+    #[test]
+    fn pops_in_order() {
+        let data: Vec<i32> = (0..100).map(|i| (i * 37) % 101).collect();
+        let mut sorted = data.clone();
+        sorted.sort_by(|a, b| b.cmp(a));
+
+        let mut heap = DaryHeap::<_, 4>::new();
+        heap.extend(data.iter().copied());
+        assert_eq!(drain(heap), sorted);
+        assert_eq!(drain(DaryHeap::<_, 8>::from_vec(data.clone())), sorted);
+        assert_eq!(drain(DaryHeap::<_, 3>::from_vec(data)), sorted);
+    }
+
+    #[test]
+    fn min_heap() {
+        let mut heap = DaryHeap::<_, 4, _>::new_min();
+        heap.extend([3, 1, 2]);
+        assert_eq!(heap.peek(), Some(&1));
+        assert_eq!(heap.pop(), Some(1));
+        assert_eq!(heap.len(), 2);
+    }
+}
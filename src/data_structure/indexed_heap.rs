@@ -0,0 +1,267 @@
+use std::{
+    cmp::Ordering,
+    sync::atomic::{self, AtomicU64},
+};
+
+use super::binary_heap::{Compare, MaxComparator, MinComparator};
+
+/// Refers to an element pushed into an addressable heap. Handles of removed elements stay
+/// invalid even if their slot is reused, and handles of another heap are never valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    pub(crate) heap: u64,
+    pub(crate) index: usize,
+    pub(crate) generation: u32,
+}
+
+/// A fresh id for an addressable heap to tag its handles with.
+pub(crate) fn next_heap_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    NEXT.fetch_add(1, atomic::Ordering::Relaxed)
+}
+
+struct Slot<T, P> {
+    entry: Option<(T, P)>,
+    /// Position in `heap`.
+    pos: usize,
+    generation: u32,
+}
+
+/// A binary heap whose elements can be located by [`Handle`] to change their priority or remove
+/// them in O(log n).
+pub struct IndexedHeap<T, P, C = MaxComparator> {
+    id: u64,
+    /// Slot indices in heap order.
+    heap: Vec<usize>,
+    slots: Vec<Slot<T, P>>,
+    free: Vec<usize>,
+    cmp: C,
+}
+
+impl<T, P> IndexedHeap<T, P> {
+    pub fn new() -> Self {
+        Self::with_comparator(MaxComparator)
+    }
+}
+
+impl<T, P: Ord> IndexedHeap<T, P, MinComparator> {
+    pub fn new_min() -> Self {
+        Self::with_comparator(MinComparator)
+    }
+}
+
+impl<T, P, C> IndexedHeap<T, P, C> {
+    pub fn with_comparator(cmp: C) -> Self {
+        Self {
+            id: next_heap_id(),
+            heap: vec![],
+            slots: vec![],
+            free: vec![],
+            cmp,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    fn slot(&self, handle: Handle) -> Option<&Slot<T, P>> {
+        self.slots
+            .get(handle.index)
+            .filter(|s| handle.heap == self.id && s.generation == handle.generation)
+            .filter(|s| s.entry.is_some())
+    }
+
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.slot(handle).is_some()
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<(&T, &P)> {
+        let (item, priority) = self.slot(handle)?.entry.as_ref()?;
+        Some((item, priority))
+    }
+
+    pub fn peek(&self) -> Option<(&T, &P)> {
+        let (item, priority) = self.slots[*self.heap.first()?].entry.as_ref()?;
+        Some((item, priority))
+    }
+
+    /// Handle of the top element.
+    pub fn peek_handle(&self) -> Option<Handle> {
+        let index = *self.heap.first()?;
+        Some(Handle {
+            heap: self.id,
+            index,
+            generation: self.slots[index].generation,
+        })
+    }
+}
+
+impl<T, P, C: Default> Default for IndexedHeap<T, P, C> {
+    fn default() -> Self {
+        Self::with_comparator(C::default())
+    }
+}
+
+impl<T, P, C: Compare<P>> IndexedHeap<T, P, C> {
+    fn priority_at(&self, pos: usize) -> &P {
+        &self.slots[self.heap[pos]].entry.as_ref().unwrap().1
+    }
+
+    fn greater(&self, a: usize, b: usize) -> bool {
+        self.cmp.compare(self.priority_at(a), self.priority_at(b)) == Ordering::Greater
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        self.slots[self.heap[a]].pos = a;
+        self.slots[self.heap[b]].pos = b;
+    }
+
+    fn sift_up(&mut self, mut pos: usize) -> usize {
+        while pos != 0 && self.greater(pos, (pos - 1) / 2) {
+            self.swap(pos, (pos - 1) / 2);
+            pos = (pos - 1) / 2;
+        }
+        pos
+    }
+
+    fn sift_down(&mut self, mut pos: usize) {
+        let end = self.heap.len();
+        while 2 * pos + 1 < end {
+            let (left, right) = (2 * pos + 1, 2 * pos + 2);
+            let mut t = if self.greater(left, pos) { left } else { pos };
+            if right < end && self.greater(right, t) {
+                t = right;
+            }
+
+            if t == pos {
+                break;
+            }
+            self.swap(t, pos);
+            pos = t;
+        }
+    }
+
+    pub fn push(&mut self, item: T, priority: P) -> Handle {
+        let pos = self.heap.len();
+        let index = match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                slot.entry = Some((item, priority));
+                slot.pos = pos;
+                index
+            }
+            None => {
+                self.slots.push(Slot {
+                    entry: Some((item, priority)),
+                    pos,
+                    generation: 0,
+                });
+                self.slots.len() - 1
+            }
+        };
+
+        self.heap.push(index);
+        self.sift_up(pos);
+        Handle {
+            heap: self.id,
+            index,
+            generation: self.slots[index].generation,
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<(T, P)> {
+        let index = *self.heap.first()?;
+        self.remove_at(index)
+    }
+
+    /// Set the priority of `handle`, returning the old one, or `None` if `handle` is invalid.
+    pub fn change_priority(&mut self, handle: Handle, priority: P) -> Option<P> {
+        self.slot(handle)?;
+        let slot = &mut self.slots[handle.index];
+        let old = std::mem::replace(&mut slot.entry.as_mut().unwrap().1, priority);
+        let pos = slot.pos;
+
+        if self.sift_up(pos) == pos {
+            self.sift_down(pos);
+        }
+        Some(old)
+    }
+
+    pub fn remove(&mut self, handle: Handle) -> Option<(T, P)> {
+        self.slot(handle)?;
+        self.remove_at(handle.index)
+    }
+
+    fn remove_at(&mut self, index: usize) -> Option<(T, P)> {
+        let pos = self.slots[index].pos;
+        let last = self.heap.len() - 1;
+        self.swap(pos, last);
+        self.heap.pop();
+
+        if pos < self.heap.len() && self.sift_up(pos) == pos {
+            self.sift_down(pos);
+        }
+
+        let slot = &mut self.slots[index];
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(index);
+        slot.entry.take()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::IndexedHeap;
+
+    #[test]
+    fn push_and_pop_in_order() {
+        let mut heap = IndexedHeap::new_min();
+        for (i, p) in [5, 1, 4, 2, 3].into_iter().enumerate() {
+            heap.push(i, p);
+        }
+
+        let popped: Vec<_> = std::iter::from_fn(|| heap.pop()).map(|(_, p)| p).collect();
+        assert_eq!(popped, [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn change_priority_and_remove() {
+        let mut heap = IndexedHeap::new_min();
+        let a = heap.push('a', 10);
+        let b = heap.push('b', 20);
+        let c = heap.push('c', 30);
+
+        assert_eq!(heap.change_priority(c, 5), Some(30));
+        assert_eq!(heap.peek(), Some((&'c', &5)));
+        assert_eq!(heap.change_priority(c, 25), Some(5));
+        assert_eq!(heap.peek(), Some((&'a', &10)));
+
+        assert_eq!(heap.remove(a), Some(('a', 10)));
+        assert!(!heap.contains(a));
+        assert_eq!(heap.remove(a), None);
+        assert_eq!(heap.change_priority(a, 0), None);
+
+        // The slot of `a` is reused, but its handle stays invalid.
+        let d = heap.push('d', 1);
+        assert!(!heap.contains(a));
+        assert_eq!(heap.get(d), Some((&'d', &1)));
+        assert_eq!(heap.pop(), Some(('d', 1)));
+        assert_eq!(heap.pop(), Some(('b', 20)));
+        assert_eq!(heap.pop(), Some(('c', 25)));
+        assert!(heap.is_empty());
+        assert!(!heap.contains(b));
+
+        // Another heap's handle, even to a slot in use with the same generation.
+        let mut other = IndexedHeap::new_min();
+        let e = other.push('e', 0);
+        heap.push('f', 0);
+        assert!(!heap.contains(e));
+        assert_eq!(heap.remove(e), None);
+    }
+}
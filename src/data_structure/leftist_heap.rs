@@ -0,0 +1,60 @@
+use super::{
+    binary_heap::MaxComparator,
+    mergeable_heap::{Link, MergeRule, MergeableHeap, Node},
+};
+
+/// Keeps every tree left-heavy: the right spine of a node, its rank, is never longer than the
+/// left one's.
+pub struct Leftist;
+
+impl MergeRule for Leftist {
+    type Rank = usize;
+    const LEAF: usize = 1;
+
+    fn restore<T>(node: &mut Node<T, Self>) {
+        let rank = |link: &Link<T, Self>| link.as_ref().map_or(0, |n| n.rank);
+        if rank(&node.left) < rank(&node.right) {
+            std::mem::swap(&mut node.left, &mut node.right);
+        }
+        node.rank = rank(&node.right) + 1;
+    }
+}
+
+/// A heap-ordered binary tree kept left-heavy, so that two heaps merge along their right spines
+/// in O(log n).
+pub type LeftistHeap<T, C = MaxComparator> = MergeableHeap<T, Leftist, C>;
+
+#[cfg(test)]
+mod test {
+    use super::LeftistHeap;
+
+    #[test]
+    fn push_and_pop_in_order() {
+        let mut heap = LeftistHeap::new();
+        heap.extend([5, 1, 4, 2, 3, 9, 0]);
+        assert_eq!(heap.peek(), Some(&9));
+
+        let popped: Vec<_> = std::iter::from_fn(|| heap.pop()).collect();
+        assert_eq!(popped, [9, 5, 4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn merge() {
+        let mut a = LeftistHeap::new_min();
+        let mut b = LeftistHeap::new_min();
+        a.extend((0..100).step_by(2));
+        b.extend((1..100).step_by(2));
+        a.merge(b);
+
+        assert_eq!(a.len(), 100);
+        let popped: Vec<_> = std::iter::from_fn(|| a.pop()).collect();
+        assert_eq!(popped, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn drop_long_spine() {
+        let mut heap = LeftistHeap::new();
+        heap.extend(0..200_000);
+        heap.pop();
+    }
+}
@@ -0,0 +1,166 @@
+use std::{cmp::Ordering, marker::PhantomData};
+
+use super::{
+    binary_heap::{Compare, MaxComparator, MinComparator},
+    priority_queue::PriorityQueue,
+};
+
+pub(super) type Link<T, R> = Option<Box<Node<T, R>>>;
+
+pub struct Node<T, R: MergeRule> {
+    pub(super) elem: T,
+    pub(super) rank: R::Rank,
+    pub(super) left: Link<T, R>,
+    pub(super) right: Link<T, R>,
+}
+
+/// How a [`MergeableHeap`] keeps its right paths short, which is what merging walks down.
+pub trait MergeRule: Sized {
+    /// Kept in every node for the rule's own use.
+    type Rank;
+    /// The rank of a node without children.
+    const LEAF: Self::Rank;
+
+    /// Restore the rule at `node`, whose right subtree just had another heap merged into it.
+    fn restore<T>(node: &mut Node<T, Self>);
+}
+
+/// A heap-ordered binary tree merged along right paths, kept short by the rule `R`. See
+/// [`LeftistHeap`](super::leftist_heap::LeftistHeap) and [`SkewHeap`](super::skew_heap::SkewHeap).
+pub struct MergeableHeap<T, R: MergeRule, C = MaxComparator> {
+    root: Link<T, R>,
+    len: usize,
+    cmp: C,
+    _rule: PhantomData<R>,
+}
+
+impl<T, R: MergeRule> MergeableHeap<T, R> {
+    pub fn new() -> Self {
+        Self::with_comparator(MaxComparator)
+    }
+}
+
+impl<T: Ord, R: MergeRule> MergeableHeap<T, R, MinComparator> {
+    pub fn new_min() -> Self {
+        Self::with_comparator(MinComparator)
+    }
+}
+
+impl<T, R: MergeRule, C> MergeableHeap<T, R, C> {
+    pub fn with_comparator(cmp: C) -> Self {
+        Self {
+            root: None,
+            len: 0,
+            cmp,
+            _rule: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.root.as_ref().map(|n| &n.elem)
+    }
+}
+
+impl<T, R: MergeRule, C: Default> Default for MergeableHeap<T, R, C> {
+    fn default() -> Self {
+        Self::with_comparator(C::default())
+    }
+}
+
+impl<T, R: MergeRule, C: Compare<T>> MergeableHeap<T, R, C> {
+    /// Walk down the right paths of both trees, then restore the rule bottom-up. A skew heap's
+    /// right path may be as long as the heap, so this is a loop rather than recursion.
+    fn merge_links(cmp: &C, mut a: Link<T, R>, mut b: Link<T, R>) -> Link<T, R> {
+        let mut path = vec![];
+        let mut merged = loop {
+            match (a, b) {
+                (Some(mut x), Some(mut y)) => {
+                    if cmp.compare(&x.elem, &y.elem) == Ordering::Less {
+                        std::mem::swap(&mut x, &mut y);
+                    }
+                    a = x.right.take();
+                    b = Some(y);
+                    path.push(x);
+                }
+                (rest, None) | (None, rest) => break rest,
+            }
+        };
+
+        while let Some(mut node) = path.pop() {
+            node.right = merged;
+            R::restore(&mut node);
+            merged = Some(node);
+        }
+        merged
+    }
+
+    pub fn push(&mut self, elem: T) {
+        let node = Box::new(Node {
+            elem,
+            rank: R::LEAF,
+            left: None,
+            right: None,
+        });
+        self.root = Self::merge_links(&self.cmp, self.root.take(), Some(node));
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let mut root = self.root.take()?;
+        self.root = Self::merge_links(&self.cmp, root.left.take(), root.right.take());
+        self.len -= 1;
+        Some(root.elem)
+    }
+
+    /// Move all elements of `other` into `self`, in O(log n) or amortized O(log n) depending on
+    /// the rule.
+    pub fn merge(&mut self, mut other: Self) {
+        self.root = Self::merge_links(&self.cmp, self.root.take(), other.root.take());
+        self.len += std::mem::take(&mut other.len);
+    }
+}
+
+impl<T, R: MergeRule, C> Drop for MergeableHeap<T, R, C> {
+    /// Either spine may be as long as the heap, so free nodes without recursion.
+    fn drop(&mut self) {
+        let mut stack: Vec<_> = self.root.take().into_iter().collect();
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.left.take());
+            stack.extend(node.right.take());
+        }
+    }
+}
+
+impl<T, R: MergeRule, C: Compare<T>> PriorityQueue<T> for MergeableHeap<T, R, C> {
+    fn push(&mut self, elem: T) {
+        MergeableHeap::push(self, elem)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        MergeableHeap::pop(self)
+    }
+
+    fn peek(&self) -> Option<&T> {
+        MergeableHeap::peek(self)
+    }
+
+    fn len(&self) -> usize {
+        MergeableHeap::len(self)
+    }
+}
+
+impl<T, R: MergeRule, C: Compare<T>> Extend<T> for MergeableHeap<T, R, C> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
@@ -0,0 +1,502 @@
+use std::{cmp::Ordering, ptr::NonNull};
+
+use super::{
+    binary_heap::{Compare, MaxComparator, MinComparator},
+    indexed_heap::next_heap_id,
+};
+
+type Link<T, P> = Option<NonNull<Node<T, P>>>;
+
+struct Node<T, P> {
+    entry: Option<(T, P)>,
+    child: Link<T, P>,
+    sibling: Link<T, P>,
+    /// The parent for a leftmost child, the left sibling otherwise.
+    prev: Link<T, P>,
+    generation: u32,
+    /// Next in the list of every node the heap owns.
+    next_owned: Link<T, P>,
+    /// Next in the free list, while `entry` is `None`.
+    next_free: Link<T, P>,
+}
+
+/// An intrusive singly linked list of nodes through one of their `next_*` links, with a tail so
+/// that two lists append in O(1).
+struct NodeList<T, P> {
+    head: Link<T, P>,
+    tail: Link<T, P>,
+    next: fn(&mut Node<T, P>) -> &mut Link<T, P>,
+}
+
+impl<T, P> NodeList<T, P> {
+    fn new(next: fn(&mut Node<T, P>) -> &mut Link<T, P>) -> Self {
+        Self {
+            head: None,
+            tail: None,
+            next,
+        }
+    }
+
+    unsafe fn push(&mut self, mut node: NonNull<Node<T, P>>) {
+        *(self.next)(node.as_mut()) = self.head;
+        self.head = Some(node);
+        self.tail.get_or_insert(node);
+    }
+
+    unsafe fn pop(&mut self) -> Link<T, P> {
+        let mut node = self.head?;
+        self.head = (self.next)(node.as_mut()).take();
+        if self.head.is_none() {
+            self.tail = None;
+        }
+        Some(node)
+    }
+
+    unsafe fn append(&mut self, other: &mut Self) {
+        let Some(head) = other.head.take() else {
+            return;
+        };
+        match self.tail {
+            Some(mut tail) => *(self.next)(tail.as_mut()) = Some(head),
+            None => self.head = Some(head),
+        }
+        self.tail = other.tail.take();
+    }
+}
+
+/// Refers to an element pushed into a [`PairingHeap`]. Handles of removed elements stay invalid
+/// even if their node is reused, and handles of another heap are never valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    heap: u64,
+    /// Only dereferenced by the heap whose id is `heap`, which owns the node.
+    node: NonNull<()>,
+    generation: u32,
+}
+
+// A handle is only an address and counters; the heap owning the node is what dereferences it.
+unsafe impl Send for Handle {}
+unsafe impl Sync for Handle {}
+
+/// A pairing heap with O(1) `push` and `meld`, amortized O(1) priority improvement and amortized
+/// O(log n) `pop`.
+///
+/// Every node is its own allocation, kept until the heap is dropped and reused after its element
+/// is removed, so that handles can be checked without a lookup table and melding only links
+/// lists together.
+pub struct PairingHeap<T, P, C = MaxComparator> {
+    id: u64,
+    root: Link<T, P>,
+    owned: NodeList<T, P>,
+    free: NodeList<T, P>,
+    len: usize,
+    cmp: C,
+}
+
+unsafe impl<T: Send, P: Send, C: Send> Send for PairingHeap<T, P, C> {}
+unsafe impl<T: Sync, P: Sync, C: Sync> Sync for PairingHeap<T, P, C> {}
+
+/// Translates handles of a heap melded into another one.
+#[derive(Debug, Clone, Copy)]
+pub struct MeldedHandles {
+    from: u64,
+    to: u64,
+}
+
+impl MeldedHandles {
+    /// The handle in the melded heap, or `None` if `handle` wasn't one of the heap melded in.
+    pub fn apply(&self, handle: Handle) -> Option<Handle> {
+        (handle.heap == self.from).then_some(Handle {
+            heap: self.to,
+            ..handle
+        })
+    }
+}
+
+impl<T, P> PairingHeap<T, P> {
+    pub fn new() -> Self {
+        Self::with_comparator(MaxComparator)
+    }
+}
+
+impl<T, P: Ord> PairingHeap<T, P, MinComparator> {
+    pub fn new_min() -> Self {
+        Self::with_comparator(MinComparator)
+    }
+}
+
+impl<T, P, C> PairingHeap<T, P, C> {
+    pub fn with_comparator(cmp: C) -> Self {
+        Self {
+            id: next_heap_id(),
+            root: None,
+            owned: NodeList::new(|n| &mut n.next_owned),
+            free: NodeList::new(|n| &mut n.next_free),
+            len: 0,
+            cmp,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn at(&self, node: NonNull<Node<T, P>>) -> &Node<T, P> {
+        // Nodes live as long as the heap owning them.
+        unsafe { node.as_ref() }
+    }
+
+    fn at_mut(&mut self, mut node: NonNull<Node<T, P>>) -> &mut Node<T, P> {
+        unsafe { node.as_mut() }
+    }
+
+    /// The node of a live element of this heap.
+    fn node(&self, handle: Handle) -> Link<T, P> {
+        if handle.heap != self.id {
+            return None;
+        }
+        let node = handle.node.cast();
+        let n = self.at(node);
+        (n.generation == handle.generation && n.entry.is_some()).then_some(node)
+    }
+
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.node(handle).is_some()
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<(&T, &P)> {
+        let (item, priority) = self.at(self.node(handle)?).entry.as_ref()?;
+        Some((item, priority))
+    }
+
+    pub fn peek(&self) -> Option<(&T, &P)> {
+        let (item, priority) = self.at(self.root?).entry.as_ref()?;
+        Some((item, priority))
+    }
+}
+
+impl<T, P, C: Default> Default for PairingHeap<T, P, C> {
+    fn default() -> Self {
+        Self::with_comparator(C::default())
+    }
+}
+
+impl<T, P, C> Drop for PairingHeap<T, P, C> {
+    fn drop(&mut self) {
+        while let Some(node) = unsafe { self.owned.pop() } {
+            drop(unsafe { Box::from_raw(node.as_ptr()) });
+        }
+    }
+}
+
+impl<T, P, C: Compare<P>> PairingHeap<T, P, C> {
+    fn priority(&self, node: NonNull<Node<T, P>>) -> &P {
+        &self.at(node).entry.as_ref().unwrap().1
+    }
+
+    /// Make the lesser of two roots the leftmost child of the other.
+    fn link(&mut self, a: Link<T, P>, b: Link<T, P>) -> Link<T, P> {
+        let (a, b) = match (a, b) {
+            (Some(a), Some(b)) => (a, b),
+            (a, None) => return a,
+            (None, b) => return b,
+        };
+
+        let (parent, child) =
+            if self.cmp.compare(self.priority(a), self.priority(b)) == Ordering::Less {
+                (b, a)
+            } else {
+                (a, b)
+            };
+
+        let first = self.at(parent).child;
+        if let Some(first) = first {
+            self.at_mut(first).prev = Some(child);
+        }
+        let c = self.at_mut(child);
+        c.sibling = first;
+        c.prev = Some(parent);
+        self.at_mut(parent).child = Some(child);
+        Some(parent)
+    }
+
+    /// Detach a non-root node, together with its subtree, from its parent.
+    fn cut(&mut self, node: NonNull<Node<T, P>>) {
+        let n = self.at_mut(node);
+        let prev = n.prev.take().unwrap();
+        let sibling = n.sibling.take();
+
+        let p = self.at_mut(prev);
+        if p.child == Some(node) {
+            p.child = sibling;
+        } else {
+            p.sibling = sibling;
+        }
+        if let Some(sibling) = sibling {
+            self.at_mut(sibling).prev = Some(prev);
+        }
+    }
+
+    /// Two-pass pairing of the children of `node`, which are detached.
+    fn merge_children(&mut self, node: NonNull<Node<T, P>>) -> Link<T, P> {
+        let mut children = vec![];
+        let mut cur = self.at_mut(node).child.take();
+        while let Some(c) = cur {
+            let c_node = self.at_mut(c);
+            cur = c_node.sibling.take();
+            c_node.prev = None;
+            children.push(c);
+        }
+
+        let paired: Vec<_> = children
+            .chunks(2)
+            .map(|pair| self.link(Some(pair[0]), pair.get(1).copied()))
+            .collect();
+        paired
+            .into_iter()
+            .rev()
+            .fold(None, |acc, p| self.link(acc, p))
+    }
+
+    pub fn push(&mut self, item: T, priority: P) -> Handle {
+        let node = match unsafe { self.free.pop() } {
+            Some(node) => {
+                self.at_mut(node).entry = Some((item, priority));
+                node
+            }
+            None => {
+                let node = NonNull::from(Box::leak(Box::new(Node {
+                    entry: Some((item, priority)),
+                    child: None,
+                    sibling: None,
+                    prev: None,
+                    generation: 0,
+                    next_owned: None,
+                    next_free: None,
+                })));
+                unsafe { self.owned.push(node) };
+                node
+            }
+        };
+
+        self.root = self.link(self.root, Some(node));
+        self.len += 1;
+        Handle {
+            heap: self.id,
+            node: node.cast(),
+            generation: self.at(node).generation,
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<(T, P)> {
+        let root = self.root?;
+        self.root = self.merge_children(root);
+        Some(self.release(root))
+    }
+
+    fn release(&mut self, node: NonNull<Node<T, P>>) -> (T, P) {
+        let n = self.at_mut(node);
+        n.generation = n.generation.wrapping_add(1);
+        let entry = n.entry.take().unwrap();
+        unsafe { self.free.push(node) };
+        self.len -= 1;
+        entry
+    }
+
+    /// Set the priority of `handle`, returning the old one, or `None` if `handle` is invalid.
+    ///
+    /// Moving an element towards the top is amortized O(1), moving it down is O(log n).
+    pub fn change_priority(&mut self, handle: Handle, priority: P) -> Option<P> {
+        let node = self.node(handle)?;
+        let entry = self.at_mut(node).entry.as_mut().unwrap();
+        let old = std::mem::replace(&mut entry.1, priority);
+        let improved = self.cmp.compare(self.priority(node), &old) != Ordering::Less;
+
+        if self.root == Some(node) {
+            if !improved {
+                let rest = self.merge_children(node);
+                self.root = self.link(rest, Some(node));
+            }
+        } else {
+            self.cut(node);
+            let rest = if improved {
+                None
+            } else {
+                self.merge_children(node)
+            };
+            let root = self.link(self.root, rest);
+            self.root = self.link(root, Some(node));
+        }
+
+        Some(old)
+    }
+
+    pub fn remove(&mut self, handle: Handle) -> Option<(T, P)> {
+        let node = self.node(handle)?;
+
+        if self.root == Some(node) {
+            return self.pop();
+        }
+
+        self.cut(node);
+        let rest = self.merge_children(node);
+        self.root = self.link(self.root, rest);
+        Some(self.release(node))
+    }
+
+    /// Move all elements of `other` into `self` in O(1). Handles of `other` are invalid in
+    /// `self` until translated by the returned [`MeldedHandles`].
+    pub fn meld(&mut self, mut other: Self) -> MeldedHandles {
+        unsafe {
+            self.owned.append(&mut other.owned);
+            self.free.append(&mut other.free);
+        }
+        self.len += std::mem::take(&mut other.len);
+        self.root = self.link(self.root, other.root.take());
+
+        MeldedHandles {
+            from: other.id,
+            to: self.id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use super::PairingHeap;
+    use crate::data_structure::indexed_heap::IndexedHeap;
+
+    #[test]
+    fn push_and_pop_in_order() {
+        let mut heap = PairingHeap::new();
+        for (i, p) in [5, 1, 4, 2, 3, 9, 0].into_iter().enumerate() {
+            heap.push(i, p);
+        }
+
+        let popped: Vec<_> = std::iter::from_fn(|| heap.pop()).map(|(_, p)| p).collect();
+        assert_eq!(popped, [9, 5, 4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn change_priority_and_remove() {
+        let mut heap = PairingHeap::new_min();
+        let handles: Vec<_> = (0..10).map(|i| heap.push(i, i * 10)).collect();
+        heap.pop();
+
+        assert_eq!(heap.change_priority(handles[7], 5), Some(70));
+        assert_eq!(heap.peek(), Some((&7, &5)));
+        assert_eq!(heap.change_priority(handles[7], 95), Some(5));
+        assert_eq!(heap.remove(handles[3]), Some((3, 30)));
+        assert_eq!(heap.remove(handles[0]), None);
+
+        let popped: Vec<_> = std::iter::from_fn(|| heap.pop()).map(|(i, _)| i).collect();
+        assert_eq!(popped, [1, 2, 4, 5, 6, 8, 9, 7]);
+    }
+
+    #[test]
+    fn meld() {
+        let mut a = PairingHeap::new_min();
+        let mut b = PairingHeap::new_min();
+        let ha = a.push('a', 3);
+        let hb = b.push('b', 2);
+        b.push('c', 1);
+
+        let melded = a.meld(b);
+        assert!(!a.contains(hb));
+        let hb = melded.apply(hb).unwrap();
+        assert_eq!(melded.apply(ha), None);
+        assert_eq!(a.len(), 3);
+        assert_eq!(a.get(hb), Some((&'b', &2)));
+        a.change_priority(hb, 0);
+
+        let popped: Vec<_> = std::iter::from_fn(|| a.pop()).map(|(c, _)| c).collect();
+        assert_eq!(popped, ['b', 'c', 'a']);
+    }
+
+    #[test]
+    fn drops_melded_elements() {
+        let rc = Rc::new(());
+        let mut a = PairingHeap::new();
+        let mut b = PairingHeap::new();
+        for i in 0..10 {
+            a.push(rc.clone(), i);
+            b.push(rc.clone(), i);
+        }
+        a.pop();
+        b.pop();
+        a.meld(b);
+        // Reuses a node freed in either heap.
+        a.push(rc.clone(), 0);
+        assert_eq!(a.len(), 19);
+        assert_eq!(Rc::strong_count(&rc), 20);
+        drop(a);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    /// Shortest paths on a small graph with both addressable heaps.
+    #[test]
+    fn dijkstra() {
+        let edges = [
+            (0, 1, 7),
+            (0, 2, 9),
+            (0, 5, 14),
+            (1, 2, 10),
+            (1, 3, 15),
+            (2, 3, 11),
+            (2, 5, 2),
+            (3, 4, 6),
+            (4, 5, 9),
+        ];
+        let mut adj = vec![vec![]; 6];
+        for (a, b, w) in edges {
+            adj[a].push((b, w));
+            adj[b].push((a, w));
+        }
+
+        let mut dist = [u32::MAX; 6];
+        let mut heap = IndexedHeap::new_min();
+        let mut handles = [None; 6];
+        handles[0] = Some(heap.push(0, 0));
+        dist[0] = 0;
+        while let Some((u, d)) = heap.pop() {
+            for &(v, w) in &adj[u] {
+                if d + w < dist[v] {
+                    dist[v] = d + w;
+                    match handles[v] {
+                        Some(h) if heap.contains(h) => {
+                            heap.change_priority(h, d + w);
+                        }
+                        _ => handles[v] = Some(heap.push(v, d + w)),
+                    }
+                }
+            }
+        }
+        assert_eq!(dist, [0, 7, 9, 20, 20, 11]);
+
+        let mut dist2 = [u32::MAX; 6];
+        let mut heap = PairingHeap::new_min();
+        let mut handles = [None; 6];
+        handles[0] = Some(heap.push(0, 0));
+        dist2[0] = 0;
+        while let Some((u, d)) = heap.pop() {
+            for &(v, w) in &adj[u] {
+                if d + w < dist2[v] {
+                    dist2[v] = d + w;
+                    match handles[v] {
+                        Some(h) if heap.contains(h) => {
+                            heap.change_priority(h, d + w);
+                        }
+                        _ => handles[v] = Some(heap.push(v, d + w)),
+                    }
+                }
+            }
+        }
+        assert_eq!(dist2, dist);
+    }
+}
@@ -0,0 +1,31 @@
+use super::binary_heap::{BinaryHeap, Compare};
+
+/// Common surface of the heaps in this crate, so call sites can swap implementations.
+pub trait PriorityQueue<T> {
+    fn push(&mut self, elem: T);
+    fn pop(&mut self) -> Option<T>;
+    fn peek(&self) -> Option<&T>;
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T, C: Compare<T>> PriorityQueue<T> for BinaryHeap<T, C> {
+    fn push(&mut self, elem: T) {
+        BinaryHeap::push(self, elem)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        BinaryHeap::pop(self)
+    }
+
+    fn peek(&self) -> Option<&T> {
+        BinaryHeap::peek(self)
+    }
+
+    fn len(&self) -> usize {
+        BinaryHeap::len(self)
+    }
+}
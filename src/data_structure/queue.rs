@@ -1,27 +1,29 @@
-use std::{
-    mem::{ManuallyDrop, MaybeUninit},
-    sync::atomic::{AtomicBool, Ordering},
-};
+use std::mem::MaybeUninit;
+
+use crossbeam::epoch::{self, Guard, Owned, Shared};
 
-use crossbeam::epoch::{self, Atomic, Guard, Owned, Shared};
+use crate::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Atomic, Backoff,
+};
 
 struct Removable<T> {
-    val: ManuallyDrop<T>,
+    /// Only initialized while `present`.
+    val: MaybeUninit<T>,
     present: AtomicBool,
 }
 
 impl<T> Removable<T> {
     pub fn new(val: T) -> Self {
         Self {
-            val: ManuallyDrop::new(val),
+            val: MaybeUninit::new(val),
             present: AtomicBool::new(true),
         }
     }
 
     pub fn empty() -> Self {
         Self {
-            #[allow(clippy::uninit_assumed_init)]
-            val: ManuallyDrop::new(unsafe { MaybeUninit::uninit().assume_init() }),
+            val: MaybeUninit::uninit(),
             present: AtomicBool::new(false),
         }
     }
@@ -29,14 +31,18 @@ impl<T> Removable<T> {
     pub fn take(&self) -> Option<T> {
         if self
             .present
-            .compare_exchange(true, false, Ordering::AcqRel, Ordering::Relaxed)
+            .compare_exchange(true, false, Ordering::AcqRel, Ordering::Acquire)
             .is_ok()
         {
-            Some(unsafe { (&self.val as *const ManuallyDrop<T> as *const T).read() })
+            Some(unsafe { self.val.as_ptr().read() })
         } else {
             None
         }
     }
+
+    pub fn is_present(&self) -> bool {
+        self.present.load(Ordering::Acquire)
+    }
 }
 
 struct Node<T> {
@@ -47,8 +53,14 @@ struct Node<T> {
 pub struct Queue<T> {
     front: Atomic<Node<T>>,
     back: Atomic<Node<T>>,
+    len: AtomicUsize,
 }
 
+// Values are moved in by `push` and out by `pop`, so only `T: Send` is needed to share the
+// queue. Borrowing them in place is what takes `T: Sync`, see `iter`.
+unsafe impl<T: Send> Send for Queue<T> {}
+unsafe impl<T: Send> Sync for Queue<T> {}
+
 impl<T> Queue<T> {
     pub fn new() -> Self {
         let sentinel = Atomic::new(Node {
@@ -59,6 +71,7 @@ impl<T> Queue<T> {
         Self {
             front: sentinel.clone(),
             back: sentinel,
+            len: AtomicUsize::new(0),
         }
     }
 
@@ -69,6 +82,7 @@ impl<T> Queue<T> {
             next: Atomic::null(),
         });
 
+        self.len.fetch_add(1, Ordering::Relaxed);
         let p = owned.into_shared(&guard);
         let prev_back = self.back.swap(p, Ordering::AcqRel, &guard);
         unsafe {
@@ -76,21 +90,33 @@ impl<T> Queue<T> {
         }
     }
 
+    /// Pop the front value, or `None` if the queue is empty.
+    ///
+    /// Not lock-free: `push` moves `back` on before it links its node, and a pop that finds the
+    /// front unlinked while `back` has moved on spins until that push is done. Reporting empty
+    /// instead would be wrong once a push queued behind the unfinished one has returned.
     pub fn pop(&self) -> Option<T> {
         let guard = epoch::pin();
+        let backoff = Backoff::new();
 
         loop {
-            let front = self.front.load(Ordering::Relaxed, &guard);
+            let front = self.front.load(Ordering::Acquire, &guard);
             let head = unsafe { front.as_ref().unwrap() };
 
             match head.val.take() {
                 Some(r) => {
+                    self.len.fetch_sub(1, Ordering::Relaxed);
                     unsafe { self.try_discard_first_and_move_on(front, &guard) };
                     break Some(r);
                 }
                 None => {
                     if unsafe { !self.try_discard_first_and_move_on(front, &guard) } {
-                        break None;
+                        // `back` moves on before the new node gets linked. Wait for such a push
+                        // rather than report empty while pushes behind it may have returned.
+                        if self.back.load(Ordering::Acquire, &guard) == front {
+                            break None;
+                        }
+                        backoff.snooze();
                     }
                 }
             }
@@ -102,8 +128,8 @@ impl<T> Queue<T> {
         prev_first: Shared<Node<T>>,
         guard: &Guard,
     ) -> bool {
-        let next = prev_first.as_ref().unwrap().next.clone();
-        let next = next.load(Ordering::Acquire, guard);
+        // Not through a clone of the link: cloning loads it `Relaxed`, missing the node's push.
+        let next = prev_first.as_ref().unwrap().next.load(Ordering::Acquire, guard);
 
         if next.is_null() {
             return false;
@@ -111,7 +137,7 @@ impl<T> Queue<T> {
 
         if self
             .front
-            .compare_exchange(prev_first, next, Ordering::Relaxed, Ordering::Relaxed, guard)
+            .compare_exchange(prev_first, next, Ordering::Release, Ordering::Relaxed, guard)
             .is_ok()
         {
             guard.defer_destroy(prev_first);
@@ -119,6 +145,76 @@ impl<T> Queue<T> {
 
         true
     }
+
+    /// Number of elements. Only exact when no other thread is modifying the queue.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let guard = epoch::pin();
+        let mut node = self.front.load(Ordering::Acquire, &guard);
+        while let Some(n) = unsafe { node.as_ref() } {
+            if n.val.is_present() {
+                return false;
+            }
+            node = n.next.load(Ordering::Acquire, &guard);
+        }
+        true
+    }
+
+    /// Pop until the queue is empty. Values pushed meanwhile are yielded as well.
+    pub fn drain(&self) -> Drain<'_, T> {
+        Drain { queue: self }
+    }
+}
+
+/// Only for `T: Copy`, as explained for the
+/// [stack](super::treiberstack#borrowing-elements), and `T: Sync` since the queue is shared.
+impl<T: Copy + Sync> Queue<T> {
+    pub fn peek<'g>(&self, guard: &'g Guard) -> Option<&'g T> {
+        self.iter(guard).next()
+    }
+
+    /// Iterate from the front without popping. References stay valid while `guard` is pinned.
+    pub fn iter<'g>(&self, guard: &'g Guard) -> Iter<'g, T> {
+        Iter {
+            curr: self.front.load(Ordering::Acquire, guard),
+            guard,
+        }
+    }
+}
+
+pub struct Iter<'g, T> {
+    curr: Shared<'g, Node<T>>,
+    guard: &'g Guard,
+}
+
+impl<'g, T: Copy + Sync> Iterator for Iter<'g, T> {
+    type Item = &'g T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Nodes behind one that was the front after pinning can't have been reclaimed yet.
+        while let Some(node) = unsafe { self.curr.as_ref() } {
+            self.curr = node.next.load(Ordering::Acquire, self.guard);
+            if node.val.is_present() {
+                return Some(unsafe { node.val.val.assume_init_ref() });
+            }
+        }
+        None
+    }
+}
+
+pub struct Drain<'a, T> {
+    queue: &'a Queue<T>,
+}
+
+impl<T> Iterator for Drain<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.queue.pop()
+    }
 }
 
 impl<T> Iterator for Queue<T> {
@@ -133,6 +229,7 @@ impl<T> Iterator for Queue<T> {
             let next = head_ptr.next.load(Ordering::Relaxed, &guard);
             match head_ptr.val.take() {
                 Some(val) => {
+                    self.len.fetch_sub(1, Ordering::Relaxed);
                     if !next.is_null() {
                         unsafe {
                             guard.defer_destroy(head);
@@ -182,7 +279,12 @@ impl<T> Default for Queue<T> {
 
 #[cfg(test)]
 mod test {
+    use std::thread;
+
+    use crossbeam::epoch;
+
     use super::Queue;
+    use crate::utils::linearizability::{check, CollectionOp::*, QueueModel, Recorder};
 
     #[test]
     fn push_and_pop() {
@@ -216,4 +318,87 @@ mod test {
             assert_eq!(v, i);
         }
     }
+
+    #[test]
+    fn iter_and_peek() {
+        let queue = Queue::new();
+        assert!(queue.is_empty());
+        for i in 0..4 {
+            queue.push(i);
+        }
+        queue.pop();
+
+        let guard = epoch::pin();
+        assert_eq!(queue.peek(&guard), Some(&1));
+        assert_eq!(queue.iter(&guard).copied().collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(queue.len(), 3);
+        assert!(!queue.is_empty());
+
+        assert_eq!(queue.drain().collect::<Vec<_>>(), [1, 2, 3]);
+        assert!(queue.is_empty());
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn histories_are_linearizable() {
+        for _ in 0..200 {
+            let queue = Queue::new();
+            let recorder = Recorder::new();
+            thread::scope(|s| {
+                for t in 0..3 {
+                    let (queue, recorder) = (&queue, &recorder);
+                    s.spawn(move || {
+                        for i in 0..3 {
+                            let val = t * 3 + i;
+                            recorder.record(Push(val), || {
+                                queue.push(val);
+                                None
+                            });
+                            recorder.record(Pop, || queue.pop());
+                        }
+                    });
+                }
+            });
+
+            let history = recorder.take();
+            assert!(check(QueueModel::default(), &history).is_some(), "{history:?}");
+        }
+    }
+
+    /// Run with `RUSTFLAGS="--cfg loom" cargo test --release --lib loom`.
+    #[cfg(loom)]
+    mod loom_model {
+        use loom::{sync::Arc, thread};
+
+        use super::super::Queue;
+        use crate::utils::linearizability::{check, CollectionOp::*, QueueModel, Recorder};
+
+        #[test]
+        fn push_pop() {
+            crate::sync::model(|| {
+                let queue = Arc::new(Queue::new());
+                let recorder = Arc::new(Recorder::new());
+
+                let handles: Vec<_> = (0..2)
+                    .map(|t| {
+                        let (queue, recorder) = (queue.clone(), recorder.clone());
+                        thread::spawn(move || {
+                            recorder.record(Push(t), || {
+                                queue.push(t);
+                                None
+                            });
+                            recorder.record(Pop, || queue.pop());
+                        })
+                    })
+                    .collect();
+                recorder.record(Pop, || queue.pop());
+                for handle in handles {
+                    handle.join().unwrap();
+                }
+
+                let history = recorder.take();
+                assert!(check(QueueModel::default(), &history).is_some(), "{history:?}");
+            });
+        }
+    }
 }
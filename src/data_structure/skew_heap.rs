@@ -0,0 +1,56 @@
+use super::{
+    binary_heap::MaxComparator,
+    mergeable_heap::{MergeRule, MergeableHeap, Node},
+};
+
+/// Swaps the children of every node on the merge path.
+pub struct Skew;
+
+impl MergeRule for Skew {
+    type Rank = ();
+    const LEAF: () = ();
+
+    fn restore<T>(node: &mut Node<T, Self>) {
+        std::mem::swap(&mut node.left, &mut node.right);
+    }
+}
+
+/// The self-adjusting variant of [`LeftistHeap`](super::leftist_heap::LeftistHeap): children are
+/// swapped on every merge instead of keeping ranks, which makes `merge` amortized O(log n).
+pub type SkewHeap<T, C = MaxComparator> = MergeableHeap<T, Skew, C>;
+
+#[cfg(test)]
+mod test {
+    use super::SkewHeap;
+
+    #[test]
+    fn push_and_pop_in_order() {
+        let mut heap = SkewHeap::new();
+        heap.extend([5, 1, 4, 2, 3, 9, 0]);
+        assert_eq!(heap.peek(), Some(&9));
+
+        let popped: Vec<_> = std::iter::from_fn(|| heap.pop()).collect();
+        assert_eq!(popped, [9, 5, 4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn merge() {
+        let mut a = SkewHeap::new_min();
+        let mut b = SkewHeap::new_min();
+        a.extend((0..100).step_by(3));
+        b.extend((0..100).filter(|i| i % 3 != 0));
+        a.merge(b);
+
+        assert_eq!(a.len(), 100);
+        let popped: Vec<_> = std::iter::from_fn(|| a.pop()).collect();
+        assert_eq!(popped, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sorted_input() {
+        let mut heap = SkewHeap::new_min();
+        heap.extend(0..200_000);
+        assert_eq!(heap.pop(), Some(0));
+        assert_eq!(heap.pop(), Some(1));
+    }
+}
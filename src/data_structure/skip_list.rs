@@ -0,0 +1,523 @@
+use std::{
+    borrow::Borrow,
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ops::{Bound, RangeBounds},
+    ptr,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use crossbeam::{
+    epoch::{self, Atomic, Guard, Owned, Shared},
+    utils::Backoff,
+};
+
+const MAX_HEIGHT: usize = 24;
+
+type Tower<K, V> = [Atomic<Node<K, V>>];
+
+struct Node<K, V> {
+    /// Dropped with the node, unless `take_first` moved them out.
+    key: ManuallyDrop<K>,
+    value: ManuallyDrop<V>,
+    /// Successor at every level. A tag of 1 marks the node as removed at that level; the mark at
+    /// level 0 decides which remover wins.
+    tower: Box<Tower<K, V>>,
+    /// The inserter and the winning remover each finish an unlinking pass before the node is
+    /// reclaimed, since the inserter may still be linking upper levels when the node is removed.
+    pending: AtomicUsize,
+    /// Searches comparing against the key right now, plus the inserter until it's done with it.
+    /// `take_first` waits for them to leave.
+    readers: AtomicUsize,
+    taken: AtomicBool,
+}
+
+impl<K, V> Node<K, V> {
+    /// Start reading the entry. Fails once the node's removal is decided, after which
+    /// `take_first` may be moving the entry out.
+    fn enter(&self, guard: &Guard) -> bool {
+        self.readers.fetch_add(1, Ordering::SeqCst);
+        // Pairs with `take_first` counting readers after the mark: either it sees us, or we
+        // see the mark.
+        if self.tower[0].load(Ordering::SeqCst, guard).tag() == 1 {
+            self.exit();
+            return false;
+        }
+        true
+    }
+
+    fn exit(&self) {
+        self.readers.fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl<K, V> Drop for Node<K, V> {
+    fn drop(&mut self) {
+        if !*self.taken.get_mut() {
+            unsafe {
+                ManuallyDrop::drop(&mut self.key);
+                ManuallyDrop::drop(&mut self.value);
+            }
+        }
+    }
+}
+
+/// Predecessors and successors of a key at every level.
+struct Position<'g, K, V> {
+    preds: [&'g Tower<K, V>; MAX_HEIGHT],
+    succs: [Shared<'g, Node<K, V>>; MAX_HEIGHT],
+}
+
+/// A lock-free skip list in the style of Fraser and Herlihy–Shavit: nodes are removed by marking
+/// their towers top-down and unlinked by whichever search passes them next.
+///
+/// Keys and values are only dropped when a node is reclaimed, so references handed out stay
+/// valid for the lifetime of the guard they were obtained under.
+pub(crate) struct SkipList<K, V> {
+    head: Box<Tower<K, V>>,
+    len: AtomicUsize,
+}
+
+fn random_height() -> usize {
+    (rand::random::<u32>().trailing_ones() as usize + 1).min(MAX_HEIGHT)
+}
+
+impl<K, V> SkipList<K, V> {
+    pub fn new() -> Self {
+        Self {
+            head: (0..MAX_HEIGHT).map(|_| Atomic::null()).collect(),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// Number of entries. Only exact when no other thread is modifying the list.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// The first node whose removal hasn't been decided yet.
+    fn first_node<'g>(&'g self, guard: &'g Guard) -> Option<Shared<'g, Node<K, V>>> {
+        let mut curr = self.head[0].load(Ordering::SeqCst, guard);
+        while let Some(c) = unsafe { curr.as_ref() } {
+            let succ = c.tower[0].load(Ordering::SeqCst, guard);
+            if succ.tag() == 0 {
+                return Some(curr);
+            }
+            curr = succ.with_tag(0);
+        }
+        None
+    }
+
+    pub fn first<'g>(&'g self, guard: &'g Guard) -> Option<(&'g K, &'g V)> {
+        let node = unsafe { self.first_node(guard)?.deref() };
+        Some((&node.key, &node.value))
+    }
+
+    /// The last node whose removal hasn't been decided yet.
+    fn last_node<'g>(&'g self, guard: &'g Guard) -> Option<Shared<'g, Node<K, V>>> {
+        'retry: loop {
+            let mut pred: &'g Tower<K, V> = &self.head;
+            let mut pred_node = None;
+            for level in (1..MAX_HEIGHT).rev() {
+                let mut curr = pred[level].load(Ordering::SeqCst, guard).with_tag(0);
+                while let Some(c) = unsafe { curr.as_ref() } {
+                    // Only descend from live nodes, or the live ones before them would be missed.
+                    if c.tower[0].load(Ordering::SeqCst, guard).tag() == 0 {
+                        (pred, pred_node) = (&c.tower, Some(curr));
+                    }
+                    curr = c.tower[level].load(Ordering::SeqCst, guard).with_tag(0);
+                }
+            }
+
+            let mut last = None;
+            let mut curr = pred[0].load(Ordering::SeqCst, guard).with_tag(0);
+            while let Some(c) = unsafe { curr.as_ref() } {
+                let succ = c.tower[0].load(Ordering::SeqCst, guard);
+                if succ.tag() == 0 {
+                    last = Some(curr);
+                }
+                curr = succ.with_tag(0);
+            }
+
+            match (last, pred_node) {
+                (Some(node), _) => return Some(node),
+                (None, None) => return None,
+                (None, Some(p)) => {
+                    if unsafe { p.deref() }.tower[0].load(Ordering::SeqCst, guard).tag() == 0 {
+                        return Some(p);
+                    }
+                    // Our starting point got removed meanwhile.
+                    continue 'retry;
+                }
+            }
+        }
+    }
+
+    pub fn last<'g>(&'g self, guard: &'g Guard) -> Option<(&'g K, &'g V)> {
+        let node = unsafe { self.last_node(guard)?.deref() };
+        Some((&node.key, &node.value))
+    }
+}
+
+/// Whether a key comes before everything `bound` admits as a lower bound.
+fn below<Q: Ord + ?Sized>(key: &Q, bound: Bound<&Q>) -> bool {
+    match bound {
+        Bound::Included(b) => key < b,
+        Bound::Excluded(b) => key <= b,
+        Bound::Unbounded => false,
+    }
+}
+
+/// Whether a key comes after everything `bound` admits as an upper bound.
+fn above<Q: Ord + ?Sized>(key: &Q, bound: Bound<&Q>) -> bool {
+    match bound {
+        Bound::Included(b) => key > b,
+        Bound::Excluded(b) => key >= b,
+        Bound::Unbounded => false,
+    }
+}
+
+// Reclaimed nodes are dropped on whichever thread runs the epoch collector.
+impl<K: Ord + Send + 'static, V: Send + 'static> SkipList<K, V> {
+    /// Drop one of the two pending unlinking passes, reclaiming the node after the last one.
+    unsafe fn release(node: Shared<'_, Node<K, V>>, guard: &Guard) {
+        if node.deref().pending.fetch_sub(1, Ordering::AcqRel) == 1 {
+            guard.defer_destroy(node);
+        }
+    }
+
+    /// Locate `key`, unlinking every removed node met on the way.
+    fn search<'g, Q>(&'g self, key: &Q, guard: &'g Guard) -> Position<'g, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        'retry: loop {
+            let mut pred: &'g Tower<K, V> = &self.head;
+            let mut pos = Position {
+                preds: [pred; MAX_HEIGHT],
+                succs: [Shared::null(); MAX_HEIGHT],
+            };
+
+            for level in (0..MAX_HEIGHT).rev() {
+                let mut curr = pred[level].load(Ordering::SeqCst, guard);
+                if curr.tag() == 1 {
+                    // `pred` got removed under us.
+                    continue 'retry;
+                }
+
+                while let Some(c) = unsafe { curr.as_ref() } {
+                    let succ = c.tower[level].load(Ordering::SeqCst, guard);
+                    if succ.tag() == 1 {
+                        match pred[level].compare_exchange(
+                            curr,
+                            succ.with_tag(0),
+                            Ordering::SeqCst,
+                            Ordering::SeqCst,
+                            guard,
+                        ) {
+                            Ok(_) => {
+                                curr = succ.with_tag(0);
+                                continue;
+                            }
+                            Err(_) => continue 'retry,
+                        }
+                    }
+
+                    if !c.enter(guard) {
+                        // Decided to be removed, so marked at this level too: go round to unlink.
+                        continue;
+                    }
+                    let before = K::borrow(&c.key) < key;
+                    c.exit();
+                    if before {
+                        pred = &c.tower;
+                        curr = succ;
+                    } else {
+                        break;
+                    }
+                }
+
+                pos.preds[level] = pred;
+                pos.succs[level] = curr;
+            }
+
+            return pos;
+        }
+    }
+
+    /// Where `key` goes, or `None` if it's there already.
+    fn position<'g>(&'g self, key: &K, guard: &'g Guard) -> Option<Position<'g, K, V>> {
+        loop {
+            let pos = self.search(key, guard);
+            let Some(n) = (unsafe { pos.succs[0].as_ref() }) else {
+                return Some(pos);
+            };
+            // Otherwise it's being removed: search again to unlink it.
+            if n.enter(guard) {
+                let present = *n.key == *key;
+                n.exit();
+                return (!present).then_some(pos);
+            }
+        }
+    }
+
+    /// The first node admitted by `bound`, removed or not. Unlike `search` this only reads.
+    fn seek<'g, Q>(&'g self, bound: Bound<&Q>, guard: &'g Guard) -> Shared<'g, Node<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut pred: &'g Tower<K, V> = &self.head;
+        let mut curr = Shared::null();
+        for level in (0..MAX_HEIGHT).rev() {
+            curr = pred[level].load(Ordering::SeqCst, guard).with_tag(0);
+            while let Some(c) = unsafe { curr.as_ref() } {
+                if !below(K::borrow(&c.key), bound) {
+                    break;
+                }
+                pred = &c.tower;
+                curr = c.tower[level].load(Ordering::SeqCst, guard).with_tag(0);
+            }
+        }
+        // Not `pred[0]` reloaded: a node inserted after it meanwhile may not be admitted.
+        curr
+    }
+
+    /// The live node holding `key`.
+    fn find<'g, Q>(&'g self, key: &Q, guard: &'g Guard) -> Option<Shared<'g, Node<K, V>>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut curr = self.seek(Bound::Included(key), guard);
+        loop {
+            let c = unsafe { curr.as_ref() }?;
+            if K::borrow(&c.key) != key {
+                return None;
+            }
+            let succ = c.tower[0].load(Ordering::SeqCst, guard);
+            if succ.tag() == 0 {
+                return Some(curr);
+            }
+            curr = succ.with_tag(0);
+        }
+    }
+
+    pub fn get<'g, Q>(&'g self, key: &Q, guard: &'g Guard) -> Option<(&'g K, &'g V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let node = unsafe { self.find(key, guard)?.deref() };
+        Some((&node.key, &node.value))
+    }
+
+    /// Entries whose keys lie in `range`, in order. Entries inserted or removed during the
+    /// iteration may or may not be seen.
+    pub fn range<'g, Q, R>(&'g self, range: R, guard: &'g Guard) -> Range<'g, K, V, Q, R>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        Range {
+            curr: self.seek(range.start_bound(), guard),
+            range,
+            guard,
+            _key: PhantomData,
+        }
+    }
+
+    /// Insert `key` unless it is already present. Returns whether it was inserted.
+    pub fn insert(&self, key: K, value: V, guard: &Guard) -> bool {
+        let Some(mut pos) = self.position(&key, guard) else {
+            return false;
+        };
+
+        let height = random_height();
+        let node = Owned::new(Node {
+            key: ManuallyDrop::new(key),
+            value: ManuallyDrop::new(value),
+            tower: (0..height).map(|_| Atomic::null()).collect(),
+            pending: AtomicUsize::new(2),
+            // We keep reading the key until we're done.
+            readers: AtomicUsize::new(1),
+            taken: AtomicBool::new(false),
+        })
+        .into_shared(guard);
+        let n = unsafe { node.deref() };
+
+        // Counted before the node becomes visible, so a removal never brings `len` below zero.
+        self.len.fetch_add(1, Ordering::Relaxed);
+        loop {
+            n.tower[0].store(pos.succs[0], Ordering::Relaxed);
+            if pos.preds[0][0]
+                .compare_exchange(pos.succs[0], node, Ordering::SeqCst, Ordering::SeqCst, guard)
+                .is_ok()
+            {
+                break;
+            }
+
+            match self.position(&n.key, guard) {
+                Some(p) => pos = p,
+                None => {
+                    // Never published.
+                    self.len.fetch_sub(1, Ordering::Relaxed);
+                    drop(unsafe { node.into_owned() });
+                    return false;
+                }
+            }
+        }
+
+        'build: for level in 1..height {
+            loop {
+                let next = n.tower[level].load(Ordering::SeqCst, guard);
+                if next.tag() == 1
+                    || n.tower[level]
+                        .compare_exchange(next, pos.succs[level], Ordering::SeqCst, Ordering::SeqCst, guard)
+                        .is_err()
+                {
+                    break 'build;
+                }
+
+                if pos.preds[level][level]
+                    .compare_exchange(pos.succs[level], node, Ordering::SeqCst, Ordering::SeqCst, guard)
+                    .is_ok()
+                {
+                    break;
+                }
+
+                pos = self.search(&*n.key, guard);
+                if pos.succs[0] != node {
+                    break 'build;
+                }
+            }
+        }
+
+        // A remover that ran before we linked some level may have missed it.
+        if n.tower[0].load(Ordering::SeqCst, guard).tag() == 1 {
+            self.search(&*n.key, guard);
+        }
+        n.exit();
+        unsafe { Self::release(node, guard) };
+        true
+    }
+
+    /// Decide the removal of `node`. Only one caller wins; it also unlinks the node.
+    fn remove_node(&self, node: Shared<'_, Node<K, V>>, guard: &Guard) -> bool {
+        let n = unsafe { node.deref() };
+        for level in (1..n.tower.len()).rev() {
+            n.tower[level].fetch_or(1, Ordering::SeqCst, guard);
+        }
+        if n.tower[0].fetch_or(1, Ordering::SeqCst, guard).tag() == 1 {
+            return false;
+        }
+
+        self.len.fetch_sub(1, Ordering::Relaxed);
+        self.search(&*n.key, guard);
+        unsafe { Self::release(node, guard) };
+        true
+    }
+
+    pub fn pop_first<'g>(&'g self, guard: &'g Guard) -> Option<(&'g K, &'g V)> {
+        loop {
+            let node = self.first_node(guard)?;
+            if self.remove_node(node, guard) {
+                let n = unsafe { node.deref() };
+                return Some((&n.key, &n.value));
+            }
+        }
+    }
+
+    /// Remove the first entry and move it out, once searches comparing against it are done.
+    ///
+    /// Only for lists read through [`Self::with_first`] alone: the references `first`, `get`,
+    /// `range` and the like hand out would dangle.
+    pub fn take_first(&self, guard: &Guard) -> Option<(K, V)> {
+        loop {
+            let node = self.first_node(guard)?;
+            if self.remove_node(node, guard) {
+                let n = unsafe { node.deref() };
+                let backoff = Backoff::new();
+                while n.readers.load(Ordering::SeqCst) != 0 {
+                    backoff.snooze();
+                }
+                n.taken.store(true, Ordering::Relaxed);
+                return Some(unsafe { (ptr::read(&*n.key), ptr::read(&*n.value)) });
+            }
+        }
+    }
+
+    /// Apply `f` to the first entry, which `take_first` can't move out meanwhile.
+    pub fn with_first<R>(&self, guard: &Guard, f: impl FnOnce(&K, &V) -> R) -> Option<R> {
+        loop {
+            let n = unsafe { self.first_node(guard)?.deref() };
+            if n.enter(guard) {
+                let r = f(&n.key, &n.value);
+                n.exit();
+                return Some(r);
+            }
+        }
+    }
+
+    pub fn remove<'g, Q>(&'g self, key: &Q, guard: &'g Guard) -> Option<(&'g K, &'g V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        loop {
+            let node = self.find(key, guard)?;
+            if self.remove_node(node, guard) {
+                let n = unsafe { node.deref() };
+                return Some((&n.key, &n.value));
+            }
+        }
+    }
+}
+
+pub struct Range<'g, K, V, Q: ?Sized, R> {
+    curr: Shared<'g, Node<K, V>>,
+    range: R,
+    guard: &'g Guard,
+    _key: PhantomData<fn(&Q)>,
+}
+
+impl<'g, K, V, Q, R> Iterator for Range<'g, K, V, Q, R>
+where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+{
+    type Item = (&'g K, &'g V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(c) = unsafe { self.curr.as_ref() } {
+            if above(K::borrow(&c.key), self.range.end_bound()) {
+                self.curr = Shared::null();
+                break;
+            }
+
+            let succ = c.tower[0].load(Ordering::SeqCst, self.guard);
+            self.curr = succ.with_tag(0);
+            if succ.tag() == 0 {
+                return Some((&c.key, &c.value));
+            }
+        }
+        None
+    }
+}
+
+impl<K, V> Drop for SkipList<K, V> {
+    fn drop(&mut self) {
+        // Every removed node has been unlinked and handed to the collector already.
+        unsafe {
+            let guard = epoch::unprotected();
+            let mut curr = self.head[0].load(Ordering::Relaxed, guard);
+            while !curr.is_null() {
+                let node = curr.into_owned();
+                curr = node.tower[0].load(Ordering::Relaxed, guard);
+            }
+        }
+    }
+}
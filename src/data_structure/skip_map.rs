@@ -0,0 +1,349 @@
+use std::{
+    borrow::Borrow,
+    ops::{RangeBounds, RangeFull},
+};
+
+use crossbeam::epoch::Guard;
+
+use super::skip_list::SkipList;
+pub use super::skip_list::Range;
+
+/// A lock-free ordered map on a skip list.
+///
+/// Lookups hand out references tied to a pinned [`Guard`]: keys and values are only dropped once
+/// every guard that could have seen their entry is gone.
+pub struct SkipMap<K, V> {
+    list: SkipList<K, V>,
+}
+
+impl<K, V> SkipMap<K, V> {
+    pub fn new() -> Self {
+        Self {
+            list: SkipList::new(),
+        }
+    }
+
+    /// Number of entries. Only exact when no other thread is modifying the map.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn first<'g>(&'g self, guard: &'g Guard) -> Option<(&'g K, &'g V)> {
+        self.list.first(guard)
+    }
+
+    pub fn last<'g>(&'g self, guard: &'g Guard) -> Option<(&'g K, &'g V)> {
+        self.list.last(guard)
+    }
+}
+
+impl<K: Ord + Send + 'static, V: Send + 'static> SkipMap<K, V> {
+    /// Insert `key` unless it is already present. Returns whether it was inserted.
+    ///
+    /// An existing entry is never replaced in place, since readers may hold references to it:
+    /// remove it first.
+    pub fn insert(&self, key: K, value: V, guard: &Guard) -> bool {
+        self.list.insert(key, value, guard)
+    }
+
+    pub fn get<'g, Q>(&'g self, key: &Q, guard: &'g Guard) -> Option<&'g V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.list.get(key, guard).map(|(_, v)| v)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q, guard: &Guard) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.list.get(key, guard).is_some()
+    }
+
+    /// Remove `key`, returning its value if this call was the one to remove it.
+    pub fn remove<'g, Q>(&'g self, key: &Q, guard: &'g Guard) -> Option<&'g V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.list.remove(key, guard).map(|(_, v)| v)
+    }
+
+    pub fn pop_first<'g>(&'g self, guard: &'g Guard) -> Option<(&'g K, &'g V)> {
+        self.list.pop_first(guard)
+    }
+
+    /// Entries whose keys lie in `range`, in order. Entries inserted or removed during the
+    /// iteration may or may not be seen.
+    pub fn range<'g, Q, R>(&'g self, range: R, guard: &'g Guard) -> Range<'g, K, V, Q, R>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        self.list.range(range, guard)
+    }
+
+    pub fn iter<'g>(&'g self, guard: &'g Guard) -> Range<'g, K, V, K, RangeFull> {
+        self.list.range(.., guard)
+    }
+}
+
+impl<K, V> Default for SkipMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A lock-free ordered set on a skip list. See [`SkipMap`].
+pub struct SkipSet<K> {
+    map: SkipMap<K, ()>,
+}
+
+impl<K> SkipSet<K> {
+    pub fn new() -> Self {
+        Self {
+            map: SkipMap::new(),
+        }
+    }
+
+    /// Number of keys. Only exact when no other thread is modifying the set.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn first<'g>(&'g self, guard: &'g Guard) -> Option<&'g K> {
+        self.map.first(guard).map(|(k, _)| k)
+    }
+
+    pub fn last<'g>(&'g self, guard: &'g Guard) -> Option<&'g K> {
+        self.map.last(guard).map(|(k, _)| k)
+    }
+}
+
+impl<K: Ord + Send + 'static> SkipSet<K> {
+    /// Returns whether `key` was newly inserted.
+    pub fn insert(&self, key: K, guard: &Guard) -> bool {
+        self.map.insert(key, (), guard)
+    }
+
+    pub fn get<'g, Q>(&'g self, key: &Q, guard: &'g Guard) -> Option<&'g K>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.list.get(key, guard).map(|(k, _)| k)
+    }
+
+    pub fn contains<Q>(&self, key: &Q, guard: &Guard) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.contains_key(key, guard)
+    }
+
+    /// Returns whether this call was the one to remove `key`.
+    pub fn remove<Q>(&self, key: &Q, guard: &Guard) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.remove(key, guard).is_some()
+    }
+
+    pub fn pop_first<'g>(&'g self, guard: &'g Guard) -> Option<&'g K> {
+        self.map.pop_first(guard).map(|(k, _)| k)
+    }
+
+    /// Keys in `range`, in order. Keys inserted or removed during the iteration may or may not
+    /// be seen.
+    pub fn range<'g, Q, R>(&'g self, range: R, guard: &'g Guard) -> impl Iterator<Item = &'g K>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized + 'g,
+        R: RangeBounds<Q> + 'g,
+    {
+        self.map.range(range, guard).map(|(k, _)| k)
+    }
+
+    pub fn iter<'g>(&'g self, guard: &'g Guard) -> impl Iterator<Item = &'g K> {
+        self.map.iter(guard).map(|(k, _)| k)
+    }
+}
+
+impl<K> Default for SkipSet<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::BTreeMap,
+        ops::{Bound, RangeBounds},
+        sync::Arc,
+        thread,
+    };
+
+    use crossbeam::epoch;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{Range, SkipMap, SkipSet};
+
+    #[test]
+    fn map_operations() {
+        let map = SkipMap::new();
+        let guard = epoch::pin();
+        for (k, v) in [(3, "c"), (1, "a"), (4, "d"), (2, "b")] {
+            assert!(map.insert(k, v, &guard));
+        }
+        assert!(!map.insert(3, "x", &guard));
+
+        assert_eq!(map.len(), 4);
+        assert_eq!(map.get(&3, &guard), Some(&"c"));
+        assert_eq!(map.get(&5, &guard), None);
+        assert_eq!(map.first(&guard), Some((&1, &"a")));
+        assert_eq!(map.last(&guard), Some((&4, &"d")));
+
+        let value = map.remove(&3, &guard);
+        assert_eq!(value, Some(&"c"));
+        assert_eq!(map.remove(&3, &guard), None);
+        assert!(!map.contains_key(&3, &guard));
+        // Still readable under the guard it was removed with.
+        assert_eq!(value, Some(&"c"));
+
+        assert_eq!(map.pop_first(&guard), Some((&1, &"a")));
+        assert_eq!(map.iter(&guard).collect::<Vec<_>>(), [(&2, &"b"), (&4, &"d")]);
+        assert_eq!(map.remove(&4, &guard), Some(&"d"));
+        assert_eq!(map.last(&guard), Some((&2, &"b")));
+    }
+
+    #[test]
+    fn ranges() {
+        let map = SkipMap::new();
+        let guard = epoch::pin();
+        for k in (0..100).step_by(10) {
+            map.insert(k, k * 2, &guard);
+        }
+
+        fn keys<R: RangeBounds<i32>>(range: Range<'_, i32, i32, i32, R>) -> Vec<i32> {
+            range.map(|(k, _)| *k).collect()
+        }
+        assert_eq!(keys(map.range(20..50, &guard)), [20, 30, 40]);
+        assert_eq!(keys(map.range(15..=50, &guard)), [20, 30, 40, 50]);
+        assert_eq!(keys(map.range(..25, &guard)), [0, 10, 20]);
+        assert_eq!(keys(map.range(85.., &guard)), [90]);
+        assert_eq!(
+            keys(map.range((Bound::Excluded(10), Bound::Excluded(40)), &guard)),
+            [20, 30]
+        );
+        assert!(keys(map.range(41..49, &guard)).is_empty());
+    }
+
+    #[test]
+    fn set_with_borrowed_keys() {
+        let set = SkipSet::new();
+        let guard = epoch::pin();
+        for word in ["pear", "apple", "fig", "kiwi"] {
+            set.insert(word.to_string(), &guard);
+        }
+
+        assert!(set.contains("fig", &guard));
+        assert_eq!(set.get("kiwi", &guard).map(String::as_str), Some("kiwi"));
+        let range = (Bound::Included("b"), Bound::Excluded("l"));
+        assert_eq!(set.range::<str, _>(range, &guard).collect::<Vec<_>>(), ["fig", "kiwi"]);
+        assert!(set.remove("fig", &guard));
+        assert!(!set.remove("fig", &guard));
+        assert_eq!(set.first(&guard).map(String::as_str), Some("apple"));
+        assert_eq!(set.last(&guard).map(String::as_str), Some("pear"));
+        assert_eq!(set.pop_first(&guard).map(String::as_str), Some("apple"));
+        assert_eq!(set.iter(&guard).collect::<Vec<_>>(), ["kiwi", "pear"]);
+    }
+
+    #[test]
+    fn sequential_model() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let map = SkipMap::new();
+        let mut model = BTreeMap::new();
+
+        for _ in 0..20_000 {
+            let guard = epoch::pin();
+            let k: u16 = rng.gen_range(0..300);
+            match rng.gen_range(0..4) {
+                0 | 1 => {
+                    let inserted = !model.contains_key(&k);
+                    if inserted {
+                        model.insert(k, k.to_string());
+                    }
+                    assert_eq!(map.insert(k, k.to_string(), &guard), inserted);
+                }
+                2 => assert_eq!(map.remove(&k, &guard), model.remove(&k).as_ref()),
+                _ => {
+                    let hi = k.saturating_add(rng.gen_range(0..40));
+                    let got: Vec<_> = map.range(k..hi, &guard).collect();
+                    let want: Vec<_> = model.range(k..hi).collect();
+                    assert_eq!(got, want);
+                }
+            }
+            assert_eq!(map.first(&guard), model.first_key_value());
+            assert_eq!(map.last(&guard), model.last_key_value());
+        }
+        assert_eq!(map.len(), model.len());
+    }
+
+    #[test]
+    fn concurrent_insert_remove_scan() {
+        const THREADS: u64 = 4;
+        const PER_THREAD: u64 = 5_000;
+        let map = Arc::new(SkipMap::new());
+
+        let handles: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let map = map.clone();
+                thread::spawn(move || {
+                    let mut rng = StdRng::seed_from_u64(t);
+                    for i in 0..PER_THREAD {
+                        let guard = epoch::pin();
+                        let k = i * THREADS + t;
+                        assert!(map.insert(k, t, &guard));
+                        if i % 2 == 0 {
+                            assert_eq!(map.remove(&k, &guard), Some(&t));
+                        }
+
+                        // Scans always come out sorted, whatever is being removed meanwhile.
+                        let lo = rng.gen_range(0..THREADS * PER_THREAD);
+                        let keys: Vec<_> = map.range(lo..lo + 100, &guard).map(|(k, _)| *k).collect();
+                        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+                        assert!(keys.iter().all(|k| (lo..lo + 100).contains(k)));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let guard = epoch::pin();
+        let keys: Vec<_> = map.iter(&guard).map(|(k, _)| *k).collect();
+        let want: Vec<_> = (0..PER_THREAD)
+            .filter(|i| i % 2 == 1)
+            .flat_map(|i| (0..THREADS).map(move |t| i * THREADS + t))
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        assert_eq!(keys, want);
+        assert_eq!(map.len(), want.len());
+    }
+}
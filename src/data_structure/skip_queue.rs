@@ -0,0 +1,167 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crossbeam::epoch;
+
+use super::skip_list::SkipList;
+
+/// A lock-free min-priority queue on a skip list. `pop_min` claims the first live node of the
+/// bottom level, so popping costs O(1) expected on top of the unlinking search.
+///
+/// Equal priorities pop in insertion order. `pop_min` moves the element out, first waiting for any
+/// push still comparing against it.
+pub struct SkipQueue<T> {
+    list: SkipList<(T, u64), ()>,
+    seq: AtomicU64,
+}
+
+impl<T> SkipQueue<T> {
+    pub fn new() -> Self {
+        Self {
+            list: SkipList::new(),
+            seq: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of elements. Only exact when no other thread is modifying the queue.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Ord + Send + 'static> SkipQueue<T> {
+    pub fn push(&self, elem: T) {
+        let guard = epoch::pin();
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        self.list.insert((elem, seq), (), &guard);
+    }
+
+    pub fn pop_min(&self) -> Option<T> {
+        let guard = epoch::pin();
+        self.list.take_first(&guard).map(|((elem, _), ())| elem)
+    }
+
+    pub fn peek(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        let guard = epoch::pin();
+        self.list.with_first(&guard, |(elem, _), _| elem.clone())
+    }
+}
+
+impl<T> Default for SkipQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cmp::Reverse, collections::BinaryHeap, sync::Arc, thread};
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::SkipQueue;
+
+    #[test]
+    fn push_and_pop() {
+        let queue = SkipQueue::new();
+        for i in [5, 1, 4, 1, 3] {
+            queue.push(i);
+        }
+
+        assert_eq!(queue.peek(), Some(1));
+        assert_eq!(queue.len(), 5);
+        let popped: Vec<_> = std::iter::from_fn(|| queue.pop_min()).collect();
+        assert_eq!(popped, [1, 1, 3, 4, 5]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn pop_min_moves_elements_out() {
+        /// Neither `Clone` nor `Copy`; the `Arc` counts the copies alive.
+        #[derive(PartialEq, Eq, PartialOrd, Ord)]
+        struct Tracked(u32, Arc<()>);
+
+        let alive = Arc::new(());
+        let queue = SkipQueue::new();
+        for i in [3, 1, 2, 5, 4] {
+            queue.push(Tracked(i, alive.clone()));
+        }
+
+        let popped: Vec<_> = (0..3).map_while(|_| queue.pop_min()).map(|t| t.0).collect();
+        assert_eq!(popped, [1, 2, 3]);
+        assert_eq!(Arc::strong_count(&alive), 3);
+        drop(queue);
+        assert_eq!(Arc::strong_count(&alive), 1);
+    }
+
+    #[test]
+    fn sequential_model() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let queue = SkipQueue::new();
+        let mut model = BinaryHeap::new();
+
+        for _ in 0..20_000 {
+            if rng.gen_bool(0.55) {
+                let x: u16 = rng.gen_range(0..500);
+                queue.push(x.to_string());
+                model.push(Reverse(x.to_string()));
+            } else {
+                assert_eq!(queue.pop_min(), model.pop().map(|r| r.0));
+            }
+            assert_eq!(queue.peek(), model.peek().map(|r| r.0.clone()));
+        }
+    }
+
+    #[test]
+    fn stress() {
+        const THREADS: u64 = 8;
+        const PER_THREAD: u64 = 5_000;
+        let queue = Arc::new(SkipQueue::new());
+
+        // Mixed phase: everything pushed is popped exactly once.
+        let handles: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    let mut rng = StdRng::seed_from_u64(t);
+                    let mut popped = vec![];
+                    for i in 0..PER_THREAD {
+                        queue.push(i * THREADS + t);
+                        if rng.gen_bool(0.5) {
+                            popped.extend(queue.pop_min());
+                        }
+                    }
+                    popped
+                })
+            })
+            .collect();
+
+        let mut all: Vec<u64> = handles.into_iter().flat_map(|h| h.join().unwrap()).collect();
+        let remaining = queue.len();
+
+        // Pop-only phase: every thread sees increasing minima.
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    let popped: Vec<_> = std::iter::from_fn(|| queue.pop_min()).collect();
+                    assert!(popped.windows(2).all(|w| w[0] < w[1]));
+                    popped
+                })
+            })
+            .collect();
+        let drained: Vec<u64> = handles.into_iter().flat_map(|h| h.join().unwrap()).collect();
+        assert_eq!(drained.len(), remaining);
+
+        all.extend(drained);
+        all.sort();
+        assert_eq!(all, (0..THREADS * PER_THREAD).collect::<Vec<_>>());
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use crossbeam::utils::CachePadded;
+
+struct Inner<T> {
+    buffer: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    /// Next position to pop, only written by the consumer.
+    head: CachePadded<AtomicUsize>,
+    /// Next position to push, only written by the producer.
+    tail: CachePadded<AtomicUsize>,
+}
+
+unsafe impl<T: Send> Sync for Inner<T> {}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        let (head, tail) = (*self.head.get_mut(), *self.tail.get_mut());
+        for pos in head..tail {
+            unsafe { self.buffer[pos % self.buffer.len()].get_mut().assume_init_drop() };
+        }
+    }
+}
+
+/// A bounded single-producer single-consumer queue. Each side keeps a stale copy of the other
+/// side's index and only reloads it when the queue looks full or empty, so in the steady state
+/// neither side touches the other's cache line.
+pub fn bounded<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    assert!(capacity > 0, "SPSC queue needs a positive capacity");
+    let inner = Arc::new(Inner {
+        buffer: (0..capacity)
+            .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+            .collect(),
+        head: CachePadded::new(AtomicUsize::new(0)),
+        tail: CachePadded::new(AtomicUsize::new(0)),
+    });
+
+    (
+        Producer {
+            inner: inner.clone(),
+            tail: 0,
+            cached_head: 0,
+        },
+        Consumer {
+            inner,
+            head: 0,
+            cached_tail: 0,
+        },
+    )
+}
+
+pub struct Producer<T> {
+    inner: Arc<Inner<T>>,
+    tail: usize,
+    cached_head: usize,
+}
+
+impl<T> Producer<T> {
+    pub fn capacity(&self) -> usize {
+        self.inner.buffer.len()
+    }
+
+    /// Number of elements, as seen by the producer.
+    pub fn len(&self) -> usize {
+        self.tail - self.inner.head.load(Ordering::Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Push `val`, or hand it back if the queue is full.
+    pub fn try_push(&mut self, val: T) -> Result<(), T> {
+        if self.tail - self.cached_head == self.capacity() {
+            self.cached_head = self.inner.head.load(Ordering::Acquire);
+            if self.tail - self.cached_head == self.capacity() {
+                return Err(val);
+            }
+        }
+
+        let cell = &self.inner.buffer[self.tail % self.capacity()];
+        unsafe { (*cell.get()).write(val) };
+        self.tail += 1;
+        self.inner.tail.store(self.tail, Ordering::Release);
+        Ok(())
+    }
+}
+
+pub struct Consumer<T> {
+    inner: Arc<Inner<T>>,
+    head: usize,
+    cached_tail: usize,
+}
+
+impl<T> Consumer<T> {
+    pub fn capacity(&self) -> usize {
+        self.inner.buffer.len()
+    }
+
+    /// Number of elements, as seen by the consumer.
+    pub fn len(&self) -> usize {
+        self.inner.tail.load(Ordering::Acquire) - self.head
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn try_pop(&mut self) -> Option<T> {
+        if self.head == self.cached_tail {
+            self.cached_tail = self.inner.tail.load(Ordering::Acquire);
+            if self.head == self.cached_tail {
+                return None;
+            }
+        }
+
+        let cell = &self.inner.buffer[self.head % self.capacity()];
+        let val = unsafe { (*cell.get()).assume_init_read() };
+        self.head += 1;
+        self.inner.head.store(self.head, Ordering::Release);
+        Some(val)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::bounded;
+
+    #[test]
+    fn push_until_full() {
+        let (mut tx, mut rx) = bounded(2);
+        assert_eq!(tx.try_push(1), Ok(()));
+        assert_eq!(tx.try_push(2), Ok(()));
+        assert_eq!(tx.try_push(3), Err(3));
+        assert_eq!(rx.len(), 2);
+
+        assert_eq!(rx.try_pop(), Some(1));
+        assert_eq!(tx.try_push(3), Ok(()));
+        assert_eq!(rx.try_pop(), Some(2));
+        assert_eq!(rx.try_pop(), Some(3));
+        assert_eq!(rx.try_pop(), None);
+        assert!(tx.is_empty());
+    }
+
+    #[test]
+    fn producer_consumer() {
+        const N: usize = 100_000;
+        let (mut tx, mut rx) = bounded(16);
+
+        let producer = thread::spawn(move || {
+            for i in 0..N {
+                let mut val = i.to_string();
+                while let Err(v) = tx.try_push(val) {
+                    val = v;
+                    thread::yield_now();
+                }
+            }
+        });
+
+        let mut expected = 0;
+        while expected < N {
+            match rx.try_pop() {
+                Some(v) => {
+                    assert_eq!(v, expected.to_string());
+                    expected += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+        producer.join().unwrap();
+    }
+}
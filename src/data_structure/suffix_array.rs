@@ -0,0 +1,273 @@
+use std::ops::Range;
+
+const NONE: usize = usize::MAX;
+
+/// Suffix array of a text with its LCP array.
+///
+/// `sa[i]` is the start of the `i`th smallest suffix and `lcp[i]` is the length of the longest
+/// common prefix of the suffixes `sa[i - 1]` and `sa[i]` (`lcp[0] == 0`).
+pub struct SuffixArray<T> {
+    text: Vec<T>,
+    sa: Vec<usize>,
+    rank: Vec<usize>,
+    lcp: Vec<usize>,
+}
+
+impl<T: Ord + Clone> SuffixArray<T> {
+    pub fn new(text: &[T]) -> Self {
+        // Compress symbols to `1..=k`, keeping `0` for the sentinel.
+        let mut alphabet: Vec<&T> = text.iter().collect();
+        alphabet.sort();
+        alphabet.dedup();
+        let mut s: Vec<usize> = text
+            .iter()
+            .map(|c| alphabet.binary_search(&c).unwrap() + 1)
+            .collect();
+        s.push(0);
+
+        let mut sa = sa_is(&s, alphabet.len() + 1);
+        sa.remove(0);
+
+        let mut rank = vec![0; sa.len()];
+        for (i, &p) in sa.iter().enumerate() {
+            rank[p] = i;
+        }
+        let lcp = kasai(&s[..text.len()], &sa, &rank);
+
+        Self {
+            text: text.to_vec(),
+            sa,
+            rank,
+            lcp,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.sa.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sa.is_empty()
+    }
+
+    pub fn suffix_array(&self) -> &[usize] {
+        &self.sa
+    }
+
+    /// Inverse of the suffix array: `rank[sa[i]] == i`.
+    pub fn rank(&self) -> &[usize] {
+        &self.rank
+    }
+
+    pub fn lcp(&self) -> &[usize] {
+        &self.lcp
+    }
+
+    /// Range of `sa` whose suffixes start with `pattern`.
+    fn equal_range(&self, pattern: &[T]) -> Range<usize> {
+        let prefix = |p: usize| &self.text[p..(p + pattern.len()).min(self.text.len())];
+
+        let l = self.sa.partition_point(|&p| prefix(p) < pattern);
+        let r = self.sa.partition_point(|&p| prefix(p) <= pattern);
+        l..r
+    }
+
+    /// Start positions of all occurrences of `pattern`, in suffix order.
+    pub fn occurrences(&self, pattern: &[T]) -> &[usize] {
+        &self.sa[self.equal_range(pattern)]
+    }
+
+    /// Longest substring occurring at least twice, as a range of the text.
+    pub fn longest_repeated_substring(&self) -> Range<usize> {
+        self.lcp
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, l)| **l)
+            .map_or(0..0, |(i, &l)| self.sa[i]..self.sa[i] + l)
+    }
+}
+
+/// Kasai's algorithm, O(n).
+fn kasai(s: &[usize], sa: &[usize], rank: &[usize]) -> Vec<usize> {
+    let mut lcp = vec![0; sa.len()];
+    let mut h = 0;
+
+    for i in 0..s.len() {
+        if rank[i] == 0 {
+            h = 0;
+            continue;
+        }
+
+        let j = sa[rank[i] - 1];
+        while i + h < s.len() && j + h < s.len() && s[i + h] == s[j + h] {
+            h += 1;
+        }
+        lcp[rank[i]] = h;
+        h = h.saturating_sub(1);
+    }
+
+    lcp
+}
+
+/// SA-IS over `s` with symbols in `0..k`, where `s` ends with a unique smallest symbol.
+fn sa_is(s: &[usize], k: usize) -> Vec<usize> {
+    let n = s.len();
+    if n == 1 {
+        return vec![0];
+    }
+
+    // `true` for S-type positions.
+    let mut t = vec![true; n];
+    for i in (0..n - 1).rev() {
+        t[i] = s[i] < s[i + 1] || (s[i] == s[i + 1] && t[i + 1]);
+    }
+    let is_lms = |i: usize| i > 0 && t[i] && !t[i - 1];
+
+    let mut bucket = vec![0; k];
+    for &c in s {
+        bucket[c] += 1;
+    }
+
+    let induce = |lms: &[usize]| {
+        let mut sa = vec![NONE; n];
+
+        let mut tails: Vec<usize> = bucket
+            .iter()
+            .scan(0, |acc, b| {
+                *acc += b;
+                Some(*acc)
+            })
+            .collect();
+        for &p in lms.iter().rev() {
+            tails[s[p]] -= 1;
+            sa[tails[s[p]]] = p;
+        }
+
+        let mut heads: Vec<usize> = bucket
+            .iter()
+            .scan(0, |acc, b| {
+                let head = *acc;
+                *acc += b;
+                Some(head)
+            })
+            .collect();
+        for i in 0..n {
+            let j = sa[i];
+            if j != NONE && j > 0 && !t[j - 1] {
+                sa[heads[s[j - 1]]] = j - 1;
+                heads[s[j - 1]] += 1;
+            }
+        }
+
+        let mut tails: Vec<usize> = bucket
+            .iter()
+            .scan(0, |acc, b| {
+                *acc += b;
+                Some(*acc)
+            })
+            .collect();
+        for i in (0..n).rev() {
+            let j = sa[i];
+            if j != NONE && j > 0 && t[j - 1] {
+                tails[s[j - 1]] -= 1;
+                sa[tails[s[j - 1]]] = j - 1;
+            }
+        }
+
+        sa
+    };
+
+    let lms: Vec<usize> = (1..n).filter(|&i| is_lms(i)).collect();
+    let sa = induce(&lms);
+
+    // Name LMS substrings by their order after the first induction.
+    let lms_equal = |a: usize, b: usize| {
+        for d in 0.. {
+            if s[a + d] != s[b + d] || t[a + d] != t[b + d] {
+                return false;
+            }
+            if d > 0 && (is_lms(a + d) || is_lms(b + d)) {
+                return is_lms(a + d) && is_lms(b + d);
+            }
+        }
+        unreachable!()
+    };
+
+    let mut names = vec![NONE; n];
+    let mut name = 0;
+    let mut prev = None;
+    for &p in sa.iter().filter(|&&p| is_lms(p)) {
+        if let Some(q) = prev {
+            if !lms_equal(q, p) {
+                name += 1;
+            }
+        }
+        names[p] = name;
+        prev = Some(p);
+    }
+
+    let reduced: Vec<usize> = lms.iter().map(|&p| names[p]).collect();
+    let reduced_sa = if name + 1 < lms.len() {
+        sa_is(&reduced, name + 1)
+    } else {
+        let mut reduced_sa = vec![0; reduced.len()];
+        for (i, &c) in reduced.iter().enumerate() {
+            reduced_sa[c] = i;
+        }
+        reduced_sa
+    };
+
+    let sorted_lms: Vec<usize> = reduced_sa.into_iter().map(|i| lms[i]).collect();
+    induce(&sorted_lms)
+}
+
+#[cfg(test)]
+mod test {
+    use super::SuffixArray;
+
+    fn naive(text: &[u8]) -> Vec<usize> {
+        let mut sa: Vec<usize> = (0..text.len()).collect();
+        sa.sort_by_key(|&i| &text[i..]);
+        sa
+    }
+
+    #[test]
+    fn matches_naive_construction() {
+        for text in [
+            &b"banana"[..],
+            b"mississippi",
+            b"aaaaaaaa",
+            b"abracadabra",
+            b"",
+            b"z",
+            b"cabbagecabbagehead",
+        ] {
+            let sa = SuffixArray::new(text);
+            assert_eq!(sa.suffix_array(), naive(text));
+        }
+
+        // A pseudo-random text to exercise the recursion.
+        let mut x = 7u32;
+        let text: Vec<u8> = (0..2000)
+            .map(|_| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                b"ab"[(x >> 16) as usize % 2]
+            })
+            .collect();
+        assert_eq!(SuffixArray::new(&text).suffix_array(), naive(&text));
+    }
+
+    #[test]
+    fn lcp_and_queries() {
+        let text: Vec<char> = "banana".chars().collect();
+        let sa = SuffixArray::new(&text);
+        assert_eq!(sa.suffix_array(), [5, 3, 1, 0, 4, 2]);
+        assert_eq!(sa.lcp(), [0, 1, 3, 0, 0, 2]);
+
+        let mut occ = sa.occurrences(&['a', 'n', 'a']).to_vec();
+        occ.sort();
+        assert_eq!(occ, [1, 3]);
+        assert!(sa.occurrences(&['n', 'b']).is_empty());
+        assert_eq!(sa.longest_repeated_substring(), 1..4);
+    }
+}
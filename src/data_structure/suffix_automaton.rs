@@ -0,0 +1,198 @@
+use std::{collections::HashMap, hash::Hash, ops::Range};
+
+struct State<T> {
+    /// Length of the longest string in this state.
+    len: usize,
+    link: Option<usize>,
+    next: HashMap<T, usize>,
+    /// Number of end positions, i.e. occurrences of every string in this state.
+    occ: usize,
+}
+
+/// The minimal automaton accepting all substrings of a text, built online in O(n).
+pub struct SuffixAutomaton<T> {
+    states: Vec<State<T>>,
+    last: usize,
+}
+
+impl<T: Eq + Hash + Copy> SuffixAutomaton<T> {
+    pub fn new(text: impl IntoIterator<Item = T>) -> Self {
+        let mut sam = Self {
+            states: vec![State {
+                len: 0,
+                link: None,
+                next: HashMap::new(),
+                occ: 0,
+            }],
+            last: 0,
+        };
+
+        for c in text {
+            sam.extend(c);
+        }
+        sam.count_occurrences();
+        sam
+    }
+
+    fn extend(&mut self, c: T) {
+        let cur = self.states.len();
+        self.states.push(State {
+            len: self.states[self.last].len + 1,
+            link: None,
+            next: HashMap::new(),
+            occ: 1,
+        });
+
+        let mut p = Some(self.last);
+        while let Some(q) = p {
+            if self.states[q].next.contains_key(&c) {
+                break;
+            }
+            self.states[q].next.insert(c, cur);
+            p = self.states[q].link;
+        }
+
+        self.states[cur].link = Some(match p {
+            None => 0,
+            Some(p) => {
+                let q = self.states[p].next[&c];
+                if self.states[p].len + 1 == self.states[q].len {
+                    q
+                } else {
+                    let clone = self.states.len();
+                    self.states.push(State {
+                        len: self.states[p].len + 1,
+                        link: self.states[q].link,
+                        next: self.states[q].next.clone(),
+                        occ: 0,
+                    });
+
+                    let mut p = Some(p);
+                    while let Some(pp) = p {
+                        match self.states[pp].next.get_mut(&c) {
+                            Some(n) if *n == q => *n = clone,
+                            _ => break,
+                        }
+                        p = self.states[pp].link;
+                    }
+
+                    self.states[q].link = Some(clone);
+                    clone
+                }
+            }
+        });
+        self.last = cur;
+    }
+
+    /// Propagate end positions along suffix links, from longer states to shorter ones.
+    fn count_occurrences(&mut self) {
+        let mut order: Vec<usize> = (1..self.states.len()).collect();
+        order.sort_unstable_by_key(|&i| std::cmp::Reverse(self.states[i].len));
+
+        for i in order {
+            if let Some(link) = self.states[i].link {
+                self.states[link].occ += self.states[i].occ;
+            }
+        }
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.states.len()
+    }
+
+    fn find(&self, pattern: impl IntoIterator<Item = T>) -> Option<usize> {
+        pattern
+            .into_iter()
+            .try_fold(0, |state, c| self.states[state].next.get(&c).copied())
+    }
+
+    pub fn contains(&self, pattern: impl IntoIterator<Item = T>) -> bool {
+        self.find(pattern).is_some()
+    }
+
+    /// Number of (possibly overlapping) occurrences of `pattern` in the text. The empty pattern
+    /// occurs at every position.
+    pub fn occurrences(&self, pattern: impl IntoIterator<Item = T>) -> usize {
+        match self.find(pattern) {
+            Some(0) => self.states[self.last].len + 1,
+            Some(state) => self.states[state].occ,
+            None => 0,
+        }
+    }
+
+    /// Number of distinct non-empty substrings of the text.
+    pub fn distinct_substrings(&self) -> usize {
+        self.states
+            .iter()
+            .filter_map(|s| s.link.map(|l| s.len - self.states[l].len))
+            .sum()
+    }
+
+    /// Longest substring shared by the text and `other`, as a range of positions in `other`.
+    pub fn longest_common_substring(&self, other: impl IntoIterator<Item = T>) -> Range<usize> {
+        let (mut state, mut len) = (0, 0);
+        let mut best = 0..0;
+
+        for (i, c) in other.into_iter().enumerate() {
+            while state != 0 && !self.states[state].next.contains_key(&c) {
+                state = self.states[state].link.unwrap();
+                len = self.states[state].len;
+            }
+
+            if let Some(&n) = self.states[state].next.get(&c) {
+                state = n;
+                len += 1;
+            }
+
+            if len > best.len() {
+                best = i + 1 - len..i + 1;
+            }
+        }
+
+        best
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::SuffixAutomaton;
+
+    #[test]
+    fn distinct_substrings() {
+        let text = "abcbcabba";
+        let sam = SuffixAutomaton::new(text.chars());
+
+        let mut set = HashSet::new();
+        for i in 0..text.len() {
+            for j in i + 1..=text.len() {
+                set.insert(&text[i..j]);
+            }
+        }
+        assert_eq!(sam.distinct_substrings(), set.len());
+        assert!(sam.state_count() < 2 * text.len());
+    }
+
+    #[test]
+    fn occurrences() {
+        let sam = SuffixAutomaton::new("abababa".chars());
+        assert_eq!(sam.occurrences("aba".chars()), 3);
+        assert_eq!(sam.occurrences("b".chars()), 3);
+        assert_eq!(sam.occurrences("abababa".chars()), 1);
+        assert_eq!(sam.occurrences("bb".chars()), 0);
+        assert_eq!(sam.occurrences("".chars()), 8);
+        assert!(sam.contains("baba".chars()));
+    }
+
+    #[test]
+    fn longest_common_substring() {
+        let sam = SuffixAutomaton::new("xabcdey".chars());
+        let other = "zzbcdezz";
+        let range = sam.longest_common_substring(other.chars());
+        assert_eq!(&other[range], "bcde");
+
+        let sam = SuffixAutomaton::new([1, 2, 3]);
+        assert_eq!(sam.longest_common_substring([4, 5]), 0..0);
+    }
+}
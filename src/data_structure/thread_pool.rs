@@ -0,0 +1,251 @@
+use std::{
+    any::Any,
+    cell::Cell,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use super::{
+    queue::Queue,
+    work_stealing::{self, Steal, Stealer, Worker},
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+struct Shared {
+    /// Jobs spawned from outside the pool.
+    injector: Queue<Job>,
+    stealers: Vec<Stealer<Job>>,
+    /// Spawned jobs not yet taken by a worker.
+    pending: AtomicUsize,
+    shutdown: AtomicBool,
+    sleep: Mutex<()>,
+    wake: Condvar,
+    /// The payload of the first job that panicked, re-raised when the pool is dropped.
+    panic: Mutex<Option<Box<dyn Any + Send>>>,
+}
+
+thread_local! {
+    /// The pool and deque of the worker running on this thread.
+    static CURRENT: Cell<Option<(*const Shared, *const Worker<Job>)>> = const { Cell::new(None) };
+}
+
+impl Shared {
+    fn notify(&self) {
+        let _lock = self.sleep.lock().unwrap();
+        self.wake.notify_one();
+    }
+
+    fn find_job(&self, index: usize, local: &Worker<Job>) -> Option<Job> {
+        if let Some(job) = local.pop().or_else(|| self.injector.pop()) {
+            return Some(job);
+        }
+
+        let n = self.stealers.len();
+        loop {
+            let mut retry = false;
+            for i in (1..n).map(|k| (index + k) % n) {
+                match self.stealers[i].steal() {
+                    Steal::Success(job) => return Some(job),
+                    Steal::Retry => retry = true,
+                    Steal::Empty => {}
+                }
+            }
+            if !retry {
+                return None;
+            }
+        }
+    }
+
+    fn run(&self, index: usize, local: Worker<Job>) {
+        CURRENT.with(|c| c.set(Some((self as *const _, &local as *const _))));
+
+        loop {
+            if let Some(job) = self.find_job(index, &local) {
+                self.pending.fetch_sub(1, Ordering::AcqRel);
+                // Keep the worker alive for the jobs still queued.
+                if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                    self.panic.lock().unwrap().get_or_insert(payload);
+                }
+                continue;
+            }
+
+            let lock = self.sleep.lock().unwrap();
+            if self.pending.load(Ordering::Acquire) == 0 {
+                if self.shutdown.load(Ordering::Acquire) {
+                    break;
+                }
+                // The timeout only guards against bugs; spawning always notifies.
+                let _ = self.wake.wait_timeout(lock, Duration::from_millis(100));
+            }
+        }
+
+        CURRENT.with(|c| c.set(None));
+    }
+}
+
+/// A fixed set of threads running CPU-bound jobs. Each worker owns a work-stealing deque: jobs
+/// spawned from inside a job go to the local deque, others to a shared injector queue, and idle
+/// workers steal from their siblings.
+///
+/// Dropping the pool runs every job spawned so far, then joins the threads. A panicking job
+/// doesn't take its worker down: the first panic is resumed when the pool is dropped.
+pub struct ThreadPool {
+    shared: Arc<Shared>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl ThreadPool {
+    pub fn new(threads: usize) -> Self {
+        assert!(threads > 0, "ThreadPool needs at least one thread");
+        let (workers, stealers): (Vec<_>, Vec<_>) =
+            (0..threads).map(|_| work_stealing::deque()).unzip();
+        let shared = Arc::new(Shared {
+            injector: Queue::new(),
+            stealers,
+            pending: AtomicUsize::new(0),
+            shutdown: AtomicBool::new(false),
+            sleep: Mutex::new(()),
+            wake: Condvar::new(),
+            panic: Mutex::new(None),
+        });
+
+        let threads = workers
+            .into_iter()
+            .enumerate()
+            .map(|(index, worker)| {
+                let shared = shared.clone();
+                thread::spawn(move || shared.run(index, worker))
+            })
+            .collect();
+
+        Self { shared, threads }
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.threads.len()
+    }
+
+    pub fn spawn<F: FnOnce() + Send + 'static>(&self, job: F) {
+        self.shared.pending.fetch_add(1, Ordering::AcqRel);
+
+        let job: Job = Box::new(job);
+        let shared = Arc::as_ptr(&self.shared);
+        match CURRENT.with(|c| c.get()) {
+            // On one of our own workers, which is alive while it runs the job calling us.
+            Some((current, local)) if current == shared => unsafe { (*local).push(job) },
+            _ => self.shared.injector.push(job),
+        }
+
+        self.shared.notify();
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::Release);
+        {
+            let _lock = self.shared.sleep.lock().unwrap();
+            self.shared.wake.notify_all();
+        }
+
+        // The last handle may be dropped by a job; that worker exits on its own.
+        let current = thread::current().id();
+        for thread in self.threads.drain(..) {
+            if thread.thread().id() != current {
+                thread.join().unwrap();
+            }
+        }
+
+        let payload = self.shared.panic.lock().unwrap().take();
+        if let Some(payload) = payload {
+            if !thread::panicking() {
+                panic::resume_unwind(payload);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        panic::{self, AssertUnwindSafe},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc, Arc,
+        },
+    };
+
+    use super::ThreadPool;
+
+    #[test]
+    fn runs_every_job() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        {
+            let pool = ThreadPool::new(4);
+            for _ in 0..1000 {
+                let counter = counter.clone();
+                pool.spawn(move || {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+        }
+        assert_eq!(counter.load(Ordering::Relaxed), 1000);
+    }
+
+    #[test]
+    fn panicking_jobs_keep_workers_alive() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let pool = ThreadPool::new(2);
+            for i in 0..100 {
+                let counter = counter.clone();
+                pool.spawn(move || {
+                    if i % 10 == 0 {
+                        panic!("job {i}");
+                    }
+                    counter.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+        }));
+        assert_eq!(counter.load(Ordering::Relaxed), 90);
+        let payload = result.unwrap_err();
+        assert!(payload.downcast_ref::<String>().unwrap().starts_with("job "));
+    }
+
+    fn fib(n: u64) -> u64 {
+        if n < 2 {
+            n
+        } else {
+            fib(n - 1) + fib(n - 2)
+        }
+    }
+
+    /// Jobs spawning jobs land on the local deques and get stolen.
+    #[test]
+    fn nested_spawn() {
+        let pool = Arc::new(ThreadPool::new(3));
+        let (tx, rx) = mpsc::channel();
+
+        for i in 0..8 {
+            let (inner_pool, tx) = (pool.clone(), tx.clone());
+            pool.spawn(move || {
+                for j in 0..8 {
+                    let tx = tx.clone();
+                    inner_pool.spawn(move || tx.send((i, j, fib(15))).unwrap());
+                }
+            });
+        }
+        drop(tx);
+
+        let mut got: Vec<_> = rx.iter().collect();
+        got.sort();
+        assert_eq!(got.len(), 64);
+        assert!(got.iter().all(|&(_, _, f)| f == 610));
+    }
+}
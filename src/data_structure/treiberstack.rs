@@ -1,19 +1,135 @@
-use crossbeam::epoch::{self, Atomic, CompareExchangeError, Owned};
-use std::{mem::ManuallyDrop, sync::atomic::Ordering};
+//! A lock-free Treiber stack.
+//!
+//! # Borrowing elements
+//!
+//! `peek` and `iter`, here and on [`Queue`](super::queue::Queue), hand out references to
+//! elements still in the collection, but `pop` moves an element out while other threads may still
+//! be reading it. That is only sound for `Copy` types, whose bytes stay untouched until the node
+//! is reclaimed, so both methods need `T: Copy`. Other elements can only be popped or drained.
+
+use crossbeam::epoch::{self, Guard, Owned, Shared};
+use rand::Rng;
+use std::mem::ManuallyDrop;
+
+use crate::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Atomic, Backoff,
+};
 
 struct Node<T> {
     val: ManuallyDrop<T>,
     next: Atomic<Node<T>>,
 }
 
+impl<T> Node<T> {
+    /// Move the value out. Must happen at most once, by whoever unlinked the node.
+    unsafe fn take(&self) -> T {
+        (&self.val as *const ManuallyDrop<T> as *const T).read()
+    }
+}
+
+/// Side array where a push and a pop colliding on `head` can hand the value over directly.
+///
+/// A pusher offers its node in a random slot and waits a little. A popper claims an offered node
+/// by tagging the slot with 1; the pusher then clears the slot, or withdraws its offer if nobody
+/// came.
+struct EliminationArray<T> {
+    slots: Box<[Atomic<Node<T>>]>,
+}
+
+impl<T> EliminationArray<T> {
+    const PATIENCE: usize = 32;
+
+    fn new(slots: usize) -> Self {
+        Self {
+            slots: (0..slots.max(1)).map(|_| Atomic::null()).collect(),
+        }
+    }
+
+    fn random_slot(&self) -> &Atomic<Node<T>> {
+        &self.slots[rand::thread_rng().gen_range(0..self.slots.len())]
+    }
+
+    /// Returns the node back if no popper took it.
+    fn push(&self, node: Owned<Node<T>>, guard: &Guard) -> Result<(), Owned<Node<T>>> {
+        let slot = self.random_slot();
+        let node = match slot.compare_exchange(
+            Shared::null(),
+            node,
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+            guard,
+        ) {
+            Ok(node) => node,
+            Err(e) => return Err(e.new),
+        };
+
+        let backoff = Backoff::new();
+        for _ in 0..Self::PATIENCE {
+            if slot.load(Ordering::Acquire, guard).tag() == 1 {
+                break;
+            }
+            backoff.snooze();
+        }
+
+        match slot.compare_exchange(
+            node,
+            Shared::null(),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+            guard,
+        ) {
+            // Withdrawn: nobody else can reach the node any more.
+            Ok(_) => Err(unsafe { node.into_owned() }),
+            Err(_) => {
+                // Taken by a popper, which owns the node now.
+                slot.store(Shared::null(), Ordering::Release);
+                Ok(())
+            }
+        }
+    }
+
+    fn pop(&self, guard: &Guard) -> Option<T> {
+        let slot = self.random_slot();
+        let node = slot.load(Ordering::Acquire, guard);
+        if node.is_null() || node.tag() == 1 {
+            return None;
+        }
+
+        slot.compare_exchange(node, node.with_tag(1), Ordering::AcqRel, Ordering::Relaxed, guard)
+            .ok()?;
+        unsafe {
+            let val = node.deref().take();
+            guard.defer_destroy(node);
+            Some(val)
+        }
+    }
+}
+
 pub struct TreiberStack<T> {
     head: Atomic<Node<T>>,
+    elimination: Option<EliminationArray<T>>,
+    len: AtomicUsize,
 }
 
 impl<T> TreiberStack<T> {
+    /// A stack that backs off on contention.
     pub fn new() -> Self {
         Self {
             head: Atomic::null(),
+            elimination: None,
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// A stack where pushes and pops that fail their CAS on `head` try to meet in one of `slots`
+    /// exchange slots instead of retrying right away. A good size is about half the number of
+    /// contending threads.
+    pub fn with_elimination(slots: usize) -> Self {
+        Self {
+            head: Atomic::null(),
+            elimination: Some(EliminationArray::new(slots)),
+            len: AtomicUsize::new(0),
         }
     }
 
@@ -23,7 +139,10 @@ impl<T> TreiberStack<T> {
             next: Atomic::null(),
         });
 
+        // Counted before the value becomes visible, so a pop never brings `len` below zero.
+        self.len.fetch_add(1, Ordering::Relaxed);
         let guard = epoch::pin();
+        let backoff = Backoff::new();
 
         loop {
             let head = self.head.load(Ordering::Relaxed, &guard);
@@ -32,34 +151,107 @@ impl<T> TreiberStack<T> {
                 Ok(_) => return,
                 Err(n) => owned = n.new,
             }
+
+            match &self.elimination {
+                Some(elimination) => match elimination.push(owned, &guard) {
+                    Ok(()) => return,
+                    Err(n) => owned = n,
+                },
+                None => backoff.spin(),
+            }
         }
     }
 
     pub fn pop(&self) -> Option<T> {
         let guard = epoch::pin();
+        let backoff = Backoff::new();
         loop {
             let head = self.head.load(Ordering::Acquire, &guard);
 
-            match unsafe { head.as_ref() } {
-                Some(h) => {
-                    let next = h.next.load(Ordering::Relaxed, &guard);
-
-                    if self
-                        .head
-                        .compare_exchange(head, next, Ordering::Release, Ordering::Relaxed, &guard)
-                        .is_ok()
-                    {
-                        let val = unsafe { (&h.val as *const ManuallyDrop<T> as *const T).read() };
-                        unsafe {
-                            guard.defer_destroy(head);
-                        }
+            let h = unsafe { head.as_ref() }?;
+            let next = h.next.load(Ordering::Relaxed, &guard);
+
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::Release, Ordering::Relaxed, &guard)
+                .is_ok()
+            {
+                let val = unsafe { h.take() };
+                unsafe {
+                    guard.defer_destroy(head);
+                }
+                self.len.fetch_sub(1, Ordering::Relaxed);
+                return Some(val);
+            }
+
+            match &self.elimination {
+                Some(elimination) => {
+                    if let Some(val) = elimination.pop(&guard) {
+                        self.len.fetch_sub(1, Ordering::Relaxed);
                         return Some(val);
                     }
                 }
-                None => return None,
+                None => backoff.spin(),
             }
         }
     }
+
+    /// Number of elements. Only exact when no other thread is modifying the stack.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire, &epoch::pin()).is_null()
+    }
+
+    /// Pop until the stack is empty. Values pushed meanwhile are yielded as well.
+    pub fn drain(&self) -> Drain<'_, T> {
+        Drain { stack: self }
+    }
+}
+
+/// Only for `T: Copy`, see [borrowing elements](self#borrowing-elements).
+impl<T: Copy> TreiberStack<T> {
+    pub fn peek<'g>(&self, guard: &'g Guard) -> Option<&'g T> {
+        self.iter(guard).next()
+    }
+
+    /// Iterate from the top without popping. References stay valid while `guard` is pinned.
+    pub fn iter<'g>(&self, guard: &'g Guard) -> Iter<'g, T> {
+        Iter {
+            curr: self.head.load(Ordering::Acquire, guard),
+            guard,
+        }
+    }
+}
+
+pub struct Iter<'g, T> {
+    curr: Shared<'g, Node<T>>,
+    guard: &'g Guard,
+}
+
+impl<'g, T: Copy> Iterator for Iter<'g, T> {
+    type Item = &'g T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // A node below one that was reachable after pinning can't have been reclaimed yet.
+        let node = unsafe { self.curr.as_ref() }?;
+        self.curr = node.next.load(Ordering::Acquire, self.guard);
+        Some(&node.val)
+    }
+}
+
+pub struct Drain<'a, T> {
+    stack: &'a TreiberStack<T>,
+}
+
+impl<T> Iterator for Drain<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.stack.pop()
+    }
 }
 
 impl<T> Default for TreiberStack<T> {
@@ -78,10 +270,11 @@ impl<T> Iterator for TreiberStack<T> {
         unsafe { top.as_ref() }.map(|top_ptr| {
             let next = top_ptr.next.load(Ordering::Relaxed, &guard);
             self.head.store(next, Ordering::Relaxed);
-            let res = unsafe { (&top_ptr.val as *const ManuallyDrop<T> as *const T).read() };
+            let res = unsafe { top_ptr.take() };
             unsafe {
                 guard.defer_destroy(top);
             }
+            self.len.fetch_sub(1, Ordering::Relaxed);
             res
         })
     }
@@ -95,7 +288,12 @@ impl<T> Drop for TreiberStack<T> {
 
 #[cfg(test)]
 mod test {
+    use std::{sync::Arc, thread};
+
+    use crossbeam::epoch;
+
     use super::TreiberStack;
+    use crate::utils::linearizability::{check, CollectionOp::*, Recorder, StackModel};
 
     #[test]
     fn push_and_pop() {
@@ -104,6 +302,27 @@ mod test {
         assert_eq!(stack.pop(), Some(1));
     }
 
+    #[test]
+    fn iter_and_peek() {
+        let stack = TreiberStack::new();
+        assert!(stack.is_empty());
+        for i in 0..4 {
+            stack.push(i);
+        }
+
+        let guard = epoch::pin();
+        assert_eq!(stack.peek(&guard), Some(&3));
+        assert_eq!(stack.iter(&guard).copied().collect::<Vec<_>>(), [3, 2, 1, 0]);
+        assert_eq!(stack.len(), 4);
+
+        let top = stack.peek(&guard).unwrap();
+        assert_eq!(stack.pop(), Some(3));
+        assert_eq!(*top, 3);
+        assert_eq!(stack.drain().collect::<Vec<_>>(), [2, 1, 0]);
+        assert!(stack.is_empty());
+        assert_eq!(stack.len(), 0);
+    }
+
     #[test]
     fn is_sync() {
         fn test_sync(_: impl Sync) {}
@@ -117,4 +336,100 @@ mod test {
         let stack = TreiberStack::<i32>::new();
         test_send(stack);
     }
+
+    #[test]
+    fn elimination_keeps_every_value() {
+        const THREADS: usize = 8;
+        const PER_THREAD: usize = 10_000;
+        let stack = Arc::new(TreiberStack::with_elimination(4));
+
+        let handles: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let stack = stack.clone();
+                thread::spawn(move || {
+                    let mut popped = vec![];
+                    for i in 0..PER_THREAD {
+                        stack.push(t * PER_THREAD + i);
+                        if i % 2 == 1 {
+                            popped.extend(stack.pop());
+                            popped.extend(stack.pop());
+                        }
+                    }
+                    popped
+                })
+            })
+            .collect();
+
+        let mut all: Vec<_> = handles.into_iter().flat_map(|h| h.join().unwrap()).collect();
+        all.extend(std::iter::from_fn(|| stack.pop()));
+        all.sort();
+        assert_eq!(all, (0..THREADS * PER_THREAD).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn histories_are_linearizable() {
+        let stacks: [fn() -> TreiberStack<usize>; 2] =
+            [TreiberStack::new, || TreiberStack::with_elimination(2)];
+        for new_stack in stacks {
+            for _ in 0..200 {
+                let stack = new_stack();
+                let recorder = Recorder::new();
+                thread::scope(|s| {
+                    for t in 0..3 {
+                        let (stack, recorder) = (&stack, &recorder);
+                        s.spawn(move || {
+                            for i in 0..3 {
+                                let val = t * 3 + i;
+                                recorder.record(Push(val), || {
+                                    stack.push(val);
+                                    None
+                                });
+                                recorder.record(Pop, || stack.pop());
+                            }
+                        });
+                    }
+                });
+
+                let history = recorder.take();
+                assert!(check(StackModel::default(), &history).is_some(), "{history:?}");
+            }
+        }
+    }
+
+    /// Run with `RUSTFLAGS="--cfg loom" cargo test --release --lib loom`.
+    #[cfg(loom)]
+    mod loom_model {
+        use loom::{sync::Arc, thread};
+
+        use super::super::TreiberStack;
+        use crate::utils::linearizability::{check, CollectionOp::*, Recorder, StackModel};
+
+        #[test]
+        fn push_pop() {
+            crate::sync::model(|| {
+                let stack = Arc::new(TreiberStack::new());
+                let recorder = Arc::new(Recorder::new());
+
+                let handles: Vec<_> = (0..2)
+                    .map(|t| {
+                        let (stack, recorder) = (stack.clone(), recorder.clone());
+                        thread::spawn(move || {
+                            recorder.record(Push(t), || {
+                                stack.push(t);
+                                None
+                            });
+                            recorder.record(Pop, || stack.pop());
+                        })
+                    })
+                    .collect();
+                for handle in handles {
+                    handle.join().unwrap();
+                }
+
+                let history = recorder.take();
+                assert!(check(StackModel::default(), &history).is_some(), "{history:?}");
+                assert_eq!(stack.len(), 0);
+            });
+        }
+    }
 }
@@ -1,10 +1,26 @@
 mod ac;
+mod aho_corasick;
+mod dawg;
+mod double_array;
+mod flat;
+mod radix;
+/// Fail links are computed while inserting and point into sibling `Box`es, so only tries built by
+/// [`TrieImpl::transform`] have complete links. Prefer [`AhoCorasickBuilder`] for an automaton that
+/// is sound to move and share.
 pub type ACAutomata<S> = TrieImpl<ac::FailTo<S>, S>;
+pub use aho_corasick::{AcNode, AhoCorasick, AhoCorasickBuilder, Match};
+pub use dawg::Dawg;
+pub use double_array::DoubleArrayTrie;
+pub use flat::{FlatNode, FlatTrie, FlatTrieError, FlatValue};
+pub use radix::{RadixNode, RadixTrie};
 
 use std::collections::HashMap;
 use std::collections::VecDeque;
 
 pub trait TrieWalk<S>: Sized {
+    /// Whether `walk` falls back along fail links instead of stopping at a missing edge.
+    const HAS_FAIL_LINKS: bool = false;
+
     fn root() -> Self;
     fn build(parent_node: &TrieImpl<Self, S>, c: char) -> Self;
     fn walk(node: &TrieImpl<Self, S>, c: char) -> Option<&TrieImpl<Self, S>>;
@@ -62,6 +78,27 @@ impl<T: TrieWalk<S>, S> TrieImpl<T, S> {
         Box::new(Self::new_root())
     }
 
+    /// Consume the trie, returning every key with its info in lexicographic order.
+    pub fn into_sorted_entries(self) -> Vec<(String, S)> {
+        fn collect<T, S>(node: TrieImpl<T, S>, key: &mut String, res: &mut Vec<(String, S)>) {
+            if let Some(info) = node.attached_info {
+                res.push((key.clone(), info));
+            }
+
+            let mut next: Vec<_> = node.next.into_iter().collect();
+            next.sort_by_key(|(c, _)| *c);
+            for (c, n) in next {
+                key.push(c);
+                collect(*n, key, res);
+                key.pop();
+            }
+        }
+
+        let mut res = vec![];
+        collect(self, &mut String::new(), &mut res);
+        res
+    }
+
     pub fn transform<T2: TrieWalk<S>>(self) -> Box<TrieImpl<T2, S>> {
         let mut q = VecDeque::new();
         let mut new_root = Box::new(TrieImpl {
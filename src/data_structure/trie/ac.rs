@@ -7,6 +7,8 @@ pub struct FailTo<S> {
 }
 
 impl<S> TrieWalk<S> for FailTo<S> {
+    const HAS_FAIL_LINKS: bool = true;
+
     fn root() -> Self {
         Self { p: None }
     }
@@ -0,0 +1,257 @@
+use std::collections::{HashMap, VecDeque};
+
+use super::{TrieImpl, TrieWalk};
+
+/// Collects patterns, then computes all fail links at once in [`AhoCorasickBuilder::build`].
+pub struct AhoCorasickBuilder<S> {
+    nodes: Vec<AcNode<S>>,
+}
+
+/// An immutable Aho–Corasick automaton. Links are indices into one `Vec`, so it can be freely
+/// moved, cloned and shared between threads.
+#[derive(Clone)]
+pub struct AhoCorasick<S> {
+    nodes: Vec<AcNode<S>>,
+}
+
+#[derive(Clone)]
+pub struct AcNode<S> {
+    next: HashMap<char, usize>,
+    fail: usize,
+    /// The nearest node along the fail chain which carries info.
+    output: Option<usize>,
+    depth: usize,
+    pub attached_info: Option<S>,
+}
+
+impl<S> AcNode<S> {
+    fn new(depth: usize) -> Self {
+        Self {
+            next: HashMap::new(),
+            fail: 0,
+            output: None,
+            depth,
+            attached_info: None,
+        }
+    }
+
+    /// Length in chars of the string leading to this node.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
+/// A pattern occurring at chars `start..end` of the input.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Match<'a, S> {
+    pub start: usize,
+    pub end: usize,
+    pub info: &'a S,
+}
+
+impl<S> AhoCorasickBuilder<S> {
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![AcNode::new(0)],
+        }
+    }
+
+    /// Add a pattern, replacing the info of an equal pattern added before.
+    pub fn add(&mut self, pattern: impl IntoIterator<Item = char>, info: S) -> &mut Self {
+        let mut cur = 0;
+        for c in pattern {
+            cur = match self.nodes[cur].next.get(&c) {
+                Some(&n) => n,
+                None => {
+                    let n = self.nodes.len();
+                    self.nodes.push(AcNode::new(self.nodes[cur].depth + 1));
+                    self.nodes[cur].next.insert(c, n);
+                    n
+                }
+            };
+        }
+
+        self.nodes[cur].attached_info = Some(info);
+        self
+    }
+
+    pub fn build(self) -> AhoCorasick<S> {
+        let mut nodes = self.nodes;
+        let mut q = VecDeque::from([0]);
+
+        while let Some(u) = q.pop_front() {
+            let next: Vec<_> = nodes[u].next.iter().map(|(c, v)| (*c, *v)).collect();
+            for (c, v) in next {
+                let fail = if u == 0 {
+                    0
+                } else {
+                    let mut f = nodes[u].fail;
+                    loop {
+                        if let Some(&n) = nodes[f].next.get(&c) {
+                            break n;
+                        }
+                        if f == 0 {
+                            break 0;
+                        }
+                        f = nodes[f].fail;
+                    }
+                };
+
+                nodes[v].fail = fail;
+                nodes[v].output = if nodes[fail].attached_info.is_some() {
+                    Some(fail)
+                } else {
+                    nodes[fail].output
+                };
+                q.push_back(v);
+            }
+        }
+
+        AhoCorasick { nodes }
+    }
+}
+
+impl<S> Default for AhoCorasickBuilder<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> AhoCorasick<S> {
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn root(&self) -> &AcNode<S> {
+        &self.nodes[0]
+    }
+
+    fn step(&self, mut state: usize, c: char) -> usize {
+        loop {
+            if let Some(&n) = self.nodes[state].next.get(&c) {
+                break n;
+            }
+            if state == 0 {
+                break 0;
+            }
+            state = self.nodes[state].fail;
+        }
+    }
+
+    /// The node reached after each char, falling back along fail links like `ACAutomata::walk`.
+    pub fn walk<'a>(
+        &'a self,
+        s: impl IntoIterator<Item = char> + 'a,
+    ) -> impl Iterator<Item = &'a AcNode<S>> + 'a {
+        s.into_iter().scan(0, move |state, c| {
+            *state = self.step(*state, c);
+            Some(&self.nodes[*state])
+        })
+    }
+
+    /// All, possibly overlapping, matches ordered by their end.
+    pub fn find_iter<'a>(
+        &'a self,
+        s: impl IntoIterator<Item = char> + 'a,
+    ) -> impl Iterator<Item = Match<'a, S>> + 'a {
+        let mut state = 0;
+        s.into_iter().enumerate().flat_map(move |(i, c)| {
+            state = self.step(state, c);
+            let first = match self.nodes[state].attached_info {
+                Some(_) => Some(state),
+                None => self.nodes[state].output,
+            };
+
+            std::iter::successors(first, |&n| self.nodes[n].output).map(move |n| {
+                let node = &self.nodes[n];
+                Match {
+                    start: i + 1 - node.depth,
+                    end: i + 1,
+                    info: node.attached_info.as_ref().unwrap(),
+                }
+            })
+        })
+    }
+}
+
+impl<T: TrieWalk<S>, S> From<TrieImpl<T, S>> for AhoCorasick<S> {
+    fn from(trie: TrieImpl<T, S>) -> Self {
+        let mut builder = AhoCorasickBuilder::new();
+        for (k, v) in trie.into_sorted_entries() {
+            builder.add(k.chars(), v);
+        }
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AhoCorasick, AhoCorasickBuilder};
+    use crate::data_structure::trie::{ACAutomata, Trie};
+
+    fn build(dict: &[&str]) -> AhoCorasick<usize> {
+        let mut builder = AhoCorasickBuilder::new();
+        for (i, w) in dict.iter().enumerate() {
+            builder.add(w.chars(), i);
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn finds_overlapping_matches() {
+        let ac = build(&["he", "she", "his", "hers"]);
+        let found: Vec<_> = ac
+            .find_iter("ushers".chars())
+            .map(|m| (m.start, m.end, *m.info))
+            .collect();
+        assert_eq!(found, [(1, 4, 1), (2, 4, 0), (2, 6, 3)]);
+    }
+
+    #[test]
+    fn walk_matches_transformed_automaton() {
+        let dict = ["abc", "aaaaa", "bcdef", "cd"];
+        let sentence = "aabcdabcdefaaaaaa";
+        let ac = build(&dict);
+
+        let mut trie = Trie::new_root();
+        for (i, w) in dict.iter().enumerate() {
+            trie.insert(w.chars(), i);
+        }
+        let old: Box<ACAutomata<usize>> = trie.transform();
+
+        let expected: Vec<_> = old.walk(sentence.chars()).map(|n| n.attached_info).collect();
+        let found: Vec<_> = ac.walk(sentence.chars()).map(|n| n.attached_info).collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn can_be_moved_and_shared() {
+        fn assert_send_sync(_: &(impl Send + Sync)) {}
+
+        let ac = build(&["ab", "b"]);
+        let moved = vec![ac.clone(), ac];
+        assert_send_sync(&moved);
+
+        let ac = std::sync::Arc::new(moved.into_iter().next().unwrap());
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let ac = ac.clone();
+                std::thread::spawn(move || ac.find_iter("abab".chars()).count())
+            })
+            .collect();
+        for h in handles {
+            assert_eq!(h.join().unwrap(), 4);
+        }
+    }
+
+    #[test]
+    fn from_trie() {
+        let mut trie = Trie::new_root();
+        trie.insert("ab".chars(), 1);
+        trie.insert("b".chars(), 2);
+
+        let ac = AhoCorasick::from(trie);
+        let found: Vec<_> = ac.find_iter("ab".chars()).map(|m| *m.info).collect();
+        assert_eq!(found, [1, 2]);
+    }
+}
@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+
+use super::{TrieImpl, TrieWalk};
+
+struct State {
+    /// Sorted by char.
+    edges: Vec<(char, u32)>,
+    is_final: bool,
+    /// Number of keys accepted from this state, used to rank keys.
+    count: u32,
+}
+
+impl State {
+    fn new() -> Self {
+        Self {
+            edges: vec![],
+            is_final: false,
+            count: 0,
+        }
+    }
+}
+
+/// A minimal deterministic acyclic automaton (DAFSA / DAWG) sharing both prefixes and suffixes.
+///
+/// Since equal suffixes are merged, infos can not live on states. Instead every key is ranked by
+/// its lexicographic position while walking, which indexes into `values`.
+pub struct Dawg<S> {
+    states: Vec<State>,
+    values: Vec<S>,
+}
+
+type Signature = (bool, Vec<(char, u32)>);
+
+struct Builder {
+    states: Vec<State>,
+    register: HashMap<Signature, u32>,
+    /// Path of the previous key whose states are not registered yet, as `(parent, c, child)`.
+    unchecked: Vec<(u32, char, u32)>,
+    prev: Option<Vec<char>>,
+}
+
+impl Builder {
+    fn new() -> Self {
+        Self {
+            states: vec![State::new()],
+            register: HashMap::new(),
+            unchecked: vec![],
+            prev: None,
+        }
+    }
+
+    fn insert(&mut self, key: &str) {
+        let key: Vec<char> = key.chars().collect();
+        let prev = self.prev.take().unwrap_or_default();
+        assert!(
+            self.states.len() == 1 && !self.states[0].is_final || key > prev,
+            "Keys must be inserted in strictly increasing order"
+        );
+
+        let common = key.iter().zip(&prev).take_while(|(a, b)| a == b).count();
+        self.minimize(common);
+
+        let mut node = self.unchecked.last().map_or(0, |(_, _, child)| *child);
+        for &c in &key[common..] {
+            let next = self.states.len() as u32;
+            self.states.push(State::new());
+            self.states[node as usize].edges.push((c, next));
+            self.unchecked.push((node, c, next));
+            node = next;
+        }
+
+        self.states[node as usize].is_final = true;
+        self.prev = Some(key);
+    }
+
+    /// Replace unchecked states deeper than `down_to` with equivalent registered ones.
+    fn minimize(&mut self, down_to: usize) {
+        while self.unchecked.len() > down_to {
+            let (parent, _, child) = self.unchecked.pop().unwrap();
+            let state = &self.states[child as usize];
+            let signature = (state.is_final, state.edges.clone());
+
+            match self.register.get(&signature) {
+                Some(&existing) => {
+                    self.states[parent as usize].edges.last_mut().unwrap().1 = existing;
+                }
+                None => {
+                    self.register.insert(signature, child);
+                }
+            }
+        }
+    }
+
+    /// Drop replaced states, renumbering the reachable ones from the root.
+    fn finish(mut self) -> Vec<State> {
+        self.minimize(0);
+
+        let mut ids = vec![u32::MAX; self.states.len()];
+        let mut order = vec![0];
+        ids[0] = 0;
+        let mut i = 0;
+        while i < order.len() {
+            for &(_, n) in &self.states[order[i] as usize].edges {
+                if ids[n as usize] == u32::MAX {
+                    ids[n as usize] = order.len() as u32;
+                    order.push(n);
+                }
+            }
+            i += 1;
+        }
+
+        let mut states: Vec<State> = order
+            .iter()
+            .map(|&old| {
+                let old = &self.states[old as usize];
+                State {
+                    edges: old
+                        .edges
+                        .iter()
+                        .map(|&(c, n)| (c, ids[n as usize]))
+                        .collect(),
+                    is_final: old.is_final,
+                    count: 0,
+                }
+            })
+            .collect();
+
+        fn count(states: &mut [State], id: u32, done: &mut [bool]) -> u32 {
+            if !done[id as usize] {
+                let edges = std::mem::take(&mut states[id as usize].edges);
+                let mut res = states[id as usize].is_final as u32;
+                for &(_, n) in &edges {
+                    res += count(states, n, done);
+                }
+                let state = &mut states[id as usize];
+                state.edges = edges;
+                state.count = res;
+                done[id as usize] = true;
+            }
+            states[id as usize].count
+        }
+
+        let mut done = vec![false; states.len()];
+        count(&mut states, 0, &mut done);
+        states
+    }
+}
+
+impl<S> Dawg<S> {
+    /// Build from `(key, info)` pairs sorted by key in strictly increasing order.
+    ///
+    /// # Panics
+    /// If the keys are not strictly increasing.
+    pub fn from_sorted<K: AsRef<str>>(entries: impl IntoIterator<Item = (K, S)>) -> Self {
+        let mut builder = Builder::new();
+        let mut values = vec![];
+        for (k, v) in entries {
+            builder.insert(k.as_ref());
+            values.push(v);
+        }
+
+        Self {
+            states: builder.finish(),
+            values,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.states.iter().map(|s| s.edges.len()).sum()
+    }
+
+    /// Follow `c` from `state`, adding the number of keys ordered before the new state to `rank`.
+    fn step(&self, state: u32, c: char, rank: &mut u32) -> Option<u32> {
+        let state = &self.states[state as usize];
+        *rank += state.is_final as u32;
+        for &(e, n) in &state.edges {
+            if e == c {
+                return Some(n);
+            }
+            if e > c {
+                break;
+            }
+            *rank += self.states[n as usize].count;
+        }
+        None
+    }
+
+    fn find(&self, key: &str) -> Option<(u32, u32)> {
+        let mut rank = 0;
+        let mut state = 0;
+        for c in key.chars() {
+            state = self.step(state, c, &mut rank)?;
+        }
+        Some((state, rank))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&S> {
+        let (state, rank) = self.find(key)?;
+        self.states[state as usize]
+            .is_final
+            .then(|| &self.values[rank as usize])
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// All keys that are prefixes of `text`, as `(byte length, info)` from shortest to longest.
+    pub fn common_prefix_search<'a>(&'a self, text: &str) -> Vec<(usize, &'a S)> {
+        let mut res = vec![];
+        let (mut state, mut rank) = (0, 0);
+
+        for (i, c) in text
+            .char_indices()
+            .map(|(i, c)| (i, Some(c)))
+            .chain(std::iter::once((text.len(), None)))
+        {
+            if self.states[state as usize].is_final {
+                res.push((i, &self.values[rank as usize]));
+            }
+
+            match c.and_then(|c| self.step(state, c, &mut rank)) {
+                Some(n) => state = n,
+                None => break,
+            }
+        }
+
+        res
+    }
+
+    /// All keys starting with `prefix`, in lexicographic order.
+    pub fn predictive_search<'a>(&'a self, prefix: &str) -> Vec<(String, &'a S)> {
+        fn collect<'a, S>(
+            dawg: &'a Dawg<S>,
+            state: u32,
+            key: &mut String,
+            rank: &mut u32,
+            res: &mut Vec<(String, &'a S)>,
+        ) {
+            let s = &dawg.states[state as usize];
+            if s.is_final {
+                res.push((key.clone(), &dawg.values[*rank as usize]));
+                *rank += 1;
+            }
+
+            for &(c, n) in &s.edges {
+                key.push(c);
+                collect(dawg, n, key, rank, res);
+                key.pop();
+            }
+        }
+
+        let mut res = vec![];
+        if let Some((state, mut rank)) = self.find(prefix) {
+            collect(self, state, &mut prefix.to_string(), &mut rank, &mut res);
+        }
+        res
+    }
+}
+
+impl<T: TrieWalk<S>, S> From<TrieImpl<T, S>> for Dawg<S> {
+    fn from(trie: TrieImpl<T, S>) -> Self {
+        Self::from_sorted(trie.into_sorted_entries())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Dawg;
+    use crate::data_structure::trie::Trie;
+
+    #[test]
+    fn shares_suffixes() {
+        let words = ["tap", "taps", "top", "tops"];
+        let dawg = Dawg::from_sorted(words.iter().enumerate().map(|(i, w)| (w, i)));
+
+        // t -> {a, o} -> p -> (final) s -> (final)
+        assert_eq!(dawg.state_count(), 5);
+        for (i, w) in words.iter().enumerate() {
+            assert_eq!(dawg.get(w), Some(&i));
+        }
+        assert!(!dawg.contains("ta"));
+        assert!(!dawg.contains("tapss"));
+    }
+
+    #[test]
+    fn prefix_searches() {
+        let dawg = Dawg::from_sorted([("t", 5), ("tea", 2), ("ted", 3), ("ten", 4), ("to", 1)]);
+
+        assert_eq!(dawg.common_prefix_search("tens"), [(1, &5), (3, &4)]);
+        let found: Vec<_> = dawg
+            .predictive_search("te")
+            .into_iter()
+            .map(|(k, v)| (k, *v))
+            .collect();
+        assert_eq!(
+            found,
+            [("tea".into(), 2), ("ted".into(), 3), ("ten".into(), 4)]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn unsorted_keys() {
+        Dawg::from_sorted([("b", ()), ("a", ())]);
+    }
+
+    #[test]
+    fn from_trie() {
+        let mut trie = Trie::new_root();
+        for (i, w) in ["walked", "talked", "walk", "talk", ""].iter().enumerate() {
+            trie.insert(w.chars(), i);
+        }
+
+        let dawg = Dawg::from(trie);
+        assert_eq!(dawg.len(), 5);
+        assert_eq!(dawg.get("talked"), Some(&1));
+        assert_eq!(dawg.get(""), Some(&4));
+        assert_eq!(dawg.get("wal"), None);
+    }
+}
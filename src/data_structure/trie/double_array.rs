@@ -0,0 +1,281 @@
+use super::{TrieImpl, TrieWalk};
+
+const NONE: u32 = u32::MAX;
+
+/// Key bytes with the index of their info.
+type Key = (Vec<u8>, u32);
+
+/// A static trie over the UTF-8 bytes of its keys, stored as the classic `base`/`check` pair.
+///
+/// The child of node `s` by byte `b` lives at `t = base[s] + b + 1` iff `check[t] == s`. The
+/// slot `base[s] + 0` is reserved for a terminal transition whose `base` is an index into
+/// `values`.
+pub struct DoubleArrayTrie<S> {
+    base: Vec<u32>,
+    check: Vec<u32>,
+    values: Vec<S>,
+}
+
+impl<S> DoubleArrayTrie<S> {
+    /// Build from `(key, info)` pairs in any order. For duplicated keys the last info wins.
+    pub fn build<K: AsRef<str>>(entries: impl IntoIterator<Item = (K, S)>) -> Self {
+        let mut entries: Vec<_> = entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, (k, v))| (k.as_ref().as_bytes().to_vec(), i, v))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+        entries.dedup_by(|a, b| a.0 == b.0);
+
+        let (keys, values): (Vec<_>, Vec<_>) = entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, (k, _, v))| ((k, i as u32), v))
+            .unzip();
+
+        let mut builder = Builder {
+            base: vec![0],
+            check: vec![NONE],
+            first_free: 1,
+        };
+        if !keys.is_empty() {
+            builder.place(0, &keys, 0);
+        }
+
+        let mut res = Self {
+            base: builder.base,
+            check: builder.check,
+            values,
+        };
+        res.shrink();
+        res
+    }
+
+    fn shrink(&mut self) {
+        while self.check.len() > 1 && self.check.last() == Some(&NONE) {
+            self.check.pop();
+            self.base.pop();
+        }
+        self.base.shrink_to_fit();
+        self.check.shrink_to_fit();
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Number of slots in each of the two arrays.
+    pub fn array_len(&self) -> usize {
+        self.base.len()
+    }
+
+    fn child(&self, node: u32, code: usize) -> Option<u32> {
+        let t = self.base[node as usize] as usize + code;
+        (t < self.check.len() && self.check[t] == node).then_some(t as u32)
+    }
+
+    fn value(&self, node: u32) -> Option<&S> {
+        self.child(node, 0)
+            .map(|t| &self.values[self.base[t as usize] as usize])
+    }
+
+    fn find(&self, key: &[u8]) -> Option<u32> {
+        key.iter()
+            .try_fold(0, |node, b| self.child(node, *b as usize + 1))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&S> {
+        self.value(self.find(key.as_bytes())?)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// All keys that are prefixes of `text`, as `(byte length, info)` from shortest to longest.
+    pub fn common_prefix_search<'a>(&'a self, text: &str) -> Vec<(usize, &'a S)> {
+        let mut res = vec![];
+        let mut node = 0;
+
+        for (i, b) in std::iter::once(None)
+            .chain(text.bytes().map(Some))
+            .enumerate()
+        {
+            if let Some(b) = b {
+                match self.child(node, b as usize + 1) {
+                    Some(n) => node = n,
+                    None => break,
+                }
+            }
+
+            if let Some(v) = self.value(node) {
+                res.push((i, v));
+            }
+        }
+
+        res
+    }
+
+    /// All keys starting with `prefix`, in lexicographic order.
+    pub fn predictive_search<'a>(&'a self, prefix: &str) -> Vec<(String, &'a S)> {
+        fn collect<'a, S>(
+            trie: &'a DoubleArrayTrie<S>,
+            node: u32,
+            key: &mut Vec<u8>,
+            res: &mut Vec<(String, &'a S)>,
+        ) {
+            if let Some(v) = trie.value(node) {
+                res.push((String::from_utf8(key.clone()).unwrap(), v));
+            }
+
+            for b in 0..=u8::MAX {
+                if let Some(n) = trie.child(node, b as usize + 1) {
+                    key.push(b);
+                    collect(trie, n, key, res);
+                    key.pop();
+                }
+            }
+        }
+
+        let mut res = vec![];
+        if let Some(node) = self.find(prefix.as_bytes()) {
+            collect(self, node, &mut prefix.as_bytes().to_vec(), &mut res);
+        }
+        res
+    }
+}
+
+struct Builder {
+    base: Vec<u32>,
+    check: Vec<u32>,
+    /// No free slot below this index.
+    first_free: usize,
+}
+
+impl Builder {
+    fn is_free(&self, t: usize) -> bool {
+        t >= self.check.len() || self.check[t] == NONE
+    }
+
+    fn reserve(&mut self, t: usize, parent: u32) {
+        if t >= self.check.len() {
+            self.check.resize(t + 1, NONE);
+            self.base.resize(t + 1, 0);
+        }
+        self.check[t] = parent;
+
+        while !self.is_free(self.first_free) {
+            self.first_free += 1;
+        }
+    }
+
+    /// `keys` are sorted and share their first `depth` bytes, which lead to `node`.
+    fn place(&mut self, node: u32, keys: &[Key], depth: usize) {
+        let code = |k: &[u8]| k.get(depth).map_or(0, |b| *b as usize + 1);
+
+        let mut groups: Vec<(usize, &[Key])> = vec![];
+        let mut start = 0;
+        for i in 1..=keys.len() {
+            if i == keys.len() || code(&keys[i].0) != code(&keys[start].0) {
+                groups.push((code(&keys[start].0), &keys[start..i]));
+                start = i;
+            }
+        }
+
+        let first = groups[0].0;
+        let mut base = self.first_free.saturating_sub(first).max(1);
+        while !groups.iter().all(|(c, _)| self.is_free(base + c)) {
+            base += 1;
+        }
+
+        self.base[node as usize] = base as u32;
+        for (c, _) in &groups {
+            self.reserve(base + c, node);
+        }
+
+        for (c, group) in groups {
+            let t = base + c;
+            if c == 0 {
+                self.base[t] = group[0].1;
+            } else {
+                self.place(t as u32, group, depth + 1);
+            }
+        }
+    }
+}
+
+impl<T: TrieWalk<S>, S> From<TrieImpl<T, S>> for DoubleArrayTrie<S> {
+    fn from(trie: TrieImpl<T, S>) -> Self {
+        Self::build(trie.into_sorted_entries())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DoubleArrayTrie;
+    use crate::data_structure::trie::Trie;
+
+    #[test]
+    fn get_and_contains() {
+        let words = ["a", "ab", "abc", "b", "bcd", "日本", "日本語"];
+        let trie = DoubleArrayTrie::build(words.iter().enumerate().map(|(i, w)| (w, i)));
+
+        assert_eq!(trie.len(), words.len());
+        for (i, w) in words.iter().enumerate() {
+            assert_eq!(trie.get(w), Some(&i));
+        }
+        assert!(!trie.contains(""));
+        assert!(!trie.contains("bc"));
+        assert!(!trie.contains("abcd"));
+        assert!(!trie.contains("日"));
+    }
+
+    #[test]
+    fn duplicated_keys_keep_the_last() {
+        let trie = DoubleArrayTrie::build([("k", 1), ("j", 2), ("k", 3)]);
+        assert_eq!(trie.len(), 2);
+        assert_eq!(trie.get("k"), Some(&3));
+    }
+
+    #[test]
+    fn prefix_searches() {
+        let trie = DoubleArrayTrie::build([("to", 1), ("tea", 2), ("ted", 3), ("ten", 4), ("t", 5)]);
+
+        assert_eq!(trie.common_prefix_search("tens"), [(1, &5), (3, &4)]);
+        let found: Vec<_> = trie
+            .predictive_search("te")
+            .into_iter()
+            .map(|(k, v)| (k, *v))
+            .collect();
+        assert_eq!(
+            found,
+            [("tea".into(), 2), ("ted".into(), 3), ("ten".into(), 4)]
+        );
+        assert!(trie.predictive_search("x").is_empty());
+    }
+
+    #[test]
+    fn from_trie() {
+        let mut trie = Trie::new_root();
+        for (i, w) in ["inn", "in", "tea", "ten", "to"].iter().enumerate() {
+            trie.insert(w.chars(), i);
+        }
+
+        let da = DoubleArrayTrie::from(trie);
+        assert_eq!(da.len(), 5);
+        assert_eq!(da.get("ten"), Some(&3));
+        assert_eq!(da.get("te"), None);
+    }
+
+    #[test]
+    fn empty() {
+        let trie = DoubleArrayTrie::<()>::build(Vec::<(&str, ())>::new());
+        assert!(trie.is_empty());
+        assert_eq!(trie.get("a"), None);
+        assert!(trie.predictive_search("").is_empty());
+    }
+}
@@ -0,0 +1,521 @@
+//! A flat, position-independent encoding of a built trie.
+//!
+//! All integers are little-endian and all links are node indices, so the bytes can be written to
+//! disk and later queried in place (e.g. from a memory-mapped file) through [`FlatTrie`].
+//!
+//! ```text
+//! header: magic "PTRI" | version u16 | flags u16 | nodes u32 | edges u32 | value size u32 | 0u32
+//! node:   first edge u32 | edge count u32 | fail u32 | has value u32 | value bytes
+//! edge:   char u32 | target u32           (sorted by char within a node)
+//! ```
+
+use std::{collections::VecDeque, fmt::Display, io, marker::PhantomData};
+
+use super::{TrieImpl, TrieWalk};
+
+const MAGIC: &[u8; 4] = b"PTRI";
+const VERSION: u16 = 1;
+const FLAG_FAIL_LINKS: u16 = 1;
+const HEADER_SIZE: usize = 24;
+const NODE_HEADER_SIZE: usize = 16;
+const EDGE_SIZE: usize = 8;
+const NO_NODE: u32 = u32::MAX;
+
+/// Attached info with a fixed-size binary representation.
+pub trait FlatValue: Sized {
+    const SIZE: usize;
+
+    fn write_to(&self, out: &mut Vec<u8>);
+    fn read_from(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_flat_value {
+    ($($t:ty),*) => {
+        $(
+            impl FlatValue for $t {
+                const SIZE: usize = std::mem::size_of::<$t>();
+
+                fn write_to(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn read_from(bytes: &[u8]) -> Self {
+                    Self::from_le_bytes(bytes[..Self::SIZE].try_into().unwrap())
+                }
+            }
+        )*
+    };
+}
+
+impl_flat_value!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+/// Stored as `u64` so the format does not depend on the pointer width.
+impl FlatValue for usize {
+    const SIZE: usize = 8;
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        (*self as u64).write_to(out);
+    }
+
+    fn read_from(bytes: &[u8]) -> Self {
+        u64::read_from(bytes) as usize
+    }
+}
+
+impl FlatValue for () {
+    const SIZE: usize = 0;
+
+    fn write_to(&self, _out: &mut Vec<u8>) {}
+    fn read_from(_bytes: &[u8]) -> Self {}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FlatTrieError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    ValueSizeMismatch { expected: usize, found: usize },
+    Truncated,
+    /// A link or edge range points outside the trie, or edges are out of order.
+    Corrupted,
+}
+
+impl Display for FlatTrieError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FlatTrieError::BadMagic => write!(f, "not a flat trie"),
+            FlatTrieError::UnsupportedVersion(v) => write!(f, "unsupported flat trie version {v}"),
+            FlatTrieError::ValueSizeMismatch { expected, found } => {
+                write!(f, "value size mismatch: expected {expected}, found {found}")
+            }
+            FlatTrieError::Truncated => write!(f, "flat trie data is truncated"),
+            FlatTrieError::Corrupted => write!(f, "flat trie data is corrupted"),
+        }
+    }
+}
+
+impl std::error::Error for FlatTrieError {}
+
+impl<T: TrieWalk<S>, S: FlatValue> TrieImpl<T, S> {
+    /// Encode the trie. Fail links are recomputed over the whole trie when `T` walks through
+    /// them, so the result is complete even if `self` was built by plain `insert`s.
+    pub fn to_flat(&self) -> Vec<u8> {
+        // Number nodes in BFS order, children sorted by char.
+        let mut nodes = vec![self];
+        let mut edges: Vec<Vec<(char, u32)>> = vec![];
+        let mut i = 0;
+        while i < nodes.len() {
+            let mut next: Vec<_> = nodes[i].next.iter().collect();
+            next.sort_by_key(|(c, _)| **c);
+            edges.push(
+                next.into_iter()
+                    .map(|(c, n)| {
+                        nodes.push(n);
+                        (*c, (nodes.len() - 1) as u32)
+                    })
+                    .collect(),
+            );
+            i += 1;
+        }
+
+        let fail = if T::HAS_FAIL_LINKS {
+            build_fail_links(&edges)
+        } else {
+            vec![NO_NODE; nodes.len()]
+        };
+
+        let edge_count: usize = edges.iter().map(Vec::len).sum();
+        let mut out = Vec::with_capacity(
+            HEADER_SIZE + nodes.len() * (NODE_HEADER_SIZE + S::SIZE) + edge_count * EDGE_SIZE,
+        );
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        let flags = if T::HAS_FAIL_LINKS { FLAG_FAIL_LINKS } else { 0 };
+        out.extend_from_slice(&flags.to_le_bytes());
+        out.extend_from_slice(&(nodes.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(edge_count as u32).to_le_bytes());
+        out.extend_from_slice(&(S::SIZE as u32).to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+
+        let mut first_edge = 0u32;
+        for (id, node) in nodes.iter().enumerate() {
+            out.extend_from_slice(&first_edge.to_le_bytes());
+            out.extend_from_slice(&(edges[id].len() as u32).to_le_bytes());
+            out.extend_from_slice(&fail[id].to_le_bytes());
+            match &node.attached_info {
+                Some(info) => {
+                    out.extend_from_slice(&1u32.to_le_bytes());
+                    info.write_to(&mut out);
+                }
+                None => {
+                    out.extend_from_slice(&0u32.to_le_bytes());
+                    out.resize(out.len() + S::SIZE, 0);
+                }
+            }
+            first_edge += edges[id].len() as u32;
+        }
+
+        for (c, target) in edges.iter().flatten() {
+            out.extend_from_slice(&(*c as u32).to_le_bytes());
+            out.extend_from_slice(&target.to_le_bytes());
+        }
+
+        out
+    }
+
+    pub fn write_flat(&self, mut w: impl io::Write) -> io::Result<()> {
+        w.write_all(&self.to_flat())
+    }
+}
+
+/// Classic BFS construction over the numbered trie. The root fails to nothing.
+fn build_fail_links(edges: &[Vec<(char, u32)>]) -> Vec<u32> {
+    let child = |node: u32, c: char| -> Option<u32> {
+        let edges = &edges[node as usize];
+        edges
+            .binary_search_by_key(&c, |(c, _)| *c)
+            .ok()
+            .map(|i| edges[i].1)
+    };
+
+    let mut fail = vec![NO_NODE; edges.len()];
+    let mut q = VecDeque::new();
+    for &(_, n) in &edges[0] {
+        fail[n as usize] = 0;
+        q.push_back(n);
+    }
+
+    while let Some(u) = q.pop_front() {
+        for &(c, v) in &edges[u as usize] {
+            let mut f = fail[u as usize];
+            fail[v as usize] = loop {
+                if let Some(n) = child(f, c) {
+                    break n;
+                }
+                if f == 0 {
+                    break 0;
+                }
+                f = fail[f as usize];
+            };
+            q.push_back(v);
+        }
+    }
+
+    fail
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+/// A read-only trie answering queries directly on its encoded bytes.
+pub struct FlatTrie<'a, S> {
+    bytes: &'a [u8],
+    node_count: usize,
+    fail_links: bool,
+    node_size: usize,
+    edges_offset: usize,
+    _marker: PhantomData<fn() -> S>,
+}
+
+impl<S> Clone for FlatTrie<'_, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S> Copy for FlatTrie<'_, S> {}
+
+impl<'a, S: FlatValue> FlatTrie<'a, S> {
+    pub fn new(bytes: &'a [u8]) -> Result<Self, FlatTrieError> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(FlatTrieError::Truncated);
+        }
+        if &bytes[..4] != MAGIC {
+            return Err(FlatTrieError::BadMagic);
+        }
+        let version = read_u16(bytes, 4);
+        if version != VERSION {
+            return Err(FlatTrieError::UnsupportedVersion(version));
+        }
+        let flags = read_u16(bytes, 6);
+        let node_count = read_u32(bytes, 8) as usize;
+        let edge_count = read_u32(bytes, 12) as usize;
+        let value_size = read_u32(bytes, 16) as usize;
+        if value_size != S::SIZE {
+            return Err(FlatTrieError::ValueSizeMismatch {
+                expected: S::SIZE,
+                found: value_size,
+            });
+        }
+
+        let node_size = NODE_HEADER_SIZE + value_size;
+        let edges_offset = HEADER_SIZE + node_count * node_size;
+        if node_count == 0 || bytes.len() < edges_offset + edge_count * EDGE_SIZE {
+            return Err(FlatTrieError::Truncated);
+        }
+
+        let trie = Self {
+            bytes,
+            node_count,
+            fail_links: flags & FLAG_FAIL_LINKS != 0,
+            node_size,
+            edges_offset,
+            _marker: PhantomData,
+        };
+        trie.validate(edge_count)?;
+        Ok(trie)
+    }
+
+    /// Check every link once, so that queries never read past the bytes and `walk` always
+    /// terminates.
+    fn validate(&self, edge_count: usize) -> Result<(), FlatTrieError> {
+        for id in 0..self.node_count as u32 {
+            let at = self.node_offset(id);
+            let first = read_u32(self.bytes, at) as usize;
+            let count = read_u32(self.bytes, at + 4) as usize;
+            if first + count > edge_count {
+                return Err(FlatTrieError::Corrupted);
+            }
+
+            // Fail links lead to shallower nodes, which come first in BFS order.
+            let fail = read_u32(self.bytes, at + 8);
+            if fail != NO_NODE && fail >= id {
+                return Err(FlatTrieError::Corrupted);
+            }
+
+            let mut prev = None;
+            for edge in first..first + count {
+                let edge = self.edges_offset + edge * EDGE_SIZE;
+                let (c, target) = (read_u32(self.bytes, edge), read_u32(self.bytes, edge + 4));
+                if prev.is_some_and(|p| p >= c) || target as usize >= self.node_count {
+                    return Err(FlatTrieError::Corrupted);
+                }
+                prev = Some(c);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.node_count
+    }
+
+    pub fn has_fail_links(&self) -> bool {
+        self.fail_links
+    }
+
+    pub fn root(&self) -> FlatNode<'a, S> {
+        FlatNode { trie: *self, id: 0 }
+    }
+
+    /// Info attached to exactly `s`.
+    pub fn get(&self, s: impl IntoIterator<Item = char>) -> Option<S> {
+        let mut cur = self.root();
+        for c in s {
+            cur = cur.child(c)?;
+        }
+        cur.attached_info()
+    }
+
+    /// Same semantics as [`TrieImpl::walk`]: a plain trie stops at the first missing edge, an
+    /// AC automaton falls back along fail links and never stops before the input ends.
+    pub fn walk<I: IntoIterator<Item = char>>(
+        &self,
+        s: I,
+    ) -> impl Iterator<Item = FlatNode<'a, S>> + use<'a, S, I> {
+        let mut cur = Some(self.root());
+        let mut it = s.into_iter();
+
+        std::iter::from_fn(move || {
+            let c = it.next()?;
+            cur = cur?.step(c);
+            cur
+        })
+    }
+
+    fn node_offset(&self, id: u32) -> usize {
+        HEADER_SIZE + id as usize * self.node_size
+    }
+}
+
+pub struct FlatNode<'a, S> {
+    trie: FlatTrie<'a, S>,
+    id: u32,
+}
+
+impl<S> Clone for FlatNode<'_, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S> Copy for FlatNode<'_, S> {}
+
+impl<'a, S: FlatValue> FlatNode<'a, S> {
+    /// Index of the node in BFS order, the root being `0`.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.id == 0
+    }
+
+    pub fn attached_info(&self) -> Option<S> {
+        let at = self.trie.node_offset(self.id);
+        (read_u32(self.trie.bytes, at + 12) != 0)
+            .then(|| S::read_from(&self.trie.bytes[at + NODE_HEADER_SIZE..]))
+    }
+
+    pub fn child(&self, c: char) -> Option<Self> {
+        let bytes = self.trie.bytes;
+        let at = self.trie.node_offset(self.id);
+        let first = read_u32(bytes, at) as usize;
+        let (mut l, mut r) = (first, first + read_u32(bytes, at + 4) as usize);
+
+        while l < r {
+            let m = l + (r - l) / 2;
+            let edge = self.trie.edges_offset + m * EDGE_SIZE;
+            match read_u32(bytes, edge).cmp(&(c as u32)) {
+                std::cmp::Ordering::Less => l = m + 1,
+                std::cmp::Ordering::Greater => r = m,
+                std::cmp::Ordering::Equal => {
+                    return Some(Self {
+                        trie: self.trie,
+                        id: read_u32(bytes, edge + 4),
+                    })
+                }
+            }
+        }
+
+        None
+    }
+
+    fn fail(&self) -> Option<Self> {
+        let fail = read_u32(self.trie.bytes, self.trie.node_offset(self.id) + 8);
+        (fail != NO_NODE).then_some(Self {
+            trie: self.trie,
+            id: fail,
+        })
+    }
+
+    fn step(self, c: char) -> Option<Self> {
+        if !self.trie.fail_links {
+            return self.child(c);
+        }
+
+        let mut node = self;
+        loop {
+            if let Some(n) = node.child(c) {
+                break Some(n);
+            }
+
+            match node.fail() {
+                Some(f) => node = f,
+                None => break Some(node),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FlatTrie, FlatTrieError};
+    use crate::data_structure::trie::{ACAutomata, Trie};
+
+    #[test]
+    fn plain_trie_get_and_walk() {
+        let mut trie = Trie::new_root();
+        for (i, w) in ["he", "her", "his"].iter().enumerate() {
+            trie.insert(w.chars(), i as u32);
+        }
+
+        let bytes = trie.to_flat();
+        let flat = FlatTrie::<u32>::new(&bytes).unwrap();
+        assert!(!flat.has_fail_links());
+        assert_eq!(flat.node_count(), 6);
+        assert_eq!(flat.get("her".chars()), Some(1));
+        assert_eq!(flat.get("hi".chars()), None);
+        assert_eq!(flat.get("hello".chars()), None);
+
+        let infos: Vec<_> = flat.walk("hex".chars()).map(|n| n.attached_info()).collect();
+        assert_eq!(infos, [None, Some(0)]);
+    }
+
+    #[test]
+    fn ac_walk_matches_transformed_automaton() {
+        let dict = ["abc", "aaaaa", "bcdef", "cd"];
+        let sentence = "aabcdabcdefaaaaaa";
+        let mut trie = Trie::new_root();
+        for (i, w) in dict.iter().enumerate() {
+            trie.insert(w.chars(), i);
+        }
+        let ac: Box<ACAutomata<usize>> = trie.transform();
+
+        let bytes = ac.to_flat();
+        let flat = FlatTrie::<usize>::new(&bytes).unwrap();
+        assert!(flat.has_fail_links());
+
+        let expected: Vec<_> = ac.walk(sentence.chars()).map(|n| n.attached_info).collect();
+        let found: Vec<_> = flat.walk(sentence.chars()).map(|n| n.attached_info()).collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let mut trie = Trie::new_root();
+        trie.insert("a".chars(), 1u8);
+        let bytes = trie.to_flat();
+
+        assert_eq!(
+            FlatTrie::<u8>::new(&bytes[..bytes.len() - 1]).err(),
+            Some(FlatTrieError::Truncated)
+        );
+        assert_eq!(
+            FlatTrie::<u32>::new(&bytes).err(),
+            Some(FlatTrieError::ValueSizeMismatch {
+                expected: 4,
+                found: 1
+            })
+        );
+        assert_eq!(
+            FlatTrie::<u8>::new(b"NOPE and some more bytes").err(),
+            Some(FlatTrieError::BadMagic)
+        );
+    }
+
+    #[test]
+    fn rejects_corrupted_links() {
+        let mut trie = Trie::new_root();
+        for w in ["ab", "ac"] {
+            trie.insert(w.chars(), 1u8);
+        }
+        let ac: Box<ACAutomata<u8>> = trie.transform();
+        let bytes = ac.to_flat();
+        assert!(FlatTrie::<u8>::new(&bytes).is_ok());
+
+        // Nodes are 17 bytes after the 24-byte header, the root first, then "a", "ab" and "ac".
+        let node = |id: usize| 24 + id * 17;
+        let edges = node(4);
+        let corrupt = |at: usize, value: u32| {
+            let mut bytes = bytes.clone();
+            bytes[at..at + 4].copy_from_slice(&value.to_le_bytes());
+            FlatTrie::<u8>::new(&bytes).err()
+        };
+
+        // The edge range of "a" runs past the last edge.
+        assert_eq!(corrupt(node(1), 2), Some(FlatTrieError::Corrupted));
+        assert_eq!(corrupt(node(1) + 4, 3), Some(FlatTrieError::Corrupted));
+        // An edge target past the last node.
+        assert_eq!(corrupt(edges + 4, 4), Some(FlatTrieError::Corrupted));
+        // The edges of "a" out of order.
+        assert_eq!(corrupt(edges + 8, 'c' as u32), Some(FlatTrieError::Corrupted));
+        // A fail link out of range, and one that would make `walk` loop forever.
+        assert_eq!(corrupt(node(2) + 8, 9), Some(FlatTrieError::Corrupted));
+        assert_eq!(corrupt(node(2) + 8, 2), Some(FlatTrieError::Corrupted));
+    }
+}
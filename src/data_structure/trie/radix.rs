@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+
+use super::{TrieImpl, TrieWalk};
+
+/// A path-compressed trie. Every edge is labelled by a non-empty string, and every node without
+/// attached info has at least two children, so long shared prefixes cost a single node.
+pub struct RadixTrie<S> {
+    root: RadixNode<S>,
+    len: usize,
+}
+
+pub struct RadixNode<S> {
+    /// Children indexed by the first char of their edge label.
+    pub next: HashMap<char, Box<RadixNode<S>>>,
+    pub label: String,
+    pub attached_info: Option<S>,
+}
+
+impl<S> RadixNode<S> {
+    fn new(label: String, attached_info: Option<S>) -> Self {
+        Self {
+            next: HashMap::new(),
+            label,
+            attached_info,
+        }
+    }
+
+    fn boxed(label: String, attached_info: Option<S>) -> Box<Self> {
+        Box::new(Self::new(label, attached_info))
+    }
+
+    fn node_count(&self) -> usize {
+        1 + self.next.values().map(|n| n.node_count()).sum::<usize>()
+    }
+
+    /// Merge the only child into `self` if `self` carries no info.
+    fn compress(&mut self) {
+        if self.attached_info.is_some() || self.next.len() != 1 {
+            return;
+        }
+
+        let (_, child) = self.next.drain().next().unwrap();
+        let child = *child;
+        self.label.push_str(&child.label);
+        self.next = child.next;
+        self.attached_info = child.attached_info;
+    }
+}
+
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.char_indices()
+        .zip(b.chars())
+        .find(|((_, x), y)| x != y)
+        .map(|((i, _), _)| i)
+        .unwrap_or_else(|| a.len().min(b.len()))
+}
+
+impl<S> RadixTrie<S> {
+    pub fn new() -> Self {
+        Self {
+            root: RadixNode::new(String::new(), None),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of nodes, including the root.
+    pub fn node_count(&self) -> usize {
+        self.root.node_count()
+    }
+
+    pub fn root(&self) -> &RadixNode<S> {
+        &self.root
+    }
+
+    /// Insert `key` with `info`, returning the info previously attached to `key`.
+    pub fn insert(&mut self, key: &str, info: S) -> Option<S> {
+        let mut cur = &mut self.root;
+        let mut rest = key;
+
+        loop {
+            let Some(c) = rest.chars().next() else {
+                let prev = cur.attached_info.replace(info);
+                if prev.is_none() {
+                    self.len += 1;
+                }
+                return prev;
+            };
+
+            let Some(child) = cur.next.get_mut(&c) else {
+                cur.next
+                    .insert(c, RadixNode::boxed(rest.to_string(), Some(info)));
+                self.len += 1;
+                return None;
+            };
+
+            let common = common_prefix_len(&child.label, rest);
+            if common < child.label.len() {
+                // Split the edge at `common`, `child` keeps the shared part of the label.
+                let suffix = child.label.split_off(common);
+                let first = suffix.chars().next().unwrap();
+                let mut lower = RadixNode::new(suffix, child.attached_info.take());
+                lower.next = std::mem::take(&mut child.next);
+                child.next.insert(first, Box::new(lower));
+            }
+
+            rest = &rest[common..];
+            cur = cur.next.get_mut(&c).unwrap();
+        }
+    }
+
+    fn find(&self, key: &str) -> Option<&RadixNode<S>> {
+        let mut cur = &self.root;
+        let mut rest = key;
+
+        while let Some(c) = rest.chars().next() {
+            let child = cur.next.get(&c)?;
+            rest = rest.strip_prefix(child.label.as_str())?;
+            cur = child;
+        }
+
+        Some(cur)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&S> {
+        self.find(key)?.attached_info.as_ref()
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut S> {
+        let mut cur = &mut self.root;
+        let mut rest = key;
+
+        while let Some(c) = rest.chars().next() {
+            let child = cur.next.get_mut(&c)?;
+            rest = rest.strip_prefix(child.label.as_str())?;
+            cur = child;
+        }
+
+        cur.attached_info.as_mut()
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Remove `key`, merging nodes that are left with a single child and no info.
+    pub fn remove(&mut self, key: &str) -> Option<S> {
+        fn remove_in<S>(node: &mut RadixNode<S>, rest: &str) -> Option<S> {
+            let Some(c) = rest.chars().next() else {
+                return node.attached_info.take();
+            };
+
+            let child = node.next.get_mut(&c)?;
+            let rest = rest.strip_prefix(child.label.as_str())?;
+            let res = remove_in(child, rest)?;
+
+            if child.attached_info.is_none() && child.next.is_empty() {
+                node.next.remove(&c);
+            } else {
+                child.compress();
+            }
+
+            Some(res)
+        }
+
+        let res = remove_in(&mut self.root, key)?;
+        self.len -= 1;
+        Some(res)
+    }
+
+    /// Iterate over all keys starting with `prefix`, in no particular order.
+    pub fn iter_prefix<'a>(&'a self, prefix: &str) -> impl Iterator<Item = (String, &'a S)> {
+        let mut stack = vec![];
+        let mut cur = &self.root;
+        let mut rest = prefix;
+        let mut path = String::new();
+
+        let found = loop {
+            let Some(c) = rest.chars().next() else {
+                break true;
+            };
+            let Some(child) = cur.next.get(&c) else {
+                break false;
+            };
+
+            path.push_str(&child.label);
+            if let Some(r) = rest.strip_prefix(child.label.as_str()) {
+                rest = r;
+            } else if child.label.starts_with(rest) {
+                // `prefix` ends in the middle of this edge.
+                rest = "";
+            } else {
+                break false;
+            }
+            cur = child;
+        };
+
+        if found {
+            stack.push((path, cur));
+        }
+
+        RadixIter { stack }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (String, &S)> {
+        self.iter_prefix("")
+    }
+}
+
+struct RadixIter<'a, S> {
+    stack: Vec<(String, &'a RadixNode<S>)>,
+}
+
+impl<'a, S> Iterator for RadixIter<'a, S> {
+    type Item = (String, &'a S);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((path, node)) = self.stack.pop() {
+            for child in node.next.values() {
+                self.stack.push((path.clone() + &child.label, child));
+            }
+
+            if let Some(info) = &node.attached_info {
+                return Some((path, info));
+            }
+        }
+
+        None
+    }
+}
+
+impl<S> Default for RadixTrie<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: TrieWalk<S>, S> From<TrieImpl<T, S>> for RadixTrie<S> {
+    fn from(trie: TrieImpl<T, S>) -> Self {
+        fn convert<T, S>(node: TrieImpl<T, S>, label: String, len: &mut usize) -> RadixNode<S> {
+            let mut node = node;
+            let mut label = label;
+
+            // Follow chains of single-child nodes without info.
+            while node.attached_info.is_none() && node.next.len() == 1 {
+                let (c, child) = node.next.into_iter().next().unwrap();
+                label.push(c);
+                node = *child;
+            }
+
+            if node.attached_info.is_some() {
+                *len += 1;
+            }
+
+            let mut res = RadixNode::new(label, node.attached_info);
+            for (c, child) in node.next {
+                res.next.insert(c, Box::new(convert(*child, c.to_string(), len)));
+            }
+
+            res
+        }
+
+        let mut len = 0;
+        let mut root = RadixNode::new(String::new(), trie.attached_info);
+        if root.attached_info.is_some() {
+            len += 1;
+        }
+        for (c, child) in trie.next {
+            root.next
+                .insert(c, Box::new(convert(*child, c.to_string(), &mut len)));
+        }
+
+        Self { root, len }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RadixTrie;
+    use crate::data_structure::trie::Trie;
+
+    #[test]
+    fn insert_and_get() {
+        let mut trie = RadixTrie::new();
+        assert_eq!(trie.insert("/usr/bin", 1), None);
+        assert_eq!(trie.insert("/usr/lib", 2), None);
+        assert_eq!(trie.insert("/usr", 3), None);
+        assert_eq!(trie.insert("/usr/lib", 4), Some(2));
+
+        assert_eq!(trie.get("/usr/bin"), Some(&1));
+        assert_eq!(trie.get("/usr/lib"), Some(&4));
+        assert_eq!(trie.get("/usr"), Some(&3));
+        assert_eq!(trie.get("/us"), None);
+        assert_eq!(trie.get("/usr/bin/env"), None);
+        assert_eq!(trie.len(), 3);
+        // root, "/usr", "/", "bin", "lib"
+        assert_eq!(trie.node_count(), 5);
+    }
+
+    #[test]
+    fn remove_merges_nodes() {
+        let mut trie = RadixTrie::new();
+        trie.insert("team", 1);
+        trie.insert("test", 2);
+        assert_eq!(trie.node_count(), 4);
+
+        assert_eq!(trie.remove("te"), None);
+        assert_eq!(trie.remove("team"), Some(1));
+        assert_eq!(trie.node_count(), 2);
+        assert_eq!(trie.root().next[&'t'].label, "test");
+        assert_eq!(trie.get("test"), Some(&2));
+        assert_eq!(trie.len(), 1);
+    }
+
+    #[test]
+    fn prefix_iteration() {
+        let mut trie = RadixTrie::new();
+        for (i, w) in ["apple", "applet", "apply", "banana"].iter().enumerate() {
+            trie.insert(w, i);
+        }
+
+        let mut words: Vec<_> = trie.iter_prefix("appl").map(|(k, _)| k).collect();
+        words.sort();
+        assert_eq!(words, ["apple", "applet", "apply"]);
+
+        let words: Vec<_> = trie.iter_prefix("applet").map(|(k, v)| (k, *v)).collect();
+        assert_eq!(words, [("applet".to_string(), 1)]);
+        assert_eq!(trie.iter_prefix("c").count(), 0);
+        assert_eq!(trie.iter().count(), 4);
+    }
+
+    #[test]
+    fn from_trie() {
+        let mut trie = Trie::new_root();
+        for (i, w) in ["/srv/www/a", "/srv/www/b", "/srv"].iter().enumerate() {
+            trie.insert(w.chars(), i);
+        }
+
+        let radix = RadixTrie::from(trie);
+        assert_eq!(radix.len(), 3);
+        assert_eq!(radix.get("/srv/www/b"), Some(&1));
+        assert_eq!(radix.get("/srv"), Some(&2));
+        // root, "/srv", "/www/", "a", "b"
+        assert_eq!(radix.node_count(), 5);
+    }
+}
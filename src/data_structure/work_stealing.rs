@@ -0,0 +1,311 @@
+use std::{
+    cell::{Cell, UnsafeCell},
+    marker::PhantomData,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{fence, AtomicIsize, Ordering},
+        Arc,
+    },
+};
+
+use crossbeam::{
+    epoch::{self, Atomic, Owned},
+    utils::CachePadded,
+};
+
+const MIN_CAPACITY: usize = 32;
+
+/// A circular buffer whose capacity is a power of two. Dropping it doesn't drop the elements.
+struct Buffer<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+}
+
+impl<T> Buffer<T> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            slots: (0..capacity)
+                .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                .collect(),
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn slot(&self, index: isize) -> *mut MaybeUninit<T> {
+        self.slots[index as usize & (self.capacity() - 1)].get()
+    }
+
+    unsafe fn write(&self, index: isize, val: T) {
+        (*self.slot(index)).write(val);
+    }
+
+    /// A bitwise copy, only to be assumed initialized once the index has been claimed.
+    unsafe fn read(&self, index: isize) -> MaybeUninit<T> {
+        self.slot(index).read()
+    }
+}
+
+struct Inner<T> {
+    /// Next index to steal. Only ever increases.
+    top: CachePadded<AtomicIsize>,
+    /// Next index to push, only written by the owner.
+    bottom: CachePadded<AtomicIsize>,
+    buffer: Atomic<Buffer<T>>,
+}
+
+unsafe impl<T: Send> Send for Inner<T> {}
+unsafe impl<T: Send> Sync for Inner<T> {}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        unsafe {
+            let guard = epoch::unprotected();
+            let buffer = self.buffer.load(Ordering::Relaxed, guard).into_owned();
+            for i in *self.top.get_mut()..*self.bottom.get_mut() {
+                buffer.read(i).assume_init_drop();
+            }
+        }
+    }
+}
+
+/// A Chase–Lev work-stealing deque, with the memory orderings of Lê et al., "Correct and
+/// Efficient Work-Stealing for Weak Memory Models". The owning [`Worker`] pushes and pops at the
+/// bottom, any number of [`Stealer`]s take from the top.
+pub fn deque<T>() -> (Worker<T>, Stealer<T>) {
+    let inner = Arc::new(Inner {
+        top: CachePadded::new(AtomicIsize::new(0)),
+        bottom: CachePadded::new(AtomicIsize::new(0)),
+        buffer: Atomic::new(Buffer::new(MIN_CAPACITY)),
+    });
+
+    (
+        Worker {
+            inner: inner.clone(),
+            _not_sync: PhantomData,
+        },
+        Stealer { inner },
+    )
+}
+
+pub struct Worker<T> {
+    inner: Arc<Inner<T>>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl<T> Worker<T> {
+    /// Approximate number of elements.
+    pub fn len(&self) -> usize {
+        let b = self.inner.bottom.load(Ordering::Relaxed);
+        let t = self.inner.top.load(Ordering::Relaxed);
+        (b - t).max(0) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn stealer(&self) -> Stealer<T> {
+        Stealer {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Move the live elements into a buffer twice as large.
+    fn grow(&self, top: isize, bottom: isize) {
+        let guard = epoch::pin();
+        let old = self.inner.buffer.load(Ordering::Relaxed, &guard);
+        let old_ref = unsafe { old.deref() };
+        let new = Buffer::new(old_ref.capacity() * 2);
+        for i in top..bottom {
+            unsafe { new.slot(i).write(old_ref.read(i)) };
+        }
+
+        self.inner.buffer.store(Owned::new(new), Ordering::Release);
+        // Thieves may still be reading from the old buffer.
+        unsafe { guard.defer_destroy(old) };
+    }
+
+    pub fn push(&self, val: T) {
+        let b = self.inner.bottom.load(Ordering::Relaxed);
+        let t = self.inner.top.load(Ordering::Acquire);
+        // Only the owner replaces the buffer.
+        let guard = unsafe { epoch::unprotected() };
+        let mut buffer = self.inner.buffer.load(Ordering::Relaxed, guard);
+        if b - t >= unsafe { buffer.deref() }.capacity() as isize {
+            self.grow(t, b);
+            buffer = self.inner.buffer.load(Ordering::Relaxed, guard);
+        }
+
+        unsafe { buffer.deref().write(b, val) };
+        fence(Ordering::Release);
+        self.inner.bottom.store(b + 1, Ordering::Relaxed);
+    }
+
+    /// Take the most recently pushed element.
+    pub fn pop(&self) -> Option<T> {
+        let b = self.inner.bottom.load(Ordering::Relaxed) - 1;
+        let guard = unsafe { epoch::unprotected() };
+        let buffer = unsafe { self.inner.buffer.load(Ordering::Relaxed, guard).deref() };
+        self.inner.bottom.store(b, Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        let t = self.inner.top.load(Ordering::Relaxed);
+
+        if t > b {
+            // Empty.
+            self.inner.bottom.store(b + 1, Ordering::Relaxed);
+            return None;
+        }
+
+        let val = unsafe { buffer.read(b) };
+        if t == b {
+            // The last element: race thieves for it.
+            let won = self
+                .inner
+                .top
+                .compare_exchange(t, t + 1, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok();
+            self.inner.bottom.store(b + 1, Ordering::Relaxed);
+            if !won {
+                return None;
+            }
+        }
+        Some(unsafe { val.assume_init() })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Steal<T> {
+    Empty,
+    Success(T),
+    /// Lost a race with another thief or the owner; the deque may still have elements.
+    Retry,
+}
+
+pub struct Stealer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Stealer<T> {
+    pub fn is_empty(&self) -> bool {
+        let t = self.inner.top.load(Ordering::Acquire);
+        let b = self.inner.bottom.load(Ordering::Acquire);
+        b <= t
+    }
+
+    /// Take the least recently pushed element.
+    pub fn steal(&self) -> Steal<T> {
+        let t = self.inner.top.load(Ordering::Acquire);
+        fence(Ordering::SeqCst);
+        let b = self.inner.bottom.load(Ordering::Acquire);
+        if t >= b {
+            return Steal::Empty;
+        }
+
+        let guard = epoch::pin();
+        let buffer = self.inner.buffer.load(Ordering::Acquire, &guard);
+        let val = unsafe { buffer.deref().read(t) };
+        if self
+            .inner
+            .top
+            .compare_exchange(t, t + 1, Ordering::SeqCst, Ordering::Relaxed)
+            .is_err()
+        {
+            return Steal::Retry;
+        }
+        Steal::Success(unsafe { val.assume_init() })
+    }
+}
+
+impl<T> Clone for Stealer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    use super::{deque, Steal};
+
+    #[test]
+    fn lifo_owner_fifo_thief() {
+        let (worker, stealer) = deque();
+        for i in 0..100 {
+            worker.push(i);
+        }
+        assert_eq!(worker.len(), 100);
+
+        assert_eq!(worker.pop(), Some(99));
+        assert_eq!(stealer.steal(), Steal::Success(0));
+        assert_eq!(stealer.steal(), Steal::Success(1));
+        assert_eq!(worker.pop(), Some(98));
+
+        let rest: Vec<_> = std::iter::from_fn(|| worker.pop()).collect();
+        assert_eq!(rest, (2..98).rev().collect::<Vec<_>>());
+        assert_eq!(stealer.steal(), Steal::Empty);
+    }
+
+    #[test]
+    fn drops_remaining() {
+        let rc = std::rc::Rc::new(());
+        let (worker, stealer) = deque();
+        for _ in 0..50 {
+            worker.push(rc.clone());
+        }
+        worker.pop();
+        drop(stealer.steal());
+        drop((worker, stealer));
+        assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn concurrent_steal() {
+        const N: usize = 100_000;
+        let (worker, stealer) = deque();
+        let done = Arc::new(AtomicBool::new(false));
+
+        let thieves: Vec<_> = (0..3)
+            .map(|_| {
+                let (stealer, done) = (stealer.clone(), done.clone());
+                thread::spawn(move || {
+                    let mut got = vec![];
+                    loop {
+                        match stealer.steal() {
+                            Steal::Success(v) => got.push(v),
+                            Steal::Retry => {}
+                            Steal::Empty if done.load(Ordering::Acquire) => break got,
+                            Steal::Empty => thread::yield_now(),
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let mut got = vec![];
+        for i in 0..N {
+            worker.push(i);
+            if i % 3 == 0 {
+                got.extend(worker.pop());
+            }
+        }
+        got.extend(std::iter::from_fn(|| worker.pop()));
+        done.store(true, Ordering::Release);
+
+        for t in thieves {
+            got.extend(t.join().unwrap());
+        }
+        got.sort();
+        assert_eq!(got, (0..N).collect::<Vec<_>>());
+    }
+}
@@ -7,3 +7,4 @@ pub mod matrix_like;
 pub mod parser_combinator;
 pub mod utils;
 pub mod nonblocking;
+pub(crate) mod sync;
@@ -1,2 +1,13 @@
 pub mod semaphore;
+pub mod timeout;
 pub mod lock;
+#[cfg(feature = "diagnostics")]
+pub mod diagnostics;
+pub mod rwlock;
+pub mod notify;
+pub mod barrier;
+pub mod condvar;
+pub mod once_cell;
+pub mod rate_limit;
+pub(crate) mod waiter;
+pub mod channel;
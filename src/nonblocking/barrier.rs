@@ -0,0 +1,193 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use super::waiter::{Waiter, WaiterList};
+use crate::sync::atomic::{fence, AtomicU64, Ordering};
+
+const ARRIVED: u64 = u32::MAX as u64;
+
+/// Lets `n` tasks wait for each other. Once the last of them arrives, all are released and the
+/// barrier starts over for the next `n`.
+///
+/// Exactly one task of every round, the last to arrive, is told it's the
+/// [leader](BarrierWaitResult::is_leader).
+pub struct Barrier {
+    n: u32,
+    /// The round in the upper half, the number of tasks arrived in it in the lower half.
+    state: AtomicU64,
+    waiters: WaiterList,
+}
+
+/// Returned by [`Barrier::wait`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl Barrier {
+    /// A barrier for `n` tasks. A barrier for none behaves like one for a single task.
+    pub fn new(n: u32) -> Self {
+        Self {
+            n: n.max(1),
+            state: AtomicU64::new(0),
+            waiters: WaiterList::new(),
+        }
+    }
+
+    pub fn wait(&self) -> BarrierWait<'_> {
+        BarrierWait {
+            barrier: self,
+            round: None,
+            waiter: None,
+        }
+    }
+
+    fn round(&self) -> u64 {
+        self.state.load(Ordering::Acquire) >> 32
+    }
+
+    /// Arrive in the current round. Returns the round, and whether this completed it.
+    fn arrive(&self) -> (u64, bool) {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            let (round, arrived) = (state >> 32, (state & ARRIVED) + 1);
+            let leader = arrived == u64::from(self.n);
+            let next = if leader { (round + 1) << 32 } else { state + 1 };
+            match self
+                .state
+                .compare_exchange_weak(state, next, Ordering::AcqRel, Ordering::Relaxed)
+            {
+                Ok(_) => return (round, leader),
+                Err(actual) => state = actual,
+            }
+        }
+    }
+}
+
+pub struct BarrierWait<'a> {
+    barrier: &'a Barrier,
+    /// Set once arrived.
+    round: Option<u64>,
+    waiter: Option<Arc<Waiter>>,
+}
+
+impl Future for BarrierWait<'_> {
+    type Output = BarrierWaitResult;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let barrier = this.barrier;
+
+        let round = match this.round {
+            Some(round) => round,
+            None => {
+                let (round, leader) = barrier.arrive();
+                if leader {
+                    // Pairs with the fence below: either waiters see the new round, or we see
+                    // them.
+                    fence(Ordering::SeqCst);
+                    barrier.waiters.notify_all();
+                    return Poll::Ready(BarrierWaitResult(true));
+                }
+                this.round = Some(round);
+                round
+            }
+        };
+
+        if let Some(waiter) = &this.waiter {
+            waiter.register(cx.waker());
+            if waiter.notified().is_none() {
+                return Poll::Pending;
+            }
+        }
+        // Woken for this round or, if the waiter is queued late, for a later one.
+        if barrier.round() != round {
+            return Poll::Ready(BarrierWaitResult(false));
+        }
+
+        let waiter = Waiter::new(cx.waker());
+        barrier.waiters.push(waiter.clone());
+        fence(Ordering::SeqCst);
+        if barrier.round() != round {
+            waiter.cancel();
+            return Poll::Ready(BarrierWaitResult(false));
+        }
+        this.waiter = Some(waiter);
+        Poll::Pending
+    }
+}
+
+impl Drop for BarrierWait<'_> {
+    fn drop(&mut self) {
+        if let Some(waiter) = self.waiter.take() {
+            // Broadcasts need not be passed on. The task still counts as arrived.
+            waiter.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        future::Future,
+        pin::pin,
+        sync::Arc,
+        task::{Context, Poll, Waker},
+    };
+
+    use super::{Barrier, BarrierWaitResult};
+
+    #[test]
+    fn last_arrival_leads() {
+        let barrier = Barrier::new(3);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut first = pin!(barrier.wait());
+        let mut second = pin!(barrier.wait());
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+        let leader = |poll: Poll<BarrierWaitResult>| poll.map(|r| r.is_leader());
+        assert_eq!(
+            leader(pin!(barrier.wait()).poll(&mut cx)),
+            Poll::Ready(true)
+        );
+        assert_eq!(leader(first.poll(&mut cx)), Poll::Ready(false));
+        assert_eq!(leader(second.poll(&mut cx)), Poll::Ready(false));
+
+        // The next round starts from scratch.
+        assert!(pin!(barrier.wait()).poll(&mut cx).is_pending());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn one_leader_per_round() {
+        const TASKS: u32 = 8;
+        const ROUNDS: usize = 200;
+        let barrier = Arc::new(Barrier::new(TASKS));
+
+        let tasks: Vec<_> = (0..TASKS)
+            .map(|_| {
+                let barrier = barrier.clone();
+                tokio::spawn(async move {
+                    let mut led = 0;
+                    for _ in 0..ROUNDS {
+                        led += usize::from(barrier.wait().await.is_leader());
+                    }
+                    led
+                })
+            })
+            .collect();
+        let mut leaders = 0;
+        for task in tasks {
+            leaders += task.await.unwrap();
+        }
+        assert_eq!(leaders, ROUNDS);
+    }
+}
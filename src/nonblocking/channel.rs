@@ -0,0 +1,578 @@
+use std::{
+    fmt::Display,
+    future::poll_fn,
+    pin::Pin,
+    sync::{
+        atomic::{fence, AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+
+use super::waiter::{Notified, Waiter, WaiterList};
+use crate::data_structure::queue::Queue;
+
+struct Chan<T> {
+    queue: Queue<T>,
+    capacity: Option<usize>,
+    /// Slots taken in a bounded channel. Reserved before a message is pushed and released after
+    /// it is popped, so it never undercounts.
+    reserved: AtomicUsize,
+    senders: AtomicUsize,
+    receivers: AtomicUsize,
+    closed: AtomicBool,
+    /// Each message sent wakes one of these, and closing wakes them all.
+    recv_waiters: WaiterList,
+    /// Each slot freed wakes one of these, and closing wakes them all.
+    send_waiters: WaiterList,
+}
+
+/// Stop waiting on `list`. A message or slot `waiter` was already told about is passed on to
+/// the next in line rather than swallowed.
+fn cancel(list: &WaiterList, waiter: &mut Option<Arc<Waiter>>) {
+    if let Some(waiter) = waiter.take() {
+        if waiter.cancel() == Some(Notified::One) {
+            list.notify_one();
+        }
+    }
+}
+
+/// The waiter a pending `send` or `recv` has queued, left on drop.
+struct Queued<'a> {
+    list: &'a WaiterList,
+    waiter: Option<Arc<Waiter>>,
+}
+
+impl<'a> Queued<'a> {
+    fn new(list: &'a WaiterList) -> Self {
+        Self { list, waiter: None }
+    }
+}
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        cancel(self.list, &mut self.waiter);
+    }
+}
+
+impl<T> Chan<T> {
+    fn new(capacity: Option<usize>) -> Self {
+        Self {
+            queue: Queue::new(),
+            capacity,
+            reserved: AtomicUsize::new(0),
+            senders: AtomicUsize::new(1),
+            receivers: AtomicUsize::new(1),
+            closed: AtomicBool::new(false),
+            recv_waiters: WaiterList::new(),
+            send_waiters: WaiterList::new(),
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        fence(Ordering::SeqCst);
+        self.recv_waiters.notify_all();
+        self.send_waiters.notify_all();
+    }
+
+    fn try_reserve(&self) -> bool {
+        match self.capacity {
+            None => true,
+            Some(cap) => self
+                .reserved
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |r| (r < cap).then_some(r + 1))
+                .is_ok(),
+        }
+    }
+
+    fn push(&self, val: T) {
+        self.queue.push(val);
+        // Pairs with the fence in `wait`: either we see its waiter, or it sees the message.
+        fence(Ordering::SeqCst);
+        self.recv_waiters.notify_one();
+    }
+
+    fn pop(&self) -> Option<T> {
+        let val = self.queue.pop()?;
+        if self.capacity.is_some() {
+            self.reserved.fetch_sub(1, Ordering::AcqRel);
+            fence(Ordering::SeqCst);
+            self.send_waiters.notify_one();
+        }
+        Some(val)
+    }
+
+    fn try_send(&self, val: T) -> Result<(), TrySendError<T>> {
+        if self.is_closed() {
+            Err(TrySendError::Closed(val))
+        } else if self.try_reserve() {
+            self.push(val);
+            Ok(())
+        } else {
+            Err(TrySendError::Full(val))
+        }
+    }
+
+    /// Poll `attempt` until it is ready, queueing `waiter` on `list` while it is not.
+    fn wait<R>(
+        list: &WaiterList,
+        cx: &mut Context<'_>,
+        waiter: &mut Option<Arc<Waiter>>,
+        mut attempt: impl FnMut() -> Poll<R>,
+    ) -> Poll<R> {
+        if let Some(queued) = waiter {
+            queued.register(cx.waker());
+            if queued.notified().is_none() {
+                return Poll::Pending;
+            }
+            *waiter = None;
+        }
+
+        if let ready @ Poll::Ready(_) = attempt() {
+            return ready;
+        }
+
+        let queued = Waiter::new(cx.waker());
+        list.push(queued.clone());
+        // Pairs with the fences in `push`, `pop` and `close`: either they see our waiter, or we
+        // see what they did.
+        fence(Ordering::SeqCst);
+
+        *waiter = Some(queued);
+        let poll = attempt();
+        if poll.is_ready() {
+            cancel(list, waiter);
+        }
+        poll
+    }
+
+    fn poll_send(
+        &self,
+        cx: &mut Context<'_>,
+        val: &mut Option<T>,
+        waiter: &mut Option<Arc<Waiter>>,
+    ) -> Poll<Result<(), SendError<T>>> {
+        Self::wait(&self.send_waiters, cx, waiter, || {
+            match self.try_send(val.take().expect("polled after completion")) {
+                Ok(()) => Poll::Ready(Ok(())),
+                Err(TrySendError::Closed(v)) => Poll::Ready(Err(SendError(v))),
+                Err(TrySendError::Full(v)) => {
+                    *val = Some(v);
+                    Poll::Pending
+                }
+            }
+        })
+    }
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        if let Some(val) = self.pop() {
+            return Ok(val);
+        }
+        if self.is_closed() {
+            // Messages sent right before closing.
+            return self.pop().ok_or(TryRecvError::Closed);
+        }
+        Err(TryRecvError::Empty)
+    }
+
+    fn poll_recv(
+        &self,
+        cx: &mut Context<'_>,
+        waiter: &mut Option<Arc<Waiter>>,
+    ) -> Poll<Option<T>> {
+        Self::wait(&self.recv_waiters, cx, waiter, || match self.try_recv() {
+            Ok(val) => Poll::Ready(Some(val)),
+            Err(TryRecvError::Closed) => Poll::Ready(None),
+            Err(TryRecvError::Empty) => Poll::Pending,
+        })
+    }
+}
+
+/// The receiving side went away; the message is handed back.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    Full(T),
+    Closed(T),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    /// Every sender is gone and the channel is drained.
+    Closed,
+}
+
+impl<T> Display for SendError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "sending on a closed channel")
+    }
+}
+
+impl<T> Display for TrySendError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrySendError::Full(_) => write!(f, "sending on a full channel"),
+            TrySendError::Closed(_) => write!(f, "sending on a closed channel"),
+        }
+    }
+}
+
+impl Display for TryRecvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TryRecvError::Empty => write!(f, "receiving on an empty channel"),
+            TryRecvError::Closed => write!(f, "receiving on a closed channel"),
+        }
+    }
+}
+
+impl<T: std::fmt::Debug> std::error::Error for SendError<T> {}
+impl<T: std::fmt::Debug> std::error::Error for TrySendError<T> {}
+impl std::error::Error for TryRecvError {}
+
+/// A channel whose `send` never waits.
+pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    let chan = Arc::new(Chan::new(None));
+    (Sender { chan: chan.clone() }, Receiver { chan, next: None })
+}
+
+/// A channel holding at most `capacity` messages; `send` waits for room.
+pub fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "bounded channel needs a positive capacity");
+    let chan = Arc::new(Chan::new(Some(capacity)));
+    (Sender { chan: chan.clone() }, Receiver { chan, next: None })
+}
+
+pub struct Sender<T> {
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> Sender<T> {
+    pub async fn send(&self, val: T) -> Result<(), SendError<T>> {
+        let (mut val, mut queued) = (Some(val), Queued::new(&self.chan.send_waiters));
+        poll_fn(|cx| self.chan.poll_send(cx, &mut val, &mut queued.waiter)).await
+    }
+
+    pub fn try_send(&self, val: T) -> Result<(), TrySendError<T>> {
+        self.chan.try_send(val)
+    }
+
+    /// Whether every receiver is gone.
+    pub fn is_closed(&self) -> bool {
+        self.chan.is_closed()
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.chan.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            chan: self.chan.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.chan.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.chan.close();
+        }
+    }
+}
+
+/// Cloning a receiver makes the channel multi-consumer: each message goes to one receiver.
+pub struct Receiver<T> {
+    chan: Arc<Chan<T>>,
+    /// Queued by the pending `poll_next`, if any.
+    next: Option<Arc<Waiter>>,
+}
+
+impl<T> Receiver<T> {
+    /// The next message, or `None` once every sender is gone and the channel is drained.
+    pub async fn recv(&self) -> Option<T> {
+        let mut queued = Queued::new(&self.chan.recv_waiters);
+        poll_fn(|cx| self.chan.poll_recv(cx, &mut queued.waiter)).await
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.chan.try_recv()
+    }
+
+    /// Approximate number of queued messages.
+    pub fn len(&self) -> usize {
+        self.chan.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chan.queue.is_empty()
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.chan.receivers.fetch_add(1, Ordering::Relaxed);
+        Self {
+            chan: self.chan.clone(),
+            next: None,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        cancel(&self.chan.recv_waiters, &mut self.next);
+        if self.chan.receivers.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.chan.close();
+        }
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        this.chan.poll_recv(cx, &mut this.next)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        cell::RefCell,
+        future::{poll_fn, Future},
+        pin::Pin,
+        rc::Rc,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Context, Poll, Wake, Waker},
+        time::Duration,
+    };
+
+    use futures_core::Stream;
+
+    use super::{bounded, unbounded, SendError, TryRecvError, TrySendError};
+    use crate::utils::executor::Executor;
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[tokio::test]
+    async fn send_and_recv() {
+        let (tx, rx) = unbounded();
+        let producer = tokio::spawn(async move {
+            for i in 0..100 {
+                tx.send(i).await.unwrap();
+            }
+        });
+
+        for i in 0..100 {
+            assert_eq!(rx.recv().await, Some(i));
+        }
+        producer.await.unwrap();
+        // All senders are gone.
+        assert_eq!(rx.recv().await, None);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Closed));
+    }
+
+    #[tokio::test]
+    async fn close_detection() {
+        let (tx, rx) = unbounded();
+        let tx2 = tx.clone();
+        tx.send(1).await.unwrap();
+        drop(tx);
+        tx2.send(2).await.unwrap();
+        drop(tx2);
+
+        // Queued messages are still delivered after closing.
+        assert_eq!(rx.recv().await, Some(1));
+        assert_eq!(rx.recv().await, Some(2));
+        assert_eq!(rx.recv().await, None);
+
+        let (tx, rx) = unbounded();
+        drop(rx);
+        assert!(tx.is_closed());
+        assert_eq!(tx.send(3).await, Err(SendError(3)));
+    }
+
+    #[tokio::test]
+    async fn bounded_backpressure() {
+        let (tx, rx) = bounded(2);
+        tx.try_send(1).unwrap();
+        tx.try_send(2).unwrap();
+        assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+
+        let blocked = tokio::spawn(async move {
+            tx.send(3).await.unwrap();
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!blocked.is_finished());
+
+        assert_eq!(rx.recv().await, Some(1));
+        blocked.await.unwrap();
+        assert_eq!(rx.recv().await, Some(2));
+        assert_eq!(rx.recv().await, Some(3));
+        assert_eq!(rx.recv().await, None);
+    }
+
+    #[test]
+    fn each_message_and_slot_wakes_one_waiter() {
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+        let woken = || counter.0.swap(0, Ordering::Relaxed);
+
+        let (tx, rx) = bounded(1);
+        let mut receivers: Vec<_> = (0..3).map(|_| Box::pin(rx.recv())).collect();
+        for receiver in &mut receivers {
+            assert!(receiver.as_mut().poll(&mut cx).is_pending());
+        }
+        tx.try_send(1).unwrap();
+        assert_eq!(woken(), 1);
+        assert_eq!(receivers[0].as_mut().poll(&mut cx), Poll::Ready(Some(1)));
+        drop(receivers.remove(0));
+
+        tx.try_send(2).unwrap();
+        let mut senders: Vec<_> = (3..6).map(|i| Box::pin(tx.send(i))).collect();
+        for sender in &mut senders {
+            assert!(sender.as_mut().poll(&mut cx).is_pending());
+        }
+        assert_eq!(woken(), 1);
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(woken(), 1);
+        assert_eq!(senders[0].as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        for sender in &mut senders[1..] {
+            assert!(sender.as_mut().poll(&mut cx).is_pending());
+        }
+
+        // Closing still wakes everyone.
+        drop(receivers);
+        drop(rx);
+        assert!(woken() > 0);
+        for sender in &mut senders[1..] {
+            assert!(matches!(sender.as_mut().poll(&mut cx), Poll::Ready(Err(_))));
+        }
+    }
+
+    #[test]
+    fn dropped_receiver_passes_the_message_on() {
+        let mut cx = Context::from_waker(Waker::noop());
+        let (tx, rx) = unbounded();
+        let mut receivers: Vec<_> = (0..2).map(|_| Box::pin(rx.recv())).collect();
+        for receiver in &mut receivers {
+            assert!(receiver.as_mut().poll(&mut cx).is_pending());
+        }
+
+        tx.try_send(1).unwrap();
+        drop(receivers.remove(0));
+        assert_eq!(receivers[0].as_mut().poll(&mut cx), Poll::Ready(Some(1)));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn mpmc() {
+        let (tx, rx) = bounded(8);
+        let producers: Vec<_> = (0..4)
+            .map(|t| {
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    for i in 0..1000 {
+                        tx.send(t * 1000 + i).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+
+        let consumers: Vec<_> = (0..3)
+            .map(|_| {
+                let rx = rx.clone();
+                tokio::spawn(async move {
+                    let mut got = vec![];
+                    while let Some(v) = rx.recv().await {
+                        got.push(v);
+                    }
+                    got
+                })
+            })
+            .collect();
+        drop(rx);
+
+        for p in producers {
+            p.await.unwrap();
+        }
+        let mut all = vec![];
+        for c in consumers {
+            all.extend(c.await.unwrap());
+        }
+        all.sort();
+        assert_eq!(all, (0..4000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn deterministic_schedules() {
+        for seed in 0..200 {
+            let executor = Executor::new(seed);
+            let (tx, rx) = bounded(2);
+            let got = Rc::new(RefCell::new(vec![]));
+            for t in 0..3 {
+                let (tx, handle) = (tx.clone(), executor.handle());
+                executor.spawn(async move {
+                    for i in 0..5 {
+                        tx.send(t * 10 + i).await.unwrap();
+                        handle.yield_now().await;
+                    }
+                });
+            }
+            drop(tx);
+            for _ in 0..2 {
+                let (rx, got) = (rx.clone(), got.clone());
+                executor.spawn(async move {
+                    while let Some(v) = rx.recv().await {
+                        got.borrow_mut().push(v);
+                    }
+                });
+            }
+            drop(rx);
+
+            if let Err(err) = executor.run() {
+                panic!("seed {seed}: {err}");
+            }
+            let mut got = got.take();
+            got.sort();
+            let sent: Vec<_> = (0..3).flat_map(|t| (0..5).map(move |i| t * 10 + i)).collect();
+            assert_eq!(got, sent);
+        }
+    }
+
+    #[tokio::test]
+    async fn stream() {
+        let (tx, mut rx) = unbounded();
+        for i in 0..3 {
+            tx.send(i).await.unwrap();
+        }
+        drop(tx);
+
+        let mut got = vec![];
+        while let Some(v) = poll_fn(|cx| Pin::new(&mut rx).poll_next(cx)).await {
+            got.push(v);
+        }
+        assert_eq!(got, [0, 1, 2]);
+    }
+}
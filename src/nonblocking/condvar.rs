@@ -0,0 +1,165 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+};
+
+use super::{
+    lock::AsyncMutexGuard,
+    waiter::{Notified, Waiter, WaiterList},
+};
+
+/// An async condition variable, used together with an [`AsyncMutex`](super::lock::AsyncMutex).
+///
+/// Wakeups only come from notifications, but a notified task still has to relock the mutex, so
+/// whatever it waited for may be gone again: check the condition in a loop, or use
+/// [`wait_while`](Self::wait_while).
+pub struct Condvar {
+    waiters: WaiterList,
+}
+
+impl Condvar {
+    pub fn new() -> Self {
+        Self {
+            waiters: WaiterList::new(),
+        }
+    }
+
+    /// Unlock `guard`'s mutex, wait for a notification and lock it again.
+    ///
+    /// The task starts waiting before the mutex is unlocked, so a notification sent by whoever
+    /// locks it next can't be missed.
+    pub async fn wait<'a, T>(&self, guard: AsyncMutexGuard<'a, T>) -> AsyncMutexGuard<'a, T> {
+        let mutex = guard.mutex;
+        let waiter = Waiter::new(Waker::noop());
+        self.waiters.push(waiter.clone());
+        drop(guard);
+        Wait {
+            condvar: self,
+            waiter: Some(waiter),
+        }
+        .await;
+        mutex.lock().await
+    }
+
+    /// Wait until `condition` is false, checking it with the mutex locked.
+    pub async fn wait_while<'a, T>(
+        &self,
+        mut guard: AsyncMutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> AsyncMutexGuard<'a, T> {
+        while condition(&mut guard) {
+            guard = self.wait(guard).await;
+        }
+        guard
+    }
+
+    /// Wake the oldest waiting task.
+    pub fn notify_one(&self) {
+        self.waiters.notify_one();
+    }
+
+    /// Wake every waiting task.
+    pub fn notify_all(&self) {
+        self.waiters.notify_all();
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Wait<'a> {
+    condvar: &'a Condvar,
+    waiter: Option<Arc<Waiter>>,
+}
+
+impl Future for Wait<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let waiter = this.waiter.as_ref().unwrap();
+        waiter.register(cx.waker());
+        if waiter.notified().is_none() {
+            return Poll::Pending;
+        }
+        this.waiter = None;
+        Poll::Ready(())
+    }
+}
+
+impl Drop for Wait<'_> {
+    fn drop(&mut self) {
+        if let Some(waiter) = self.waiter.take() {
+            if waiter.cancel() == Some(Notified::One) {
+                self.condvar.notify_one();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Duration};
+
+    use super::Condvar;
+    use crate::nonblocking::lock::AsyncMutex;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn producer_consumer() {
+        let state = Arc::new((AsyncMutex::new(Vec::new()), Condvar::new()));
+
+        let consumers: Vec<_> = (0..4)
+            .map(|_| {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    let (items, ready) = &*state;
+                    let mut sum = 0;
+                    for _ in 0..250 {
+                        let mut items =
+                            ready.wait_while(items.lock().await, |v| v.is_empty()).await;
+                        sum += items.pop().unwrap();
+                    }
+                    sum
+                })
+            })
+            .collect();
+
+        let (items, ready) = &*state;
+        for i in 0..1000 {
+            items.lock().await.push(i);
+            ready.notify_one();
+        }
+        let mut sum = 0;
+        for consumer in consumers {
+            sum += consumer.await.unwrap();
+        }
+        assert_eq!(sum, (0..1000).sum::<i32>());
+    }
+
+    #[tokio::test]
+    async fn notify_all_releases_everyone() {
+        let state = Arc::new((AsyncMutex::new(false), Condvar::new()));
+        let tasks: Vec<_> = (0..5)
+            .map(|_| {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    let (open, cond) = &*state;
+                    drop(cond.wait_while(open.lock().await, |open| !*open).await);
+                })
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let (open, cond) = &*state;
+        *open.lock().await = true;
+        cond.notify_all();
+        for task in tasks {
+            task.await.unwrap();
+        }
+    }
+}
@@ -0,0 +1,436 @@
+//! Contention and deadlock diagnostics for [`AsyncMutex`](super::lock::AsyncMutex), enabled by
+//! the `diagnostics` feature.
+//!
+//! Every mutex records who holds it and from where, how many tasks wait for it and how long
+//! they waited. Across all mutexes, the order tasks lock them in forms a graph: a cycle in it
+//! means two tasks can each end up waiting for a lock the other holds, even if no run has hung
+//! yet. See [`lock_order_cycles`].
+//!
+//! Locks are attributed to the tokio task taking them. Outside of tokio tasks, everything on a
+//! thread counts as one task, so an executor running several tasks per thread can see cycles
+//! that are none.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Display,
+    panic::Location,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        LazyLock, Mutex,
+    },
+    thread::{self, ThreadId},
+    time::Duration,
+};
+
+// Follows tokio's clock, so paused time in tests applies.
+use tokio::time::Instant;
+
+/// Identifies a mutex in diagnostics, in creation order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LockId(u64);
+
+impl Display for LockId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "lock #{}", self.0)
+    }
+}
+
+/// The tokio task a lock was taken on, or the thread outside of any task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TaskKey {
+    Task(tokio::task::Id),
+    Thread(ThreadId),
+}
+
+impl TaskKey {
+    fn current() -> Self {
+        match tokio::task::try_id() {
+            Some(id) => Self::Task(id),
+            None => Self::Thread(thread::current().id()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Holder {
+    pub task: TaskKey,
+    /// Where the lock was taken.
+    pub location: &'static Location<'static>,
+    pub since: Instant,
+}
+
+/// Wait times in buckets by powers of two: bucket `i` counts waits shorter than `2^i` µs, the
+/// last one everything longer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    buckets: [u64; BUCKETS],
+}
+
+const BUCKETS: usize = 32;
+
+impl Histogram {
+    fn bucket(wait: Duration) -> usize {
+        let micros = wait.as_micros().min(u64::MAX as u128) as u64;
+        (u64::BITS - micros.leading_zeros()).min(BUCKETS as u32 - 1) as usize
+    }
+
+    fn upper_bound(bucket: usize) -> Duration {
+        Duration::from_micros(1 << bucket)
+    }
+
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Non-empty buckets as their upper bound and count, shortest first.
+    pub fn buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, &n)| n > 0)
+            .map(|(i, &n)| (Self::upper_bound(i), n))
+    }
+
+    /// An upper bound on the `q`-quantile of the waits, with `q` in `0.0..=1.0`.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        let rank = (q.clamp(0.0, 1.0) * self.count() as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        self.buckets().find_map(|(bound, n)| {
+            seen += n;
+            (seen >= rank).then_some(bound)
+        })
+    }
+}
+
+/// A snapshot of one mutex's diagnostics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MutexDiagnostics {
+    pub id: LockId,
+    pub holder: Option<Holder>,
+    /// Tasks waiting for the lock.
+    pub waiting: usize,
+    pub wait_times: Histogram,
+}
+
+/// Locks held per task, and which locks have been waited for while holding which. Edges leave
+/// with their mutexes, cycles stay.
+#[derive(Default)]
+struct LockOrder {
+    held: HashMap<TaskKey, Vec<LockId>>,
+    edges: HashMap<LockId, HashSet<LockId>>,
+    cycles: Vec<Vec<LockId>>,
+}
+
+/// Cycles kept for [`lock_order_cycles`]; any found past this many are dropped.
+const MAX_CYCLES: usize = 256;
+
+static LOCK_ORDER: LazyLock<Mutex<LockOrder>> = LazyLock::new(Mutex::default);
+
+impl LockOrder {
+    /// Record that `to` is waited for while holding `from`, and report the cycle it closes.
+    fn add_edge(&mut self, from: LockId, to: LockId) {
+        if self.edges.get(&from).is_some_and(|to_| to_.contains(&to)) {
+            return;
+        }
+        if let Some(mut path) = self.path(to, from) {
+            path.insert(0, from);
+            path.pop();
+            let known = self.cycles.iter().any(|cycle| same_cycle(cycle, &path));
+            if !known && self.cycles.len() < MAX_CYCLES {
+                self.cycles.push(path);
+            }
+        }
+        self.edges.entry(from).or_default().insert(to);
+    }
+
+    fn remove_lock(&mut self, id: LockId) {
+        self.edges.remove(&id);
+        self.edges.retain(|_, to| {
+            to.remove(&id);
+            !to.is_empty()
+        });
+    }
+
+    /// The shortest path of edges from `from` to `to`, both included.
+    fn path(&self, from: LockId, to: LockId) -> Option<Vec<LockId>> {
+        let mut paths = VecDeque::from([vec![from]]);
+        let mut seen = HashSet::from([from]);
+        while let Some(path) = paths.pop_front() {
+            let last = *path.last().unwrap();
+            if last == to {
+                return Some(path);
+            }
+            for &next in self.edges.get(&last).into_iter().flatten() {
+                if seen.insert(next) {
+                    let mut path = path.clone();
+                    path.push(next);
+                    paths.push_back(path);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Whether `b` is `a` entered at another lock.
+fn same_cycle(a: &[LockId], b: &[LockId]) -> bool {
+    a.len() == b.len() && (0..a.len()).any(|i| a[i..].iter().chain(&a[..i]).eq(b))
+}
+
+/// Every lock-order cycle seen so far, each as the shortest chain of locks closed by the lock
+/// waited for last, in the order they are taken, up to the first 256.
+/// A task locking a mutex it already holds shows up as a cycle of one.
+pub fn lock_order_cycles() -> Vec<Vec<LockId>> {
+    LOCK_ORDER.lock().unwrap().cycles.clone()
+}
+
+/// The diagnostics kept in each mutex.
+pub(crate) struct Diagnostics {
+    id: LockId,
+    holder: Mutex<Option<Holder>>,
+    waiting: AtomicUsize,
+    wait_times: [AtomicU64; BUCKETS],
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self {
+            id: LockId(NEXT_ID.fetch_add(1, Ordering::Relaxed)),
+            holder: Mutex::new(None),
+            waiting: AtomicUsize::new(0),
+            wait_times: [const { AtomicU64::new(0) }; BUCKETS],
+        }
+    }
+
+    /// Start waiting for the lock, from `location`.
+    pub fn wait(&self, location: &'static Location<'static>) -> Wait<'_> {
+        let task = TaskKey::current();
+        let mut order = LOCK_ORDER.lock().unwrap();
+        for held in order.held.get(&task).cloned().unwrap_or_default() {
+            order.add_edge(held, self.id);
+        }
+        drop(order);
+
+        self.waiting.fetch_add(1, Ordering::Relaxed);
+        Wait {
+            diagnostics: self,
+            location,
+            start: Instant::now(),
+        }
+    }
+
+    /// Record the lock taken without waiting, as `try_lock` does.
+    pub fn acquired(&self, location: &'static Location<'static>) {
+        let task = TaskKey::current();
+        LOCK_ORDER
+            .lock()
+            .unwrap()
+            .held
+            .entry(task)
+            .or_default()
+            .push(self.id);
+        *self.holder.lock().unwrap() = Some(Holder {
+            task,
+            location,
+            since: Instant::now(),
+        });
+    }
+
+    pub fn released(&self) {
+        let Some(holder) = self.holder.lock().unwrap().take() else {
+            return;
+        };
+        let mut order = LOCK_ORDER.lock().unwrap();
+        if let Some(held) = order.held.get_mut(&holder.task) {
+            held.retain(|&id| id != self.id);
+            if held.is_empty() {
+                order.held.remove(&holder.task);
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> MutexDiagnostics {
+        let mut buckets = [0; BUCKETS];
+        for (count, bucket) in buckets.iter_mut().zip(&self.wait_times) {
+            *count = bucket.load(Ordering::Relaxed);
+        }
+        MutexDiagnostics {
+            id: self.id,
+            holder: *self.holder.lock().unwrap(),
+            waiting: self.waiting.load(Ordering::Relaxed),
+            wait_times: Histogram { buckets },
+        }
+    }
+}
+
+impl Drop for Diagnostics {
+    fn drop(&mut self) {
+        LOCK_ORDER.lock().unwrap().remove_lock(self.id);
+    }
+}
+
+/// A task counted as waiting for the lock until this is dropped.
+pub(crate) struct Wait<'a> {
+    diagnostics: &'a Diagnostics,
+    location: &'static Location<'static>,
+    start: Instant,
+}
+
+impl Wait<'_> {
+    pub fn acquired(self) {
+        let bucket = Histogram::bucket(self.start.elapsed());
+        self.diagnostics.wait_times[bucket].fetch_add(1, Ordering::Relaxed);
+        self.diagnostics.acquired(self.location);
+    }
+}
+
+impl Drop for Wait<'_> {
+    fn drop(&mut self) {
+        self.diagnostics.waiting.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, thread, time::Duration};
+
+    use super::{lock_order_cycles, TaskKey, LOCK_ORDER};
+    use crate::nonblocking::lock::AsyncMutex;
+
+    #[tokio::test]
+    async fn records_holder() {
+        let mutex = AsyncMutex::new(());
+        assert_eq!(mutex.diagnostics().holder, None);
+
+        let line = line!() + 1;
+        let guard = mutex.lock().await;
+        let holder = mutex.diagnostics().holder.unwrap();
+        // The test's body runs on its thread rather than in a task.
+        assert_eq!(holder.task, TaskKey::Thread(thread::current().id()));
+        assert_eq!(
+            (holder.location.file(), holder.location.line()),
+            (file!(), line)
+        );
+
+        drop(guard);
+        assert_eq!(mutex.diagnostics().holder, None);
+        let line = line!() + 1;
+        let _guard = mutex.try_lock().unwrap();
+        let holder = mutex.diagnostics().holder.unwrap();
+        assert_eq!(holder.location.line(), line);
+
+        let mutex = Arc::new(AsyncMutex::new(()));
+        let task = tokio::spawn({
+            let mutex = mutex.clone();
+            async move {
+                let _guard = mutex.lock().await;
+                (tokio::task::id(), mutex.diagnostics().holder.unwrap().task)
+            }
+        });
+        let (id, holder) = task.await.unwrap();
+        assert_eq!(holder, TaskKey::Task(id));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn counts_waiters_and_wait_times() {
+        let mutex = Arc::new(AsyncMutex::new(()));
+        let guard = mutex.lock().await;
+
+        let waiters: Vec<_> = (0..3)
+            .map(|_| {
+                let mutex = mutex.clone();
+                tokio::spawn(async move { drop(mutex.lock().await) })
+            })
+            .collect();
+        tokio::task::yield_now().await;
+        assert_eq!(mutex.diagnostics().waiting, 3);
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        drop(guard);
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+
+        let diagnostics = mutex.diagnostics();
+        assert_eq!(diagnostics.waiting, 0);
+        assert_eq!(diagnostics.wait_times.count(), 4);
+        assert!(diagnostics.wait_times.quantile(0.25).unwrap() <= Duration::from_micros(1));
+        assert!(diagnostics.wait_times.quantile(1.0).unwrap() >= Duration::from_millis(10));
+    }
+
+    #[tokio::test]
+    async fn reports_lock_order_cycles() {
+        let (a, b, c) = (
+            AsyncMutex::new(()),
+            AsyncMutex::new(()),
+            AsyncMutex::new(()),
+        );
+        let ids = [a.diagnostics().id, b.diagnostics().id, c.diagnostics().id];
+        // Other tests add cycles of their own.
+        let ours = || -> Vec<_> {
+            lock_order_cycles()
+                .into_iter()
+                .filter(|cycle| cycle.iter().all(|id| ids.contains(id)))
+                .collect()
+        };
+
+        {
+            let _a = a.lock().await;
+            let _b = b.lock().await;
+            let _c = c.lock().await;
+        }
+        assert!(ours().is_empty());
+
+        // Never hangs run by one task, but two tasks doing this and the above can deadlock.
+        {
+            let _c = c.lock().await;
+            let _a = a.lock().await;
+        }
+        assert_eq!(ours(), [vec![ids[2], ids[0]]]);
+    }
+
+    #[tokio::test]
+    async fn unpolled_locks_neither_wait_nor_order() {
+        let (a, b) = (AsyncMutex::new(()), AsyncMutex::new(()));
+        let (id_a, id_b) = (a.diagnostics().id, b.diagnostics().id);
+        let edge = |from, to| {
+            LOCK_ORDER
+                .lock()
+                .unwrap()
+                .edges
+                .get(&from)
+                .is_some_and(|edges| edges.contains(&to))
+        };
+
+        let _a = a.lock().await;
+        let lock_b = b.lock();
+        assert_eq!(b.diagnostics().waiting, 0);
+        assert!(!edge(id_a, id_b));
+        drop(lock_b.await);
+        assert!(edge(id_a, id_b));
+
+        drop(b);
+        assert!(!edge(id_a, id_b));
+        assert!(!LOCK_ORDER.lock().unwrap().edges.contains_key(&id_b));
+    }
+
+    #[tokio::test]
+    async fn keeps_each_cycle_once() {
+        let (a, b) = (AsyncMutex::new(()), AsyncMutex::new(()));
+        let ids = [a.diagnostics().id, b.diagnostics().id];
+        for _ in 0..3 {
+            {
+                let _a = a.lock().await;
+                let _b = b.lock().await;
+            }
+            let _b = b.lock().await;
+            let _a = a.lock().await;
+        }
+        let ours: Vec<_> = lock_order_cycles()
+            .into_iter()
+            .filter(|cycle| cycle.iter().all(|id| ids.contains(id)))
+            .collect();
+        assert_eq!(ours, [vec![ids[1], ids[0]]]);
+    }
+}
@@ -2,45 +2,92 @@ use std::{
     cell::UnsafeCell,
     future::Future,
     ops::{Deref, DerefMut},
-    sync::atomic::{AtomicBool, Ordering},
-    task::{Poll, Waker},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
 };
 
-use crate::data_structure::queue::Queue;
+#[cfg(feature = "diagnostics")]
+use std::panic::Location;
 
+#[cfg(feature = "diagnostics")]
+use super::diagnostics::{Diagnostics, MutexDiagnostics};
+use super::{
+    timeout::{timeout, timeout_with, TimeoutError},
+    waiter::{Waiter, WaiterList},
+};
+use crate::sync::atomic::{fence, AtomicBool, Ordering};
+
+/// An async lock whose waiters queue up in FIFO order. `unlock` wakes only the oldest waiter.
+///
+/// By default a woken waiter still races newcomers for the lock, which keeps it busy. A
+/// [fair](Self::new_fair) lock hands ownership straight to the oldest waiter instead, so nobody
+/// can overtake a task that is already queued.
 pub struct AsyncLock {
     inner: AtomicBool,
-    // Wrap each waker with `Option`, because `Queue` needs elem to be `Removeable`.
-    wakers: Queue<Option<Waker>>,
+    fair: bool,
+    waiters: WaiterList,
 }
 
 unsafe impl Sync for AsyncLock {}
 
-struct AsyncLockFut<'a> {
+pub struct AsyncLockFut<'a> {
     lock: &'a AsyncLock,
+    waiter: Option<Arc<Waiter>>,
 }
 
 impl<'a> Future for AsyncLockFut<'a> {
     type Output = AsyncLockGuard<'a>;
 
-    fn poll(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
-        if let Ok(false) =
-            self.lock
-                .inner
-                .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
-        {
-            Poll::Ready(AsyncLockGuard::new(self.lock))
-        } else {
-            self.lock.wakers.push(cx.waker().clone().into());
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let lock = this.lock;
 
-            // Ensure there is at least one task can wake up this task.
-            if !self.lock.inner.load(Ordering::Acquire) {
-                if let Some(waker) = self.lock.wakers.pop() {
-                    waker.unwrap().wake();
+        if let Some(waiter) = &this.waiter {
+            waiter.register(cx.waker());
+            match waiter.notified() {
+                None => return Poll::Pending,
+                Some(_) if lock.fair => {
+                    this.waiter = None;
+                    return Poll::Ready(AsyncLockGuard::new(lock));
                 }
+                // Woken to race for the lock again.
+                Some(_) => this.waiter = None,
             }
+        }
+
+        if let Some(guard) = lock.try_lock() {
+            return Poll::Ready(guard);
+        }
+
+        let waiter = Waiter::new(cx.waker());
+        lock.waiters.push(waiter.clone());
+        // Pairs with the fence in `release`: either it sees our waiter, or we see it unlocked.
+        fence(Ordering::SeqCst);
+
+        if let Some(guard) = lock.try_lock() {
+            // Nobody hands over or wakes for a lock that was free, so this can't miss anything.
+            waiter.cancel();
+            return Poll::Ready(guard);
+        }
+
+        this.waiter = Some(waiter);
+        Poll::Pending
+    }
+}
 
-            Poll::Pending
+impl Drop for AsyncLockFut<'_> {
+    fn drop(&mut self) {
+        if let Some(waiter) = self.waiter.take() {
+            if waiter.cancel().is_some() {
+                // Notified but dropped before noticing: pass it on rather than swallow it.
+                if self.lock.fair {
+                    self.lock.unlock();
+                } else {
+                    self.lock.notify_next();
+                }
+            }
         }
     }
 }
@@ -65,37 +112,113 @@ impl AsyncLock {
     pub fn new() -> Self {
         Self {
             inner: AtomicBool::new(false),
-            wakers: Queue::new(),
+            fair: false,
+            waiters: WaiterList::new(),
         }
     }
 
-    pub async fn lock(&self) -> AsyncLockGuard {
-        AsyncLockFut { lock: self }.await
+    /// A lock that `unlock` hands over to the oldest waiter, if any.
+    pub fn new_fair() -> Self {
+        Self {
+            fair: true,
+            ..Self::new()
+        }
+    }
+
+    pub fn lock(&self) -> AsyncLockFut<'_> {
+        AsyncLockFut {
+            lock: self,
+            waiter: None,
+        }
+    }
+
+    /// Like [`Self::lock`], giving up after `duration`. A waiter that times out leaves the queue,
+    /// and if the lock was just passed to it, passes it on.
+    ///
+    /// Timed by tokio, so this needs a tokio runtime. See [`Self::lock_timeout_with`] for others.
+    pub async fn lock_timeout(
+        &self,
+        duration: Duration,
+    ) -> Result<AsyncLockGuard<'_>, TimeoutError> {
+        timeout(duration, self.lock()).await
+    }
+
+    /// Like [`Self::lock_timeout`], timed by whatever `sleep` returns for `duration`.
+    pub async fn lock_timeout_with<S: Future<Output = ()>>(
+        &self,
+        duration: Duration,
+        sleep: impl FnOnce(Duration) -> S,
+    ) -> Result<AsyncLockGuard<'_>, TimeoutError> {
+        timeout_with(duration, sleep, self.lock()).await
+    }
+
+    pub fn try_lock(&self) -> Option<AsyncLockGuard<'_>> {
+        self.acquire().then(|| AsyncLockGuard::new(self))
+    }
+
+    fn acquire(&self) -> bool {
+        self.inner
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Wake the oldest waiter that is still waiting. Returns whether there was one.
+    fn notify_next(&self) -> bool {
+        self.waiters.notify_one()
+    }
+
+    fn release(&self) {
+        self.inner.store(false, Ordering::Release);
+        fence(Ordering::SeqCst);
     }
 
     pub fn unlock(&self) {
-        if let Ok(true) =
-            self.inner
-                .compare_exchange(true, false, Ordering::AcqRel, Ordering::Relaxed)
-        {
-            while let Some(waker) = self.wakers.pop() {
-                waker.unwrap().wake();
-            }
+        // A read-modify-write rather than a load: loom can't see the synchronization of a hand
+        // over through the waiter, and only this reads the latest value regardless.
+        let locked = if self.fair {
+            self.inner.fetch_or(true, Ordering::Acquire)
         } else {
-            panic!()
+            self.inner.swap(false, Ordering::AcqRel)
+        };
+        assert!(locked, "unlocking an AsyncLock that isn't locked");
+
+        if !self.fair {
+            fence(Ordering::SeqCst);
+            self.notify_next();
+            return;
+        }
+
+        loop {
+            if self.notify_next() {
+                // Handed over: the lock stays locked.
+                return;
+            }
+            self.release();
+            // A waiter queued after we looked sees the lock free, unless it's taken first.
+            if self.waiters.is_empty() || !self.acquire() {
+                return;
+            }
         }
     }
 }
 
+impl Default for AsyncLock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct AsyncMutex<T> {
     inner: UnsafeCell<T>,
     lock: AsyncLock,
+    #[cfg(feature = "diagnostics")]
+    diagnostics: Diagnostics,
 }
 
 unsafe impl<T> Sync for AsyncMutex<T> {}
 
 pub struct AsyncMutexGuard<'a, T> {
-    mutex: &'a AsyncMutex<T>,
+    pub(super) mutex: &'a AsyncMutex<T>,
     _guard: AsyncLockGuard<'a>,
 }
 
@@ -113,19 +236,402 @@ impl<T> DerefMut for AsyncMutexGuard<'_, T> {
     }
 }
 
+#[cfg(feature = "diagnostics")]
+impl<T> Drop for AsyncMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // Before unlocking, so the next holder isn't cleared instead.
+        self.mutex.diagnostics.released();
+    }
+}
+
 impl<T> AsyncMutex<T> {
     pub fn new(data: T) -> Self {
         Self {
             inner: UnsafeCell::new(data),
             lock: AsyncLock::new(),
+            #[cfg(feature = "diagnostics")]
+            diagnostics: Diagnostics::new(),
         }
     }
 
-    pub async fn lock(&self) -> AsyncMutexGuard<T> {
-        let lock_guard = self.lock.lock().await;
-        AsyncMutexGuard {
+    /// A mutex whose lock is handed to the oldest waiter. See [`AsyncLock::new_fair`].
+    pub fn new_fair(data: T) -> Self {
+        Self {
+            lock: AsyncLock::new_fair(),
+            ..Self::new(data)
+        }
+    }
+
+    /// With the `diagnostics` feature, the caller's location is recorded as the holder's.
+    // Not an `async fn`, which `track_caller` can't see the caller of.
+    #[allow(clippy::manual_async_fn)]
+    #[cfg_attr(feature = "diagnostics", track_caller)]
+    pub fn lock(&self) -> impl Future<Output = AsyncMutexGuard<'_, T>> {
+        #[cfg(feature = "diagnostics")]
+        let location = Location::caller();
+        async move {
+            // Only once polled, so that a future never awaited doesn't count as waiting.
+            #[cfg(feature = "diagnostics")]
+            let wait = self.diagnostics.wait(location);
+            let lock_guard = self.lock.lock().await;
+            #[cfg(feature = "diagnostics")]
+            wait.acquired();
+            AsyncMutexGuard {
+                mutex: self,
+                _guard: lock_guard,
+            }
+        }
+    }
+
+    /// Like [`Self::lock`], giving up after `duration`. See [`AsyncLock::lock_timeout`].
+    #[cfg_attr(feature = "diagnostics", track_caller)]
+    pub fn lock_timeout(
+        &self,
+        duration: Duration,
+    ) -> impl Future<Output = Result<AsyncMutexGuard<'_, T>, TimeoutError>> {
+        timeout(duration, self.lock())
+    }
+
+    /// Like [`Self::lock_timeout`], timed by whatever `sleep` returns for `duration`.
+    #[cfg_attr(feature = "diagnostics", track_caller)]
+    pub fn lock_timeout_with<S: Future<Output = ()>>(
+        &self,
+        duration: Duration,
+        sleep: impl FnOnce(Duration) -> S,
+    ) -> impl Future<Output = Result<AsyncMutexGuard<'_, T>, TimeoutError>> {
+        timeout_with(duration, sleep, self.lock())
+    }
+
+    #[cfg_attr(feature = "diagnostics", track_caller)]
+    pub fn try_lock(&self) -> Option<AsyncMutexGuard<'_, T>> {
+        let lock_guard = self.lock.try_lock()?;
+        #[cfg(feature = "diagnostics")]
+        self.diagnostics.acquired(Location::caller());
+        Some(AsyncMutexGuard {
             mutex: self,
             _guard: lock_guard,
+        })
+    }
+
+    /// Who holds the lock, how many wait for it and how long they waited so far.
+    #[cfg(feature = "diagnostics")]
+    pub fn diagnostics(&self) -> MutexDiagnostics {
+        self.diagnostics.snapshot()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        future::Future,
+        pin::pin,
+        rc::Rc,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Context, Poll, Wake, Waker},
+        time::Duration,
+    };
+
+    use super::{AsyncLock, AsyncMutex};
+    use crate::utils::{
+        executor::Executor,
+        linearizability::{check, Recorder, SemaphoreModel, SemaphoreOp::*},
+    };
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn try_lock() {
+        let lock = AsyncLock::new();
+        let guard = lock.try_lock().unwrap();
+        assert!(lock.try_lock().is_none());
+        drop(guard);
+        assert!(lock.try_lock().is_some());
+    }
+
+    #[test]
+    fn unlock_wakes_one_waiter() {
+        let lock = AsyncLock::new();
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let guard = lock.try_lock().unwrap();
+        let mut waiters: Vec<_> = (0..5).map(|_| Box::pin(lock.lock())).collect();
+        for waiter in &mut waiters {
+            assert!(waiter.as_mut().poll(&mut cx).is_pending());
+        }
+
+        drop(guard);
+        assert_eq!(counter.0.load(Ordering::Relaxed), 1);
+        let guard = match waiters[0].as_mut().poll(&mut cx) {
+            Poll::Ready(guard) => guard,
+            Poll::Pending => panic!("the oldest waiter should get the lock"),
+        };
+        drop(guard);
+        assert_eq!(counter.0.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn fair_lock_hands_over() {
+        let lock = AsyncLock::new_fair();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let guard = lock.try_lock().unwrap();
+        let mut waiter = pin!(lock.lock());
+        assert!(waiter.as_mut().poll(&mut cx).is_pending());
+
+        // The waiter owns the lock as soon as it's released, before it even runs.
+        drop(guard);
+        assert!(lock.try_lock().is_none());
+        assert!(matches!(waiter.as_mut().poll(&mut cx), Poll::Ready(_)));
+        assert!(lock.try_lock().is_some());
+    }
+
+    #[test]
+    fn cancelled_waiters() {
+        for lock in [AsyncLock::new(), AsyncLock::new_fair()] {
+            let mut cx = Context::from_waker(Waker::noop());
+            let guard = lock.try_lock().unwrap();
+            let mut waiters: Vec<_> = (0..3).map(|_| Box::pin(lock.lock())).collect();
+            for waiter in &mut waiters {
+                assert!(waiter.as_mut().poll(&mut cx).is_pending());
+            }
+
+            // Waiting: removed from the line. Notified: passes the notification on.
+            drop(waiters.remove(1));
+            drop(guard);
+            drop(waiters.remove(0));
+            assert!(matches!(waiters[0].as_mut().poll(&mut cx), Poll::Ready(_)));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn lock_timeout() {
+        for mutex in [AsyncMutex::new(0), AsyncMutex::new_fair(0)] {
+            let guard = mutex.lock().await;
+            let Err(err) = mutex.lock_timeout(Duration::from_millis(10)).await else {
+                panic!("locked a mutex that is held");
+            };
+            assert_eq!(err.duration(), Duration::from_millis(10));
+
+            // The waiter that gave up doesn't stand between the lock and the next one.
+            drop(guard);
+            assert!(mutex.lock_timeout(Duration::from_millis(10)).await.is_ok());
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn timed_out_waiters_pass_the_lock_on() {
+        for fair in [false, true] {
+            let mutex = Arc::new(if fair {
+                AsyncMutex::new_fair(0)
+            } else {
+                AsyncMutex::new(0)
+            });
+            let tasks: Vec<_> = (0..8)
+                .map(|i| {
+                    let mutex = mutex.clone();
+                    tokio::spawn(async move {
+                        for _ in 0..100 {
+                            let timeout = Duration::from_micros(i * 20);
+                            if let Ok(mut guard) = mutex.lock_timeout(timeout).await {
+                                *guard += 1;
+                                tokio::task::yield_now().await;
+                            }
+                        }
+                    })
+                })
+                .collect();
+            for task in tasks {
+                task.await.unwrap();
+            }
+            // A lock handed to a waiter just as it timed out would be stuck locked.
+            assert!(mutex.try_lock().is_some());
+        }
+    }
+
+    #[test]
+    fn deterministic_schedules() {
+        for seed in 0..200 {
+            for fair in [false, true] {
+                let executor = Executor::new(seed);
+                let mutex = Rc::new(if fair {
+                    AsyncMutex::new_fair(0)
+                } else {
+                    AsyncMutex::new(0)
+                });
+                for _ in 0..4 {
+                    let (mutex, handle) = (mutex.clone(), executor.handle());
+                    executor.spawn(async move {
+                        for _ in 0..5 {
+                            let mut guard = mutex.lock().await;
+                            let count = *guard;
+                            handle.sleep(Duration::from_millis(1)).await;
+                            *guard = count + 1;
+                        }
+                    });
+                }
+                // Times out on the same ticks the holders wake on, so in whichever order the seed
+                // picks.
+                let (impatient, handle) = (mutex.clone(), executor.handle());
+                executor.spawn(async move {
+                    for _ in 0..5 {
+                        let sleep = |duration| handle.sleep(duration);
+                        let _ = impatient.lock_timeout_with(Duration::from_millis(1), sleep).await;
+                    }
+                });
+
+                if let Err(err) = executor.run() {
+                    panic!("seed {seed}, fair: {fair}: {err}");
+                }
+                assert_eq!(*mutex.try_lock().unwrap(), 20);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn fair_lock_is_fifo() {
+        let mutex = Arc::new(AsyncMutex::new_fair(vec![]));
+        let guard = mutex.lock().await;
+
+        let tasks: Vec<_> = (0..10)
+            .map(|i| {
+                let mutex = mutex.clone();
+                tokio::spawn(async move { mutex.lock().await.push(i) })
+            })
+            .collect();
+        // Let every task queue up, in spawning order.
+        tokio::task::yield_now().await;
+        drop(guard);
+
+        // Newcomers can't overtake the queued tasks.
+        let late = mutex.lock().await;
+        assert_eq!(*late, (0..10).collect::<Vec<_>>());
+        drop(late);
+        for task in tasks {
+            task.await.unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn fair_mutex_excludes() {
+        let mutex = Arc::new(AsyncMutex::new_fair(0));
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let mutex = mutex.clone();
+                tokio::spawn(async move {
+                    for _ in 0..500 {
+                        let mut count = mutex.lock().await;
+                        *count += 1;
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(*mutex.lock().await, 4000);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn mutex_excludes() {
+        let mutex = Arc::new(AsyncMutex::new(0));
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let mutex = mutex.clone();
+                tokio::spawn(async move {
+                    for _ in 0..500 {
+                        let mut count = mutex.lock().await;
+                        let seen = *count;
+                        tokio::task::yield_now().await;
+                        *count = seen + 1;
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(*mutex.lock().await, 4000);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn history_is_linearizable() {
+        let lock = Arc::new(AsyncLock::new());
+        let recorder = Arc::new(Recorder::new());
+        let tasks: Vec<_> = (0..4)
+            .map(|_| {
+                let (lock, recorder) = (lock.clone(), recorder.clone());
+                tokio::spawn(async move {
+                    for _ in 0..25 {
+                        let invoke = recorder.invoke();
+                        let guard = lock.lock().await;
+                        recorder.respond(invoke, Down, ());
+                        tokio::task::yield_now().await;
+                        recorder.record(Up, || drop(guard));
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let history = recorder.take();
+        assert!(check(SemaphoreModel(1), &history).is_some(), "{history:?}");
+    }
+
+    #[test]
+    #[should_panic]
+    fn unlock_unlocked() {
+        AsyncLock::new().unlock();
+    }
+
+    /// Run with `RUSTFLAGS="--cfg loom" cargo test --release --lib loom`.
+    #[cfg(loom)]
+    mod loom_model {
+        use loom::{future::block_on, sync::Arc, thread};
+
+        use super::super::AsyncLock;
+        use crate::utils::linearizability::{check, Recorder, SemaphoreModel, SemaphoreOp::*};
+
+        /// Besides linearizability, loom reports a deadlock if a waiter is never woken.
+        #[test]
+        fn lock_unlock() {
+            crate::sync::model(|| run(AsyncLock::new()));
+            crate::sync::model(|| run(AsyncLock::new_fair()));
+        }
+
+        fn run(lock: AsyncLock) {
+            let lock = Arc::new(lock);
+            let recorder = Arc::new(Recorder::new());
+
+            let handles: Vec<_> = (0..2)
+                .map(|_| {
+                    let (lock, recorder) = (lock.clone(), recorder.clone());
+                    thread::spawn(move || {
+                        let invoke = recorder.invoke();
+                        let guard = block_on(lock.lock());
+                        recorder.respond(invoke, Down, ());
+                        recorder.record(Up, || drop(guard));
+                    })
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+
+            let history = recorder.take();
+            assert!(check(SemaphoreModel(1), &history).is_some(), "{history:?}");
         }
     }
 }
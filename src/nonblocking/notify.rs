@@ -0,0 +1,232 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use super::waiter::{Notified as How, Waiter, WaiterList};
+use crate::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+
+/// Wakes tasks waiting for an event, without any data attached.
+///
+/// [`notify_one`](Self::notify_one) wakes the oldest waiter, or, if nobody waits, stores a single
+/// permit that the next [`notified`](Self::notified) consumes right away.
+/// [`notify_waiters`](Self::notify_waiters) wakes everyone waiting at the time and stores nothing.
+pub struct Notify {
+    permit: AtomicBool,
+    /// Bumped by every `notify_waiters`.
+    generation: AtomicUsize,
+    waiters: WaiterList,
+}
+
+impl Notify {
+    pub fn new() -> Self {
+        Self {
+            permit: AtomicBool::new(false),
+            generation: AtomicUsize::new(0),
+            waiters: WaiterList::new(),
+        }
+    }
+
+    /// Wait for a notification. The future counts as waiting from its creation on, so a
+    /// `notify_waiters` after `notified()` but before the first poll still completes it.
+    pub fn notified(&self) -> Notified<'_> {
+        Notified {
+            notify: self,
+            generation: self.generation.load(Ordering::Acquire),
+            waiter: None,
+        }
+    }
+
+    pub fn notify_one(&self) {
+        loop {
+            if self.waiters.notify_one() {
+                return;
+            }
+            self.permit.store(true, Ordering::Release);
+            // Pairs with the fence in `Notified::poll`: either a waiter queued meanwhile sees the
+            // permit, or we see the waiter and take the permit back to wake it instead.
+            fence(Ordering::SeqCst);
+            if self.waiters.is_empty() || !self.take_permit() {
+                return;
+            }
+        }
+    }
+
+    pub fn notify_waiters(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        fence(Ordering::SeqCst);
+        self.waiters.notify_all();
+    }
+
+    fn take_permit(&self) -> bool {
+        self.permit
+            .compare_exchange(true, false, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    generation: usize,
+    waiter: Option<Arc<Waiter>>,
+}
+
+impl Notified<'_> {
+    /// Whether a `notify_waiters` happened since creation, or a permit could be taken.
+    fn is_notified(&self) -> bool {
+        self.notify.generation.load(Ordering::Acquire) != self.generation
+            || self.notify.take_permit()
+    }
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let notify = this.notify;
+
+        if let Some(waiter) = &this.waiter {
+            waiter.register(cx.waker());
+            if waiter.notified().is_none() {
+                return Poll::Pending;
+            }
+            this.waiter = None;
+            return Poll::Ready(());
+        }
+
+        if this.is_notified() {
+            return Poll::Ready(());
+        }
+
+        let waiter = Waiter::new(cx.waker());
+        notify.waiters.push(waiter.clone());
+        // Pairs with the fences in `notify_one` and `notify_waiters`.
+        fence(Ordering::SeqCst);
+
+        if this.is_notified() {
+            if waiter.cancel() == Some(How::One) {
+                // Notified twice over: one of them belongs to someone else.
+                notify.notify_one();
+            }
+            return Poll::Ready(());
+        }
+
+        this.waiter = Some(waiter);
+        Poll::Pending
+    }
+}
+
+impl Drop for Notified<'_> {
+    fn drop(&mut self) {
+        if let Some(waiter) = self.waiter.take() {
+            // Dropped after `notify_one` picked it: pass the notification on rather than lose it.
+            if waiter.cancel() == Some(How::One) {
+                self.notify.notify_one();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        future::Future,
+        pin::pin,
+        sync::Arc,
+        task::{Context, Waker},
+    };
+
+    use super::Notify;
+
+    #[test]
+    fn permit_is_stored_once() {
+        let notify = Notify::new();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        notify.notify_one();
+        notify.notify_one();
+        assert!(pin!(notify.notified()).poll(&mut cx).is_ready());
+        assert!(pin!(notify.notified()).poll(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn notify_waiters_wakes_everyone_present() {
+        let notify = Notify::new();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut polled = pin!(notify.notified());
+        assert!(polled.as_mut().poll(&mut cx).is_pending());
+        // Not polled yet, but created before the call.
+        let created = pin!(notify.notified());
+        notify.notify_waiters();
+        assert!(polled.poll(&mut cx).is_ready());
+        assert!(created.poll(&mut cx).is_ready());
+
+        // Nothing is stored for later.
+        assert!(pin!(notify.notified()).poll(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn dropped_waiter_passes_notification_on() {
+        let notify = Notify::new();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut first = Box::pin(notify.notified());
+        let mut second = Box::pin(notify.notified());
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+
+        notify.notify_one();
+        drop(first);
+        assert!(second.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn ping_pong() {
+        let (ping, pong) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+        let task = {
+            let (ping, pong) = (ping.clone(), pong.clone());
+            tokio::spawn(async move {
+                for _ in 0..1000 {
+                    ping.notified().await;
+                    pong.notify_one();
+                }
+            })
+        };
+        for _ in 0..1000 {
+            ping.notify_one();
+            pong.notified().await;
+        }
+        task.await.unwrap();
+    }
+
+    #[cfg(loom)]
+    mod loom_model {
+        use loom::{future::block_on, sync::Arc, thread};
+
+        use super::super::Notify;
+
+        /// loom reports a deadlock if the notification is lost.
+        #[test]
+        fn notify_one() {
+            crate::sync::model(|| {
+                let notify = Arc::new(Notify::new());
+                let handle = {
+                    let notify = notify.clone();
+                    thread::spawn(move || notify.notify_one())
+                };
+                block_on(notify.notified());
+                handle.join().unwrap();
+            });
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use std::{
+    cell::UnsafeCell,
+    future::Future,
+    mem::MaybeUninit,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use super::waiter::{Waiter, WaiterList};
+use crate::sync::atomic::{fence, AtomicU8, Ordering};
+
+const EMPTY: u8 = 0;
+const RUNNING: u8 = 1;
+const READY: u8 = 2;
+
+/// A cell written at most once, by whichever task first runs its async initializer.
+///
+/// Concurrent [`get_or_init`](Self::get_or_init) calls wait for that initializer instead of
+/// running their own. If it's cancelled or panics, a waiting task takes over.
+pub struct OnceCell<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+    waiters: WaiterList,
+}
+
+unsafe impl<T: Send> Send for OnceCell<T> {}
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    pub fn new() -> Self {
+        Self {
+            state: AtomicU8::new(EMPTY),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+            waiters: WaiterList::new(),
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        (self.state.load(Ordering::Acquire) == READY)
+            .then(|| unsafe { (*self.value.get()).assume_init_ref() })
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        (self.state.load(Ordering::Relaxed) == READY)
+            .then(|| unsafe { self.value.get_mut().assume_init_mut() })
+    }
+
+    pub fn into_inner(mut self) -> Option<T> {
+        let ready = self.state.swap(EMPTY, Ordering::Relaxed) == READY;
+        ready.then(|| unsafe { self.value.get_mut().assume_init_read() })
+    }
+
+    /// Set the value unless the cell is set or being initialized, otherwise return it.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        if !self.start() {
+            return Err(value);
+        }
+        self.finish(value);
+        Ok(())
+    }
+
+    /// The value, running `init` first if the cell is empty.
+    pub async fn get_or_init<F, Fut>(&self, init: F) -> &T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        match self
+            .get_or_try_init(|| async { Ok::<_, ()>(init().await) })
+            .await
+        {
+            Ok(value) => value,
+            Err(()) => unreachable!(),
+        }
+    }
+
+    /// Like [`get_or_init`](Self::get_or_init), but an initializer that fails leaves the cell
+    /// empty for the next one.
+    pub async fn get_or_try_init<F, Fut, E>(&self, init: F) -> Result<&T, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        loop {
+            if let Some(value) = self.get() {
+                return Ok(value);
+            }
+            if self.start() {
+                break;
+            }
+            Wait {
+                cell: self,
+                waiter: None,
+            }
+            .await;
+        }
+
+        let reset = Reset(self);
+        let value = init().await?;
+        std::mem::forget(reset);
+        self.finish(value);
+        Ok(unsafe { (*self.value.get()).assume_init_ref() })
+    }
+
+    fn start(&self) -> bool {
+        self.state
+            .compare_exchange(EMPTY, RUNNING, Ordering::Acquire, Ordering::Acquire)
+            .is_ok()
+    }
+
+    fn finish(&self, value: T) {
+        unsafe { (*self.value.get()).write(value) };
+        self.state.store(READY, Ordering::Release);
+        self.wake_all();
+    }
+
+    fn wake_all(&self) {
+        // Pairs with the fence in `Wait::poll`: either waiters see the new state, or we see them.
+        fence(Ordering::SeqCst);
+        self.waiters.notify_all();
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for OnceCell<T> {
+    fn drop(&mut self) {
+        if self.state.load(Ordering::Relaxed) == READY {
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+/// Empties the cell again if its initializer doesn't complete.
+struct Reset<'a, T>(&'a OnceCell<T>);
+
+impl<T> Drop for Reset<'_, T> {
+    fn drop(&mut self) {
+        self.0.state.store(EMPTY, Ordering::Release);
+        self.0.wake_all();
+    }
+}
+
+/// Waits for the cell to stop running an initializer.
+struct Wait<'a, T> {
+    cell: &'a OnceCell<T>,
+    waiter: Option<Arc<Waiter>>,
+}
+
+impl<T> Future for Wait<'_, T> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        if let Some(waiter) = &this.waiter {
+            waiter.register(cx.waker());
+            return match waiter.notified() {
+                Some(_) => Poll::Ready(()),
+                None => Poll::Pending,
+            };
+        }
+
+        let waiter = Waiter::new(cx.waker());
+        this.cell.waiters.push(waiter.clone());
+        fence(Ordering::SeqCst);
+        if this.cell.state.load(Ordering::Acquire) != RUNNING {
+            waiter.cancel();
+            return Poll::Ready(());
+        }
+        this.waiter = Some(waiter);
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Wait<'_, T> {
+    fn drop(&mut self) {
+        if let Some(waiter) = self.waiter.take() {
+            waiter.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        future::Future,
+        pin::pin,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Context, Poll, Waker},
+        time::Duration,
+    };
+
+    use super::OnceCell;
+
+    #[test]
+    fn set_and_get() {
+        let mut cell = OnceCell::new();
+        assert_eq!(cell.get(), None);
+        assert_eq!(cell.set(1), Ok(()));
+        assert_eq!(cell.set(2), Err(2));
+        assert_eq!(cell.get(), Some(&1));
+        *cell.get_mut().unwrap() += 1;
+        assert_eq!(cell.into_inner(), Some(2));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn initializes_once() {
+        let cell = Arc::new(OnceCell::new());
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..16)
+            .map(|i| {
+                let (cell, runs) = (cell.clone(), runs.clone());
+                tokio::spawn(async move {
+                    *cell
+                        .get_or_init(|| async {
+                            runs.fetch_add(1, Ordering::Relaxed);
+                            tokio::time::sleep(Duration::from_millis(10)).await;
+                            i
+                        })
+                        .await
+                })
+            })
+            .collect();
+        let mut values = vec![];
+        for task in tasks {
+            values.push(task.await.unwrap());
+        }
+        assert_eq!(runs.load(Ordering::Relaxed), 1);
+        assert!(values.iter().all(|v| *v == values[0]));
+    }
+
+    #[test]
+    fn cancelled_initializer_hands_over() {
+        let cell = OnceCell::new();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut first = Box::pin(cell.get_or_init(std::future::pending::<i32>));
+        let mut second = pin!(cell.get_or_init(|| async { 2 }));
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+
+        drop(first);
+        assert_eq!(second.poll(&mut cx), Poll::Ready(&2));
+        assert_eq!(cell.get(), Some(&2));
+    }
+
+    #[tokio::test]
+    async fn failed_initializer_leaves_cell_empty() {
+        let cell = OnceCell::new();
+        assert_eq!(
+            cell.get_or_try_init(|| async { Err("nope") }).await,
+            Err("nope")
+        );
+        assert_eq!(cell.get(), None);
+        assert_eq!(
+            cell.get_or_try_init(|| async { Ok::<_, ()>(3) }).await,
+            Ok(&3)
+        );
+    }
+}
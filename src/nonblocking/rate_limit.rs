@@ -0,0 +1,303 @@
+//! Rate limiters on [`AsyncSemaphore`] permits, each permit standing for one unit of work.
+//! Units are taken for good when acquired, and come back on the tokio timer, so the limiters need
+//! a tokio runtime.
+
+use std::{fmt::Display, sync::Arc, time::Duration};
+
+use tokio::{
+    task::JoinHandle,
+    time::{self, Instant},
+};
+
+use super::semaphore::AsyncSemaphore;
+
+/// More units were asked for at once than the limiter ever holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExceedsCapacity {
+    pub requested: usize,
+    pub capacity: usize,
+}
+
+impl Display for ExceedsCapacity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "requested {} units from a rate limiter holding at most {}",
+            self.requested, self.capacity
+        )
+    }
+}
+
+impl std::error::Error for ExceedsCapacity {}
+
+/// Take `n` permits for good, once there are enough.
+async fn take(
+    semaphore: &AsyncSemaphore,
+    n: usize,
+    capacity: usize,
+) -> Result<(), ExceedsCapacity> {
+    if n > capacity {
+        return Err(ExceedsCapacity {
+            requested: n,
+            capacity,
+        });
+    }
+    semaphore
+        .acquire_many(n)
+        .await
+        .expect("rate limiters never close their semaphore")
+        .forget();
+    Ok(())
+}
+
+/// A token bucket: holds up to `burst` tokens, starts full, and gets `tokens` more every
+/// `every`. Each unit of work takes a token.
+///
+/// `tokens` and `every` have to be positive, or the bucket would never refill.
+///
+/// Large requests can be overtaken by smaller ones while they wait. See [`AsyncSemaphore`].
+pub struct TokenBucket {
+    semaphore: Arc<AsyncSemaphore>,
+    burst: usize,
+    refill: JoinHandle<()>,
+}
+
+impl TokenBucket {
+    pub fn new(burst: usize, tokens: usize, every: Duration) -> Self {
+        assert!(tokens > 0, "TokenBucket needs to refill a positive number of tokens");
+        assert!(every > Duration::ZERO, "TokenBucket needs a positive refill period");
+        let semaphore = Arc::new(AsyncSemaphore::new(burst));
+        let refill = tokio::spawn({
+            let semaphore = Arc::downgrade(&semaphore);
+            async move {
+                let mut interval = time::interval_at(Instant::now() + every, every);
+                loop {
+                    interval.tick().await;
+                    let Some(semaphore) = semaphore.upgrade() else {
+                        return;
+                    };
+                    // Only this adds permits, so the bucket can't overflow in between.
+                    let room = burst.saturating_sub(semaphore.available_permits());
+                    semaphore.add_permits(tokens.min(room));
+                }
+            }
+        });
+        Self {
+            semaphore,
+            burst,
+            refill,
+        }
+    }
+
+    /// A leaky bucket: lets one unit through every `every`, evenly spaced, without bursts.
+    pub fn leaky(every: Duration) -> Self {
+        Self::new(1, 1, every)
+    }
+
+    pub fn burst(&self) -> usize {
+        self.burst
+    }
+
+    /// Tokens in the bucket right now.
+    pub fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+
+    /// Wait for `n` tokens and take them.
+    pub async fn acquire(&self, n: usize) -> Result<(), ExceedsCapacity> {
+        take(&self.semaphore, n, self.burst).await
+    }
+
+    /// Take `n` tokens if there are as many right now.
+    pub fn try_acquire(&self, n: usize) -> bool {
+        self.semaphore
+            .try_acquire_many(n)
+            .map(|permits| permits.forget())
+            .is_ok()
+    }
+}
+
+impl Drop for TokenBucket {
+    fn drop(&mut self) {
+        self.refill.abort();
+    }
+}
+
+/// Lets at most `limit` units through in any `window` of time: each unit taken comes back once
+/// `window` has passed since.
+pub struct SlidingWindow {
+    semaphore: Arc<AsyncSemaphore>,
+    limit: usize,
+    window: Duration,
+}
+
+impl SlidingWindow {
+    pub fn new(limit: usize, window: Duration) -> Self {
+        Self {
+            semaphore: Arc::new(AsyncSemaphore::new(limit)),
+            limit,
+            window,
+        }
+    }
+
+    /// Units that could go through right now.
+    pub fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+
+    /// Wait until `n` more units fit in the window, and take them.
+    pub async fn acquire(&self, n: usize) -> Result<(), ExceedsCapacity> {
+        take(&self.semaphore, n, self.limit).await?;
+        self.expire(n);
+        Ok(())
+    }
+
+    /// Take `n` units if they fit in the window right now.
+    pub fn try_acquire(&self, n: usize) -> bool {
+        let Ok(permits) = self.semaphore.try_acquire_many(n) else {
+            return false;
+        };
+        permits.forget();
+        self.expire(n);
+        true
+    }
+
+    /// Give `n` units back once the window has slid past them.
+    fn expire(&self, n: usize) {
+        let (semaphore, window) = (self.semaphore.clone(), self.window);
+        tokio::spawn(async move {
+            time::sleep(window).await;
+            semaphore.add_permits(n);
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Duration};
+
+    use tokio::time::{self, Instant};
+
+    use super::{ExceedsCapacity, SlidingWindow, TokenBucket};
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[tokio::test(start_paused = true)]
+    async fn token_bucket() {
+        let start = Instant::now();
+        let bucket = TokenBucket::new(5, 1, 100 * MS);
+
+        // A full burst right away, then one token per 100ms.
+        bucket.acquire(5).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        assert!(!bucket.try_acquire(1));
+        bucket.acquire(1).await.unwrap();
+        assert_eq!(start.elapsed(), 100 * MS);
+        bucket.acquire(3).await.unwrap();
+        assert_eq!(start.elapsed(), 400 * MS);
+
+        assert_eq!(
+            bucket.acquire(6).await,
+            Err(ExceedsCapacity {
+                requested: 6,
+                capacity: 5
+            })
+        );
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "positive number of tokens")]
+    async fn no_refill_tokens() {
+        TokenBucket::new(5, 0, 100 * MS);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "positive refill period")]
+    async fn no_refill_period() {
+        TokenBucket::new(5, 1, Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn refills_up_to_burst() {
+        let bucket = TokenBucket::new(5, 2, 100 * MS);
+        bucket.acquire(5).await.unwrap();
+        time::sleep(150 * MS).await;
+        assert_eq!(bucket.available(), 2);
+        time::sleep(10_000 * MS).await;
+        assert_eq!(bucket.available(), 5);
+        assert!(bucket.try_acquire(5));
+        assert!(!bucket.try_acquire(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn leaky_bucket_spaces_out_units() {
+        let start = Instant::now();
+        let bucket = Arc::new(TokenBucket::leaky(100 * MS));
+
+        let tasks: Vec<_> = (0..5)
+            .map(|_| {
+                let bucket = bucket.clone();
+                tokio::spawn(async move {
+                    bucket.acquire(1).await.unwrap();
+                    start.elapsed()
+                })
+            })
+            .collect();
+        let mut times = vec![];
+        for task in tasks {
+            times.push(task.await.unwrap());
+        }
+        times.sort();
+        assert_eq!(times, [0, 100, 200, 300, 400].map(|ms| ms * MS));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sliding_window() {
+        let start = Instant::now();
+        let window = SlidingWindow::new(3, 1000 * MS);
+
+        window.acquire(2).await.unwrap();
+        time::sleep(400 * MS).await;
+        assert!(window.try_acquire(1));
+        assert_eq!(window.available(), 0);
+
+        // The first two come back a window after they were taken, the third 400ms later.
+        window.acquire(2).await.unwrap();
+        assert_eq!(start.elapsed(), 1000 * MS);
+        window.acquire(1).await.unwrap();
+        assert_eq!(start.elapsed(), 1400 * MS);
+        assert!(window.acquire(4).await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sliding_window_bounds_every_window() {
+        const LIMIT: usize = 4;
+        let start = Instant::now();
+        let window = Arc::new(SlidingWindow::new(LIMIT, 1000 * MS));
+
+        let tasks: Vec<_> = (0..6)
+            .map(|i| {
+                let window = window.clone();
+                tokio::spawn(async move {
+                    let mut times = vec![];
+                    for _ in 0..5 {
+                        time::sleep(i * 70 * MS).await;
+                        window.acquire(1).await.unwrap();
+                        times.push(start.elapsed());
+                    }
+                    times
+                })
+            })
+            .collect();
+        let mut times = vec![];
+        for task in tasks {
+            times.extend(task.await.unwrap());
+        }
+        times.sort();
+        assert_eq!(times.len(), 30);
+        // Any LIMIT + 1 consecutive units span at least a window.
+        assert!(times
+            .windows(LIMIT + 1)
+            .all(|w| w[LIMIT] - w[0] >= 1000 * MS));
+    }
+}
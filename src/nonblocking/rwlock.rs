@@ -0,0 +1,537 @@
+use std::{
+    cell::UnsafeCell,
+    future::Future,
+    mem,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use super::waiter::{Waiter, WaiterList};
+use crate::sync::atomic::{fence, AtomicUsize, Ordering};
+
+/// Held for writing.
+const WRITER: usize = 1;
+/// Held by an upgradable reader.
+const UPGRADER: usize = 1 << 1;
+/// One plain reader; the count takes the remaining bits.
+const READER: usize = 1 << 2;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Upgradable,
+    Write,
+    /// From upgradable read to write.
+    Upgrade,
+}
+
+impl Access {
+    fn is_writer(self) -> bool {
+        matches!(self, Access::Write | Access::Upgrade)
+    }
+}
+
+/// An async reader-writer lock preferring writers: once a writer waits, new readers queue up
+/// behind it, so a steady stream of readers can't starve writers.
+///
+/// An upgradable read shares the lock with plain readers but excludes writers and other
+/// upgradable reads, so it can later become a write without anybody writing in between.
+pub struct AsyncRwLock<T> {
+    data: UnsafeCell<T>,
+    state: AtomicUsize,
+    /// Writers (and upgrades) waiting for the lock. New readers wait while this is nonzero.
+    writers_waiting: AtomicUsize,
+    /// Plain and upgradable readers.
+    readers: WaiterList,
+    writers: WaiterList,
+    /// The upgradable reader waiting to write, kept apart since only it can use `UPGRADER` alone.
+    upgrade: WaiterList,
+}
+
+unsafe impl<T: Send> Send for AsyncRwLock<T> {}
+unsafe impl<T: Send + Sync> Sync for AsyncRwLock<T> {}
+
+impl<T> AsyncRwLock<T> {
+    pub fn new(data: T) -> Self {
+        Self {
+            data: UnsafeCell::new(data),
+            state: AtomicUsize::new(0),
+            writers_waiting: AtomicUsize::new(0),
+            readers: WaiterList::new(),
+            writers: WaiterList::new(),
+            upgrade: WaiterList::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn try_acquire(&self, access: Access) -> bool {
+        let writer_waiting = self.writers_waiting.load(Ordering::Acquire) > 0;
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            let new = match access {
+                Access::Read if state & WRITER == 0 && !writer_waiting => state + READER,
+                Access::Upgradable if state & (WRITER | UPGRADER) == 0 && !writer_waiting => {
+                    state | UPGRADER
+                }
+                Access::Write if state == 0 => WRITER,
+                Access::Upgrade if state == UPGRADER => WRITER,
+                _ => return false,
+            };
+
+            match self
+                .state
+                .compare_exchange_weak(state, new, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => return true,
+                Err(s) => state = s,
+            }
+        }
+    }
+
+    fn acquire(&self, access: Access) -> Acquire<'_, T> {
+        Acquire {
+            lock: self,
+            access,
+            counted: false,
+            waiter: None,
+        }
+    }
+
+    fn waiters(&self, access: Access) -> &WaiterList {
+        match access {
+            Access::Read | Access::Upgradable => &self.readers,
+            Access::Write => &self.writers,
+            Access::Upgrade => &self.upgrade,
+        }
+    }
+
+    fn release(&self, bits: usize) {
+        self.state.fetch_sub(bits, Ordering::Release);
+        self.notify();
+    }
+
+    /// Wake whoever the lock as it is now lets in: the upgrade once only its upgradable read is
+    /// left, else one writer once it's free, else every reader unless a writer is waiting.
+    fn notify(&self) {
+        // Pairs with the fence in `Acquire::poll`: either we see its waiter, or it sees the
+        // lock released.
+        fence(Ordering::SeqCst);
+        let state = self.state.load(Ordering::Relaxed);
+        if state == UPGRADER && self.upgrade.notify_one() {
+            return;
+        }
+        if state == 0 && self.writers.notify_one() {
+            return;
+        }
+        if state & WRITER == 0 && self.writers_waiting.load(Ordering::Relaxed) == 0 {
+            self.readers.notify_all();
+        }
+    }
+
+    pub async fn read(&self) -> AsyncRwLockReadGuard<'_, T> {
+        self.acquire(Access::Read).await;
+        AsyncRwLockReadGuard { lock: self }
+    }
+
+    pub fn try_read(&self) -> Option<AsyncRwLockReadGuard<'_, T>> {
+        self.try_acquire(Access::Read)
+            .then(|| AsyncRwLockReadGuard { lock: self })
+    }
+
+    pub async fn upgradable_read(&self) -> AsyncRwLockUpgradableReadGuard<'_, T> {
+        self.acquire(Access::Upgradable).await;
+        AsyncRwLockUpgradableReadGuard { lock: self }
+    }
+
+    pub fn try_upgradable_read(&self) -> Option<AsyncRwLockUpgradableReadGuard<'_, T>> {
+        self.try_acquire(Access::Upgradable)
+            .then(|| AsyncRwLockUpgradableReadGuard { lock: self })
+    }
+
+    pub async fn write(&self) -> AsyncRwLockWriteGuard<'_, T> {
+        self.acquire(Access::Write).await;
+        AsyncRwLockWriteGuard { lock: self }
+    }
+
+    pub fn try_write(&self) -> Option<AsyncRwLockWriteGuard<'_, T>> {
+        self.try_acquire(Access::Write)
+            .then(|| AsyncRwLockWriteGuard { lock: self })
+    }
+}
+
+impl<T: Default> Default for AsyncRwLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+struct Acquire<'a, T> {
+    lock: &'a AsyncRwLock<T>,
+    access: Access,
+    /// Whether we're counted in `writers_waiting`.
+    counted: bool,
+    waiter: Option<Arc<Waiter>>,
+}
+
+impl<T> Acquire<'_, T> {
+    fn uncount(&mut self) {
+        if self.counted {
+            self.counted = false;
+            if self.lock.writers_waiting.fetch_sub(1, Ordering::AcqRel) == 1 {
+                // Readers may have been waiting on us alone.
+                self.lock.notify();
+            }
+        }
+    }
+
+    /// Leave the line. A notification that already came is passed on rather than swallowed.
+    fn cancel(&mut self, waiter: Arc<Waiter>) {
+        if waiter.cancel().is_some() {
+            self.lock.notify();
+        }
+    }
+}
+
+impl<T> Future for Acquire<'_, T> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        let lock = this.lock;
+
+        if let Some(waiter) = &this.waiter {
+            waiter.register(cx.waker());
+            if waiter.notified().is_none() {
+                return Poll::Pending;
+            }
+            // Woken to try again.
+            this.waiter = None;
+        }
+
+        if lock.try_acquire(this.access) {
+            this.uncount();
+            return Poll::Ready(());
+        }
+
+        if this.access.is_writer() && !this.counted {
+            this.counted = true;
+            lock.writers_waiting.fetch_add(1, Ordering::AcqRel);
+        }
+        let waiter = Waiter::new(cx.waker());
+        lock.waiters(this.access).push(waiter.clone());
+        fence(Ordering::SeqCst);
+
+        if lock.try_acquire(this.access) {
+            this.cancel(waiter);
+            this.uncount();
+            return Poll::Ready(());
+        }
+
+        this.waiter = Some(waiter);
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Acquire<'_, T> {
+    fn drop(&mut self) {
+        if let Some(waiter) = self.waiter.take() {
+            self.cancel(waiter);
+        }
+        // A writer given up on must not keep holding readers back.
+        self.uncount();
+    }
+}
+
+pub struct AsyncRwLockReadGuard<'a, T> {
+    lock: &'a AsyncRwLock<T>,
+}
+
+impl<T> Deref for AsyncRwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for AsyncRwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.release(READER);
+    }
+}
+
+pub struct AsyncRwLockUpgradableReadGuard<'a, T> {
+    lock: &'a AsyncRwLock<T>,
+}
+
+impl<'a, T> AsyncRwLockUpgradableReadGuard<'a, T> {
+    /// Wait for the plain readers to leave, then write. New readers are held back meanwhile.
+    ///
+    /// Dropping the returned future before it completes gives up the upgradable read as well.
+    pub async fn upgrade(self) -> AsyncRwLockWriteGuard<'a, T> {
+        let lock = self.lock;
+        lock.acquire(Access::Upgrade).await;
+        mem::forget(self);
+        AsyncRwLockWriteGuard { lock }
+    }
+
+    pub fn try_upgrade(self) -> Result<AsyncRwLockWriteGuard<'a, T>, Self> {
+        if self.lock.try_acquire(Access::Upgrade) {
+            let lock = self.lock;
+            mem::forget(self);
+            Ok(AsyncRwLockWriteGuard { lock })
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl<T> Deref for AsyncRwLockUpgradableReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for AsyncRwLockUpgradableReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.release(UPGRADER);
+    }
+}
+
+pub struct AsyncRwLockWriteGuard<'a, T> {
+    lock: &'a AsyncRwLock<T>,
+}
+
+impl<'a, T> AsyncRwLockWriteGuard<'a, T> {
+    /// Turn into a read guard without letting another writer in.
+    pub fn downgrade(self) -> AsyncRwLockReadGuard<'a, T> {
+        let lock = self.lock;
+        mem::forget(self);
+        lock.state.fetch_add(READER - WRITER, Ordering::Release);
+        lock.notify();
+        AsyncRwLockReadGuard { lock }
+    }
+}
+
+impl<T> Deref for AsyncRwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for AsyncRwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for AsyncRwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.release(WRITER);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        future::Future,
+        pin::pin,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Context, Poll, Wake, Waker},
+    };
+
+    use super::AsyncRwLock;
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn try_variants() {
+        let lock = AsyncRwLock::new(1);
+        let (r1, r2) = (lock.try_read().unwrap(), lock.try_read().unwrap());
+        assert_eq!(*r1 + *r2, 2);
+        assert!(lock.try_write().is_none());
+
+        let up = lock.try_upgradable_read().unwrap();
+        assert!(lock.try_upgradable_read().is_none());
+        assert!(lock.try_read().is_some());
+
+        // Plain readers still in: no upgrade yet.
+        let Err(up) = up.try_upgrade() else {
+            panic!("upgraded with readers in")
+        };
+        drop((r1, r2));
+        let mut w = up.try_upgrade().ok().unwrap();
+        *w = 5;
+        assert!(lock.try_read().is_none());
+
+        let r = w.downgrade();
+        assert_eq!(*r, 5);
+        assert!(lock.try_read().is_some());
+        assert!(lock.try_write().is_none());
+        drop(r);
+        assert!(lock.try_write().is_some());
+    }
+
+    #[test]
+    fn waiting_writer_holds_back_readers() {
+        let lock = AsyncRwLock::new(0);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let reader = lock.try_read().unwrap();
+        let mut writer = pin!(lock.write());
+        assert!(writer.as_mut().poll(&mut cx).is_pending());
+        assert!(lock.try_read().is_none());
+        let mut late_reader = pin!(lock.read());
+        assert!(late_reader.as_mut().poll(&mut cx).is_pending());
+
+        drop(reader);
+        let Poll::Ready(mut guard) = writer.as_mut().poll(&mut cx) else {
+            panic!("the writer goes first")
+        };
+        *guard = 1;
+        assert!(late_reader.as_mut().poll(&mut cx).is_pending());
+        drop(guard);
+        assert!(matches!(late_reader.as_mut().poll(&mut cx), Poll::Ready(r) if *r == 1));
+    }
+
+    #[test]
+    fn release_wakes_one_writer_once() {
+        let lock = AsyncRwLock::new(());
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let reader = lock.try_read().unwrap();
+        let mut writers: Vec<_> = (0..2).map(|_| Box::pin(lock.write())).collect();
+        for _ in 0..3 {
+            for writer in &mut writers {
+                assert!(writer.as_mut().poll(&mut cx).is_pending());
+            }
+        }
+
+        drop(reader);
+        assert_eq!(counter.0.load(Ordering::Relaxed), 1);
+        let Poll::Ready(guard) = writers[0].as_mut().poll(&mut cx) else {
+            panic!("the oldest writer goes first")
+        };
+        assert!(writers[1].as_mut().poll(&mut cx).is_pending());
+        drop(guard);
+        assert_eq!(counter.0.load(Ordering::Relaxed), 2);
+        assert!(matches!(writers[1].as_mut().poll(&mut cx), Poll::Ready(_)));
+    }
+
+    #[test]
+    fn dropped_writer_passes_the_wakeup_on() {
+        let lock = AsyncRwLock::new(());
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let reader = lock.try_read().unwrap();
+        let mut writers: Vec<_> = (0..2).map(|_| Box::pin(lock.write())).collect();
+        for writer in &mut writers {
+            assert!(writer.as_mut().poll(&mut cx).is_pending());
+        }
+
+        drop(reader);
+        drop(writers.remove(0));
+        assert!(matches!(writers[0].as_mut().poll(&mut cx), Poll::Ready(_)));
+    }
+
+    #[test]
+    fn cancelled_writer_lets_readers_in() {
+        let lock = AsyncRwLock::new(());
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let reader = lock.try_read().unwrap();
+        let mut writer = Box::pin(lock.write());
+        assert!(writer.as_mut().poll(&mut cx).is_pending());
+        assert!(lock.try_read().is_none());
+
+        drop(writer);
+        assert!(lock.try_read().is_some());
+        drop(reader);
+    }
+
+    #[tokio::test]
+    async fn upgrade_waits_for_readers() {
+        let lock = Arc::new(AsyncRwLock::new(vec![]));
+        let up = lock.upgradable_read().await;
+        let reader = lock.read().await;
+
+        let upgrader = {
+            let lock = lock.clone();
+            tokio::spawn(async move {
+                let up = lock.upgradable_read().await;
+                let mut w = up.upgrade().await;
+                w.push(2);
+            })
+        };
+        let mut w = {
+            // The spawned task waits for our upgradable read; upgrading waits for `reader`.
+            tokio::task::yield_now().await;
+            assert!(lock.try_read().is_some());
+            drop(reader);
+            up.upgrade().await
+        };
+        w.push(1);
+        drop(w);
+
+        upgrader.await.unwrap();
+        assert_eq!(*lock.read().await, [1, 2]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn readers_see_whole_writes() {
+        let lock = Arc::new(AsyncRwLock::new((0, 0)));
+        let writers: Vec<_> = (0..2)
+            .map(|_| {
+                let lock = lock.clone();
+                tokio::spawn(async move {
+                    for _ in 0..500 {
+                        let mut w = lock.write().await;
+                        w.0 += 1;
+                        tokio::task::yield_now().await;
+                        w.1 += 1;
+                    }
+                })
+            })
+            .collect();
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let lock = lock.clone();
+                tokio::spawn(async move {
+                    for _ in 0..500 {
+                        let r = lock.read().await;
+                        assert_eq!(r.0, r.1);
+                    }
+                })
+            })
+            .collect();
+
+        for task in writers.into_iter().chain(readers) {
+            task.await.unwrap();
+        }
+        assert_eq!(*lock.read().await, (1000, 1000));
+    }
+}
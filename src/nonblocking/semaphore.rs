@@ -1,90 +1,600 @@
-use crate::data_structure::queue::Queue;
-use std::{
-    future::Future,
-    sync::atomic::{AtomicUsize, Ordering},
-    task::{Poll, Waker},
+use crate::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+use std::{fmt::Display, future::Future, mem, sync::Arc, task::Poll, time::Duration};
+
+use super::{
+    timeout::{timeout, timeout_with, TimeoutError},
+    waiter::{Waiter, WaiterList},
 };
 
+/// A counting semaphore. Waiters for more permits than available are all woken whenever permits
+/// come back, so a large `acquire_many` can be overtaken by smaller ones.
 pub struct AsyncSemaphore {
     val: AtomicUsize,
-    queue: Queue<Option<Waker>>,
+    closed: AtomicBool,
+    queue: WaiterList,
 }
 
 impl AsyncSemaphore {
     pub fn new(init: usize) -> Self {
         Self {
             val: AtomicUsize::new(init),
-            queue: Queue::new(),
+            closed: AtomicBool::new(false),
+            queue: WaiterList::new(),
         }
     }
 
-    unsafe fn up(&self) {
-        self.val.fetch_add(1, Ordering::Release);
+    pub fn available_permits(&self) -> usize {
+        self.val.load(Ordering::Acquire)
+    }
+
+    pub fn add_permits(&self, n: usize) {
+        self.val.fetch_add(n, Ordering::Release);
+        self.wake_all();
+    }
+
+    /// Make every pending and future acquisition fail. Permits held stay valid, and are still
+    /// returned when dropped.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.wake_all();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn wake_all(&self) {
+        // Pairs with the fence in `AcquireFut::poll`: either we see its waker, or it sees what
+        // we changed.
+        fence(Ordering::SeqCst);
+        self.queue.notify_all();
+    }
 
-        while let Some(task) = self.queue.pop() {
-            task.unwrap().wake();
+    fn try_take(&self, n: usize) -> Result<(), TryAcquireError> {
+        if self.is_closed() {
+            return Err(TryAcquireError::Closed);
         }
+
+        let mut val = self.val.load(Ordering::Acquire);
+        loop {
+            if val < n {
+                return Err(TryAcquireError::NoPermits);
+            }
+            match self
+                .val
+                .compare_exchange(val, val - n, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return Ok(()),
+                Err(v) => val = v,
+            }
+        }
+    }
+
+    /// Like [`Self::acquire`], for semaphores that are never closed.
+    ///
+    /// # Panics
+    /// If the semaphore is closed.
+    pub async fn down(&self) -> AsyncSemaphoreGuard<'_> {
+        self.acquire().await.expect("down on a closed semaphore")
+    }
+
+    /// Like [`Self::down`], giving up after `duration`. Timed by tokio, so this needs a tokio
+    /// runtime. See [`Self::down_timeout_with`] for others.
+    ///
+    /// # Panics
+    /// If the semaphore is closed.
+    pub async fn down_timeout(
+        &self,
+        duration: Duration,
+    ) -> Result<AsyncSemaphoreGuard<'_>, TimeoutError> {
+        timeout(duration, self.down()).await
     }
 
-    pub async fn down(&self) -> AsyncSemaphoreGuard {
-        DownFut { semaphore: self }.await;
-        AsyncSemaphoreGuard { semaphore: self }
+    /// Like [`Self::down_timeout`], timed by whatever `sleep` returns for `duration`.
+    ///
+    /// # Panics
+    /// If the semaphore is closed.
+    pub async fn down_timeout_with<S: Future<Output = ()>>(
+        &self,
+        duration: Duration,
+        sleep: impl FnOnce(Duration) -> S,
+    ) -> Result<AsyncSemaphoreGuard<'_>, TimeoutError> {
+        timeout_with(duration, sleep, self.down()).await
+    }
+
+    pub async fn acquire(&self) -> Result<AsyncSemaphoreGuard<'_>, AcquireError> {
+        self.acquire_many(1).await
+    }
+
+    /// Wait until `n` permits are available at once, and take them together.
+    pub async fn acquire_many(&self, n: usize) -> Result<AsyncSemaphoreGuard<'_>, AcquireError> {
+        AcquireFut::new(self, n).await?;
+        Ok(AsyncSemaphoreGuard {
+            semaphore: self,
+            permits: n,
+        })
+    }
+
+    pub fn try_acquire(&self) -> Result<AsyncSemaphoreGuard<'_>, TryAcquireError> {
+        self.try_acquire_many(1)
+    }
+
+    pub fn try_acquire_many(&self, n: usize) -> Result<AsyncSemaphoreGuard<'_>, TryAcquireError> {
+        self.try_take(n)?;
+        Ok(AsyncSemaphoreGuard {
+            semaphore: self,
+            permits: n,
+        })
+    }
+
+    /// Like [`Self::acquire`], with a guard owning a handle to the semaphore, so it can be moved
+    /// into spawned tasks.
+    pub async fn acquire_owned(self: Arc<Self>) -> Result<OwnedAsyncSemaphoreGuard, AcquireError> {
+        self.acquire_many_owned(1).await
+    }
+
+    pub async fn acquire_many_owned(
+        self: Arc<Self>,
+        n: usize,
+    ) -> Result<OwnedAsyncSemaphoreGuard, AcquireError> {
+        AcquireFut::new(&self, n).await?;
+        Ok(OwnedAsyncSemaphoreGuard {
+            semaphore: self,
+            permits: n,
+        })
+    }
+
+    pub fn try_acquire_owned(self: Arc<Self>) -> Result<OwnedAsyncSemaphoreGuard, TryAcquireError> {
+        self.try_take(1)?;
+        Ok(OwnedAsyncSemaphoreGuard {
+            semaphore: self,
+            permits: 1,
+        })
     }
 }
 
-struct DownFut<'a> {
+/// The semaphore was closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AcquireError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryAcquireError {
+    Closed,
+    NoPermits,
+}
+
+impl Display for AcquireError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "acquiring from a closed semaphore")
+    }
+}
+
+impl Display for TryAcquireError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TryAcquireError::Closed => write!(f, "acquiring from a closed semaphore"),
+            TryAcquireError::NoPermits => write!(f, "not enough permits available"),
+        }
+    }
+}
+
+impl std::error::Error for AcquireError {}
+impl std::error::Error for TryAcquireError {}
+
+/// Waits until `permits` can be taken. Dropping it only leaves the queue: wakeups go to every
+/// waiter, so none is meant for it alone.
+struct AcquireFut<'a> {
     semaphore: &'a AsyncSemaphore,
+    permits: usize,
+    waiter: Option<Arc<Waiter>>,
+}
+
+impl<'a> AcquireFut<'a> {
+    fn new(semaphore: &'a AsyncSemaphore, permits: usize) -> Self {
+        Self {
+            semaphore,
+            permits,
+            waiter: None,
+        }
+    }
+
+    fn try_take(&self) -> Poll<Result<(), AcquireError>> {
+        match self.semaphore.try_take(self.permits) {
+            Ok(()) => Poll::Ready(Ok(())),
+            Err(TryAcquireError::Closed) => Poll::Ready(Err(AcquireError)),
+            Err(TryAcquireError::NoPermits) => Poll::Pending,
+        }
+    }
 }
 
-impl Future for DownFut<'_> {
-    type Output = ();
+impl Future for AcquireFut<'_> {
+    type Output = Result<(), AcquireError>;
 
     fn poll(
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Self::Output> {
-        loop {
-            let val = self.semaphore.val.load(Ordering::Acquire);
-
-            if val > 0 {
-                let new = val - 1;
-                if self
-                    .semaphore
-                    .val
-                    .compare_exchange(val, new, Ordering::AcqRel, Ordering::Acquire)
-                    .is_ok()
-                {
-                    break Poll::Ready(());
-                }
-            } else {
-                let waker = cx.waker().clone();
-                self.semaphore.queue.push(Some( waker ));
-
-                if self.semaphore.val.load(Ordering::Acquire) > 0 {
-                    if let Some(waker) = self.semaphore.queue.pop() {
-                        waker.unwrap().wake();
-                    }
-                }
+        let this = self.get_mut();
 
-                break Poll::Pending;
+        if let Some(waiter) = &this.waiter {
+            waiter.register(cx.waker());
+            if waiter.notified().is_none() {
+                return Poll::Pending;
             }
+            this.waiter = None;
+        }
+
+        if let ready @ Poll::Ready(_) = this.try_take() {
+            return ready;
+        }
+
+        let waiter = Waiter::new(cx.waker());
+        this.semaphore.queue.push(waiter.clone());
+        // Pairs with the fence in `wake_all`: either it sees our waiter, or we see its permits.
+        fence(Ordering::SeqCst);
+
+        let poll = this.try_take();
+        if poll.is_ready() {
+            waiter.cancel();
+        } else {
+            this.waiter = Some(waiter);
+        }
+        poll
+    }
+}
+
+impl Drop for AcquireFut<'_> {
+    fn drop(&mut self) {
+        if let Some(waiter) = self.waiter.take() {
+            waiter.cancel();
         }
     }
 }
 
+/// Permits returned to the semaphore on drop.
 pub struct AsyncSemaphoreGuard<'a> {
     semaphore: &'a AsyncSemaphore,
+    permits: usize,
+}
+
+impl AsyncSemaphoreGuard<'_> {
+    pub fn num_permits(&self) -> usize {
+        self.permits
+    }
+
+    /// Keep the permits away from the semaphore for good.
+    pub fn forget(self) {
+        mem::forget(self);
+    }
 }
 
 impl Drop for AsyncSemaphoreGuard<'_> {
     fn drop(&mut self) {
-        unsafe {
-            self.semaphore.up();
+        self.semaphore.add_permits(self.permits);
+    }
+}
+
+/// Permits returned to the semaphore on drop, keeping it alive meanwhile.
+pub struct OwnedAsyncSemaphoreGuard {
+    semaphore: Arc<AsyncSemaphore>,
+    permits: usize,
+}
+
+impl OwnedAsyncSemaphoreGuard {
+    pub fn num_permits(&self) -> usize {
+        self.permits
+    }
+
+    pub fn semaphore(&self) -> &Arc<AsyncSemaphore> {
+        &self.semaphore
+    }
+
+    /// Keep the permits away from the semaphore for good.
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl Drop for OwnedAsyncSemaphoreGuard {
+    fn drop(&mut self) {
+        if self.permits > 0 {
+            self.semaphore.add_permits(self.permits);
         }
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::{
+        cell::Cell,
+        future::Future,
+        rc::Rc,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Context, Wake, Waker},
+        time::Duration,
+    };
+
+    use super::{AcquireError, AsyncSemaphore, TryAcquireError};
+    use crate::utils::executor::Executor;
+    use crate::utils::linearizability::{check, Recorder, SemaphoreModel, SemaphoreOp::*};
+
+    #[tokio::test]
+    async fn down_and_up() {
+        let semaphore = AsyncSemaphore::new(2);
+        let a = semaphore.down().await;
+        let _b = semaphore.down().await;
+        drop(a);
+        let _c = semaphore.down().await;
+    }
+
+    #[test]
+    fn try_acquire_and_permits() {
+        let semaphore = AsyncSemaphore::new(3);
+        let two = semaphore.try_acquire_many(2).unwrap();
+        assert_eq!(two.num_permits(), 2);
+        assert_eq!(semaphore.available_permits(), 1);
+        assert_eq!(
+            semaphore.try_acquire_many(2).err(),
+            Some(TryAcquireError::NoPermits)
+        );
+
+        let one = semaphore.try_acquire().unwrap();
+        one.forget();
+        drop(two);
+        assert_eq!(semaphore.available_permits(), 2);
+
+        semaphore.add_permits(3);
+        assert_eq!(semaphore.available_permits(), 5);
+        semaphore.close();
+        assert_eq!(semaphore.try_acquire().err(), Some(TryAcquireError::Closed));
+    }
 
+    #[tokio::test]
+    async fn acquire_many_waits_for_all() {
+        let semaphore = Arc::new(AsyncSemaphore::new(2));
+        let held = semaphore.acquire().await.unwrap();
+
+        let waiter = tokio::spawn(semaphore.clone().acquire_many_owned(3));
+        tokio::task::yield_now().await;
+        semaphore.add_permits(1);
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        drop(held);
+        let permits = waiter.await.unwrap().unwrap();
+        assert_eq!(permits.num_permits(), 3);
+        assert_eq!(semaphore.available_permits(), 0);
+        drop(permits);
+        assert_eq!(semaphore.available_permits(), 3);
+    }
+
+    #[tokio::test]
+    async fn close_fails_waiters() {
+        let semaphore = Arc::new(AsyncSemaphore::new(1));
+        let held = semaphore.clone().acquire_owned().await.unwrap();
+
+        let waiters: Vec<_> = (0..3)
+            .map(|_| tokio::spawn(semaphore.clone().acquire_owned()))
+            .collect();
+        tokio::task::yield_now().await;
+        semaphore.close();
+        for waiter in waiters {
+            assert!(matches!(waiter.await.unwrap(), Err(AcquireError)));
+        }
+
+        // Held permits outlive the close.
+        drop(held);
+        assert_eq!(semaphore.available_permits(), 1);
+        assert!(semaphore.acquire().await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn owned_permits_move_into_tasks() {
+        let semaphore = Arc::new(AsyncSemaphore::new(2));
+        let inside = Arc::new(AtomicUsize::new(0));
+
+        let mut tasks = vec![];
+        for _ in 0..20 {
+            let permit = semaphore.clone().acquire_owned().await.unwrap();
+            let inside = inside.clone();
+            tasks.push(tokio::spawn(async move {
+                assert!(inside.fetch_add(1, Ordering::SeqCst) < 2);
+                tokio::task::yield_now().await;
+                inside.fetch_sub(1, Ordering::SeqCst);
+                drop(permit);
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn down_timeout() {
+        let semaphore = AsyncSemaphore::new(1);
+        let permit = semaphore.down().await;
+        let Err(err) = semaphore.down_timeout(Duration::from_millis(10)).await else {
+            panic!("acquired a permit that is held");
+        };
+        assert_eq!(err.duration(), Duration::from_millis(10));
+        drop(permit);
+        assert!(semaphore
+            .down_timeout(Duration::from_millis(10))
+            .await
+            .is_ok());
+    }
+
+    #[test]
+    fn dropped_waiter_releases_its_waker() {
+        struct Task(AtomicUsize);
+
+        impl Wake for Task {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let semaphore = AsyncSemaphore::new(0);
+        let task = Arc::new(Task(AtomicUsize::new(0)));
+        let waker = Waker::from(task.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let mut acquire = Box::pin(semaphore.acquire());
+        for _ in 0..3 {
+            assert!(acquire.as_mut().poll(&mut cx).is_pending());
+        }
+        // Polling again doesn't queue the same task over and over.
+        assert_eq!(Arc::strong_count(&task), 3);
+
+        drop(acquire);
+        assert_eq!(Arc::strong_count(&task), 2);
+        semaphore.add_permits(1);
+        assert_eq!(task.0.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn timeouts_keep_permits() {
+        const PERMITS: usize = 2;
+        let semaphore = Arc::new(AsyncSemaphore::new(PERMITS));
+
+        let tasks: Vec<_> = (0..16)
+            .map(|i| {
+                let semaphore = semaphore.clone();
+                tokio::spawn(async move {
+                    for _ in 0..50 {
+                        let timeout = Duration::from_micros(i * 10);
+                        if let Ok(_permit) = semaphore.down_timeout(timeout).await {
+                            tokio::task::yield_now().await;
+                        }
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(semaphore.available_permits(), PERMITS);
+        // Whatever timed out, nobody is left waiting for nothing.
+        assert!(semaphore.try_acquire_many(PERMITS).is_ok());
+    }
+
+    #[test]
+    fn deterministic_schedules() {
+        const PERMITS: usize = 3;
+        for seed in 0..200 {
+            let executor = Executor::new(seed);
+            let semaphore = Rc::new(AsyncSemaphore::new(PERMITS));
+            let inside = Rc::new(Cell::new(0));
+            for n in 1..=PERMITS {
+                let (semaphore, inside, handle) =
+                    (semaphore.clone(), inside.clone(), executor.handle());
+                executor.spawn(async move {
+                    for _ in 0..4 {
+                        let _permits = semaphore.acquire_many(n).await.unwrap();
+                        inside.set(inside.get() + n);
+                        assert!(inside.get() <= PERMITS);
+                        handle.yield_now().await;
+                        inside.set(inside.get() - n);
+                    }
+                });
+            }
+
+            if let Err(err) = executor.run() {
+                panic!("seed {seed}: {err}");
+            }
+            assert_eq!(semaphore.available_permits(), PERMITS);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn bounds_concurrency() {
+        const PERMITS: usize = 3;
+        let semaphore = Arc::new(AsyncSemaphore::new(PERMITS));
+        let (inside, most) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let (semaphore, inside, most) = (semaphore.clone(), inside.clone(), most.clone());
+                tokio::spawn(async move {
+                    for _ in 0..200 {
+                        let _permit = semaphore.down().await;
+                        let now = inside.fetch_add(1, Ordering::SeqCst) + 1;
+                        most.fetch_max(now, Ordering::SeqCst);
+                        tokio::task::yield_now().await;
+                        inside.fetch_sub(1, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert!(most.load(Ordering::SeqCst) <= PERMITS);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn history_is_linearizable() {
+        let semaphore = Arc::new(AsyncSemaphore::new(2));
+        let recorder = Arc::new(Recorder::new());
+        let tasks: Vec<_> = (0..4)
+            .map(|_| {
+                let (semaphore, recorder) = (semaphore.clone(), recorder.clone());
+                tokio::spawn(async move {
+                    for _ in 0..25 {
+                        let invoke = recorder.invoke();
+                        let permit = semaphore.down().await;
+                        recorder.respond(invoke, Down, ());
+                        tokio::task::yield_now().await;
+                        recorder.record(Up, || drop(permit));
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let history = recorder.take();
+        assert!(check(SemaphoreModel(2), &history).is_some(), "{history:?}");
+    }
+
+    /// Run with `RUSTFLAGS="--cfg loom" cargo test --release --lib loom`.
+    #[cfg(loom)]
+    mod loom_model {
+        use loom::{future::block_on, sync::Arc, thread};
+
+        use super::super::AsyncSemaphore;
+        use crate::utils::linearizability::{check, Recorder, SemaphoreModel, SemaphoreOp::*};
+
+        /// Besides linearizability, loom reports a deadlock if a waiter is never woken.
+        #[test]
+        fn down_up() {
+            crate::sync::model(|| {
+                let semaphore = Arc::new(AsyncSemaphore::new(1));
+                let recorder = Arc::new(Recorder::new());
+
+                let handles: Vec<_> = (0..2)
+                    .map(|_| {
+                        let (semaphore, recorder) = (semaphore.clone(), recorder.clone());
+                        thread::spawn(move || {
+                            let invoke = recorder.invoke();
+                            let permit = block_on(semaphore.down());
+                            recorder.respond(invoke, Down, ());
+                            recorder.record(Up, || drop(permit));
+                        })
+                    })
+                    .collect();
+                for handle in handles {
+                    handle.join().unwrap();
+                }
+
+                let history = recorder.take();
+                assert!(check(SemaphoreModel(1), &history).is_some(), "{history:?}");
+            });
+        }
+    }
 }
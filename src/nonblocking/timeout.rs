@@ -0,0 +1,53 @@
+use std::{
+    fmt::Display,
+    future::{poll_fn, Future},
+    pin::pin,
+    task::Poll,
+    time::Duration,
+};
+
+/// A wait gave up after its timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeoutError {
+    duration: Duration,
+}
+
+impl TimeoutError {
+    /// How long was waited.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+impl Display for TimeoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "timed out after {:?}", self.duration)
+    }
+}
+
+impl std::error::Error for TimeoutError {}
+
+/// Run `fut` for at most `duration` on the tokio timer, dropping it once the time is up. Needs a
+/// tokio runtime.
+pub(crate) async fn timeout<F: Future>(
+    duration: Duration,
+    fut: F,
+) -> Result<F::Output, TimeoutError> {
+    timeout_with(duration, tokio::time::sleep, fut).await
+}
+
+/// Like [`timeout`], on the timer `sleep` belongs to.
+pub(crate) async fn timeout_with<F: Future, S: Future<Output = ()>>(
+    duration: Duration,
+    sleep: impl FnOnce(Duration) -> S,
+    fut: F,
+) -> Result<F::Output, TimeoutError> {
+    let (mut fut, mut sleep) = (pin!(fut), pin!(sleep(duration)));
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = fut.as_mut().poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+        sleep.as_mut().poll(cx).map(|()| Err(TimeoutError { duration }))
+    })
+    .await
+}
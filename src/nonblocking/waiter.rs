@@ -0,0 +1,142 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    task::Waker,
+};
+
+use crate::{
+    data_structure::queue::Queue,
+    sync::atomic::{AtomicU8, Ordering},
+    utils::tag::{Tag, Tagged},
+};
+
+const WAITING: u8 = 0;
+const CANCELLED: u8 = 1;
+const NOTIFIED_ONE: u8 = 2;
+const NOTIFIED_ALL: u8 = 3;
+
+/// How a waiter was notified, which decides whether a notification it gives up is passed on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Notified {
+    /// Meant for exactly one waiter.
+    One,
+    /// Broadcast to everyone waiting at the time.
+    All,
+}
+
+/// A task queued on a [`WaiterList`]. Cancelled waiters stay in the queue and are skipped.
+pub(crate) struct Waiter {
+    state: AtomicU8,
+    waker: Mutex<Tagged<Waker>>,
+}
+
+impl Waiter {
+    pub fn new(waker: &Waker) -> Arc<Self> {
+        Arc::new(Self {
+            state: AtomicU8::new(WAITING),
+            waker: Mutex::new(waker.clone().tagged()),
+        })
+    }
+
+    /// Wake `waker` from now on. Re-polls by the same task keep the waker they registered.
+    ///
+    /// Register before checking [`Self::notified`]: a notification then either sees this waker
+    /// or is seen.
+    pub fn register(&self, waker: &Waker) {
+        let mut current = self.waker.lock().unwrap();
+        if *current.tag() != waker.tag() {
+            *current = waker.clone().tagged();
+        }
+    }
+
+    pub fn notified(&self) -> Option<Notified> {
+        match self.state.load(Ordering::Acquire) {
+            NOTIFIED_ONE => Some(Notified::One),
+            NOTIFIED_ALL => Some(Notified::All),
+            _ => None,
+        }
+    }
+
+    /// Mark the waiter notified. Returns whether it was still waiting.
+    fn notify(&self, how: Notified) -> bool {
+        let state = match how {
+            Notified::One => NOTIFIED_ONE,
+            Notified::All => NOTIFIED_ALL,
+        };
+        self.state
+            .compare_exchange(WAITING, state, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    /// Stop waiting. Returns the notification that came first, if any, for the caller to pass
+    /// on instead of swallowing it.
+    ///
+    /// The waker is released right away rather than when the list gets to the waiter.
+    pub fn cancel(&self) -> Option<Notified> {
+        match self
+            .state
+            .compare_exchange(WAITING, CANCELLED, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => {
+                *self.waker.lock().unwrap() = Waker::noop().clone().tagged();
+                None
+            }
+            Err(_) => self.notified(),
+        }
+    }
+}
+
+/// A FIFO list of waiters on the lock-free [`Queue`].
+///
+/// Whoever queues a waiter has to re-check its condition after a `SeqCst` fence, pairing with a
+/// fence the notifier issues between changing the condition and notifying.
+pub(crate) struct WaiterList {
+    queue: Queue<Arc<Waiter>>,
+}
+
+impl WaiterList {
+    pub fn new() -> Self {
+        Self {
+            queue: Queue::new(),
+        }
+    }
+
+    pub fn push(&self, waiter: Arc<Waiter>) {
+        self.queue.push(waiter);
+    }
+
+    /// Whether nobody might be waiting. Cancelled waiters not skipped yet still count.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Notify the oldest waiter that is still waiting. Returns whether there was one.
+    pub fn notify_one(&self) -> bool {
+        while let Some(waiter) = self.queue.pop() {
+            if waiter.notify(Notified::One) {
+                waiter.waker.lock().unwrap().get().wake_by_ref();
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Notify every waiter queued so far, waking each task once even if it waits several times.
+    pub fn notify_all(&self) {
+        let mut tasks = HashSet::new();
+        while let Some(waiter) = self.queue.pop() {
+            if waiter.notify(Notified::All) {
+                tasks.insert(waiter.waker.lock().unwrap().clone());
+            }
+        }
+        for task in tasks {
+            task.get().wake_by_ref();
+        }
+    }
+}
+
+impl Default for WaiterList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,129 @@
+//! Atomics shared with the model checker. They are `std`'s, or `loom`'s when built with
+//! `--cfg loom`, so that loom can explore every interleaving of the operations on them.
+//!
+//! The pointer operations inside `crossbeam::epoch` are invisible to loom, which would run them as
+//! parts of one step between two operations it can see. [`Atomic`] is therefore swapped as well:
+//! under loom it keeps the tagged pointer in a loom atomic, so the links of `TreiberStack` and
+//! `Queue` are explored like any other atomic. Pinning and reclamation still go through
+//! `crossbeam::epoch`. [`Backoff`] yields to loom, which otherwise keeps running a thread that
+//! spins on one of these.
+
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic;
+#[cfg(not(loom))]
+pub(crate) use std::sync::atomic;
+
+#[cfg(not(loom))]
+pub(crate) use crossbeam::epoch::Atomic;
+#[cfg(loom)]
+pub(crate) use self::loom_shim::Atomic;
+
+#[cfg(not(loom))]
+pub(crate) use crossbeam::utils::Backoff;
+#[cfg(loom)]
+pub(crate) use self::loom_shim::Backoff;
+
+/// `loom::model` for anything built on [`Atomic`].
+///
+/// Every preemption lets a spinning `pop` reread stale links, so unless `LOOM_MAX_PREEMPTIONS`
+/// says otherwise the search stops at two, which is enough to catch a missing release/acquire
+/// pair on a link.
+#[cfg(all(test, loom))]
+pub(crate) fn model(f: impl Fn() + Sync + Send + 'static) {
+    let mut builder = loom::model::Builder::new();
+    builder.preemption_bound.get_or_insert(2);
+    builder.check(move || {
+        // Loom's threads share one OS thread and so one epoch participant. Pinning it for the
+        // whole run keeps reclamation, whose fences loom can't see, from freeing a node that a
+        // stale load may still return.
+        let _pin = crossbeam::epoch::pin();
+        f();
+    });
+}
+
+#[cfg(loom)]
+mod loom_shim {
+    use std::marker::PhantomData;
+
+    use crossbeam::epoch::{CompareExchangeError, Guard, Owned, Pointer, Shared};
+
+    use super::atomic::{AtomicUsize, Ordering};
+
+    /// The subset of `crossbeam::epoch::Atomic` the collections use, on a loom atomic.
+    pub(crate) struct Atomic<T> {
+        data: AtomicUsize,
+        _marker: PhantomData<*mut T>,
+    }
+
+    unsafe impl<T: Send + Sync> Send for Atomic<T> {}
+    unsafe impl<T: Send + Sync> Sync for Atomic<T> {}
+
+    impl<T> Atomic<T> {
+        fn from_usize(data: usize) -> Self {
+            Self {
+                data: AtomicUsize::new(data),
+                _marker: PhantomData,
+            }
+        }
+
+        pub fn null() -> Self {
+            Self::from_usize(0)
+        }
+
+        pub fn new(val: T) -> Self {
+            Self::from_usize(Owned::new(val).into_usize())
+        }
+
+        pub fn load<'g>(&self, ord: Ordering, _: &'g Guard) -> Shared<'g, T> {
+            unsafe { Shared::from_usize(self.data.load(ord)) }
+        }
+
+        pub fn store<P: Pointer<T>>(&self, new: P, ord: Ordering) {
+            self.data.store(new.into_usize(), ord);
+        }
+
+        pub fn swap<'g, P: Pointer<T>>(&self, new: P, ord: Ordering, _: &'g Guard) -> Shared<'g, T> {
+            unsafe { Shared::from_usize(self.data.swap(new.into_usize(), ord)) }
+        }
+
+        pub fn compare_exchange<'g, P: Pointer<T>>(
+            &self,
+            current: Shared<'_, T>,
+            new: P,
+            success: Ordering,
+            failure: Ordering,
+            _: &'g Guard,
+        ) -> Result<Shared<'g, T>, CompareExchangeError<'g, T, P>> {
+            let new = new.into_usize();
+            match self.data.compare_exchange(current.into_usize(), new, success, failure) {
+                Ok(_) => Ok(unsafe { Shared::from_usize(new) }),
+                Err(current) => Err(CompareExchangeError {
+                    current: unsafe { Shared::from_usize(current) },
+                    new: unsafe { P::from_usize(new) },
+                }),
+            }
+        }
+    }
+
+    impl<T> Clone for Atomic<T> {
+        fn clone(&self) -> Self {
+            Self::from_usize(self.data.load(Ordering::Relaxed))
+        }
+    }
+
+    pub(crate) struct Backoff;
+
+    impl Backoff {
+        pub fn new() -> Self {
+            Self
+        }
+
+        pub fn spin(&self) {
+            loom::thread::yield_now();
+        }
+
+        pub fn snooze(&self) {
+            loom::thread::yield_now();
+        }
+    }
+}
@@ -1,6 +1,8 @@
 pub mod tag;
 pub mod trc;
 pub mod ghost_cell;
+pub mod linearizability;
+pub mod executor;
 
 #[macro_export]
 macro_rules! log_call {
@@ -0,0 +1,430 @@
+//! A single-threaded executor for testing async code deterministically.
+//!
+//! Which ready task runs next is picked by a seeded random number generator, so every seed is
+//! one reproducible interleaving of the tasks at their `.await` points. Time is virtual: it only
+//! moves when every task is blocked, straight to the next [`sleep`](Handle::sleep) deadline.
+//!
+//! A run that ends with tasks blocked and no timer left to fire reports a [`Deadlock`], which is
+//! what a lost wakeup looks like.
+
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fmt::Display,
+    future::Future,
+    mem,
+    pin::Pin,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Wake, Waker},
+    time::Duration,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+/// Ids of the tasks woken since they last ran. Wakers may be sent to other threads, so this is
+/// the only part shared through a `Mutex`.
+#[derive(Default)]
+struct ReadyList(Mutex<Vec<usize>>);
+
+impl ReadyList {
+    fn push(&self, id: usize) {
+        let mut ready = self.0.lock().unwrap();
+        if !ready.contains(&id) {
+            ready.push(id);
+        }
+    }
+}
+
+struct TaskWaker {
+    id: usize,
+    ready: Arc<ReadyList>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.ready.push(self.id);
+    }
+}
+
+struct Timer {
+    deadline: Duration,
+    /// Breaks ties in the order the timers were set.
+    seq: u64,
+    waker: Waker,
+}
+
+impl PartialEq for Timer {
+    fn eq(&self, other: &Self) -> bool {
+        (self.deadline, self.seq) == (other.deadline, other.seq)
+    }
+}
+
+impl Eq for Timer {}
+
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timer {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.deadline, self.seq).cmp(&(other.deadline, other.seq))
+    }
+}
+
+struct Inner {
+    rng: RefCell<StdRng>,
+    /// Indexed by task id. Finished tasks leave `None` behind.
+    tasks: RefCell<Vec<Option<Task>>>,
+    ready: Arc<ReadyList>,
+    now: Cell<Duration>,
+    timers: RefCell<BinaryHeap<Reverse<Timer>>>,
+    timer_seq: Cell<u64>,
+}
+
+/// Tasks were left blocked with nothing that could wake them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadlock {
+    /// How many tasks were blocked.
+    pub blocked: usize,
+    /// The virtual time it happened at.
+    pub at: Duration,
+}
+
+impl Display for Deadlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} tasks blocked for good at {:?}",
+            self.blocked, self.at
+        )
+    }
+}
+
+impl std::error::Error for Deadlock {}
+
+pub struct Executor {
+    handle: Handle,
+}
+
+impl Executor {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            handle: Handle(Rc::new(Inner {
+                rng: RefCell::new(StdRng::seed_from_u64(seed)),
+                tasks: RefCell::new(vec![]),
+                ready: Arc::default(),
+                now: Cell::new(Duration::ZERO),
+                timers: RefCell::new(BinaryHeap::new()),
+                timer_seq: Cell::new(0),
+            })),
+        }
+    }
+
+    /// A handle for tasks to spawn more tasks and use the clock.
+    pub fn handle(&self) -> Handle {
+        self.handle.clone()
+    }
+
+    pub fn spawn<F: Future + 'static>(&self, fut: F) -> JoinHandle<F::Output> {
+        self.handle.spawn(fut)
+    }
+
+    /// Run every task to completion.
+    pub fn run(&self) -> Result<(), Deadlock> {
+        while self.step()? {}
+        Ok(())
+    }
+
+    /// Run tasks until `fut` completes, and return its output. Other tasks may be left
+    /// unfinished.
+    pub fn block_on<F: Future + 'static>(&self, fut: F) -> Result<F::Output, Deadlock> {
+        let handle = self.spawn(fut);
+        loop {
+            if let Some(output) = handle.take() {
+                return Ok(output);
+            }
+            self.step()?;
+        }
+    }
+
+    /// Poll one ready task, advancing the clock first if none is. Returns whether any task was
+    /// left to run.
+    fn step(&self) -> Result<bool, Deadlock> {
+        let inner = &self.handle.0;
+        let id = loop {
+            let mut ready = inner.ready.0.lock().unwrap();
+            if !ready.is_empty() {
+                let i = inner.rng.borrow_mut().gen_range(0..ready.len());
+                break ready.swap_remove(i);
+            }
+            drop(ready);
+
+            let blocked = inner.tasks.borrow().iter().flatten().count();
+            if blocked == 0 {
+                return Ok(false);
+            }
+            if !self.handle.fire_timers() {
+                return Err(Deadlock {
+                    blocked,
+                    at: inner.now.get(),
+                });
+            }
+        };
+
+        // Taken out while polled, since the task may spawn others.
+        let Some(mut task) = inner.tasks.borrow_mut()[id].take() else {
+            // Woken after finishing.
+            return Ok(true);
+        };
+        let waker = Waker::from(Arc::new(TaskWaker {
+            id,
+            ready: inner.ready.clone(),
+        }));
+        if task
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending()
+        {
+            inner.tasks.borrow_mut()[id] = Some(task);
+        }
+        Ok(true)
+    }
+}
+
+/// Spawns tasks onto an [`Executor`] and gives them its virtual clock.
+#[derive(Clone)]
+pub struct Handle(Rc<Inner>);
+
+impl Handle {
+    pub fn spawn<F: Future + 'static>(&self, fut: F) -> JoinHandle<F::Output> {
+        let join = Rc::new(Join {
+            output: RefCell::new(None),
+            waker: RefCell::new(None),
+        });
+        let task = {
+            let join = join.clone();
+            async move {
+                *join.output.borrow_mut() = Some(fut.await);
+                if let Some(waker) = join.waker.take() {
+                    waker.wake();
+                }
+            }
+        };
+
+        let mut tasks = self.0.tasks.borrow_mut();
+        tasks.push(Some(Box::pin(task)));
+        self.0.ready.push(tasks.len() - 1);
+        JoinHandle { join }
+    }
+
+    /// Virtual time since the executor was created.
+    pub fn now(&self) -> Duration {
+        self.0.now.get()
+    }
+
+    pub fn sleep(&self, duration: Duration) -> Sleep {
+        Sleep {
+            handle: self.clone(),
+            deadline: self.now() + duration,
+        }
+    }
+
+    /// Let other ready tasks run, in whatever order the seed says.
+    pub fn yield_now(&self) -> YieldNow {
+        YieldNow { yielded: false }
+    }
+
+    /// Move the clock to the earliest deadline and wake every timer due. Returns whether there
+    /// was one.
+    fn fire_timers(&self) -> bool {
+        let mut timers = self.0.timers.borrow_mut();
+        let Some(Reverse(first)) = timers.peek() else {
+            return false;
+        };
+        self.0.now.set(self.0.now.get().max(first.deadline));
+        while let Some(Reverse(timer)) = timers.peek() {
+            if timer.deadline > self.now() {
+                break;
+            }
+            let Reverse(timer) = timers.pop().unwrap();
+            timer.waker.wake();
+        }
+        true
+    }
+}
+
+struct Join<T> {
+    output: RefCell<Option<T>>,
+    waker: RefCell<Option<Waker>>,
+}
+
+/// Resolves to the output of a spawned task.
+pub struct JoinHandle<T> {
+    join: Rc<Join<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn is_finished(&self) -> bool {
+        self.join.output.borrow().is_some()
+    }
+
+    fn take(&self) -> Option<T> {
+        self.join.output.take()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.take() {
+            Some(output) => Poll::Ready(output),
+            None => {
+                *self.join.waker.borrow_mut() = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Completes once the virtual clock reaches its deadline.
+pub struct Sleep {
+    handle: Handle,
+    deadline: Duration,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = &self.handle.0;
+        if inner.now.get() >= self.deadline {
+            return Poll::Ready(());
+        }
+        let seq = inner.timer_seq.get();
+        inner.timer_seq.set(seq + 1);
+        inner.timers.borrow_mut().push(Reverse(Timer {
+            deadline: self.deadline,
+            seq,
+            waker: cx.waker().clone(),
+        }));
+        Poll::Pending
+    }
+}
+
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if mem::replace(&mut self.yielded, true) {
+            return Poll::Ready(());
+        }
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, future::pending, rc::Rc, time::Duration};
+
+    use super::{Deadlock, Executor};
+
+    /// The order three yielding tasks take turns in under `seed`.
+    fn trace(seed: u64) -> Vec<usize> {
+        let executor = Executor::new(seed);
+        let trace = Rc::new(RefCell::new(vec![]));
+        for id in 0..3 {
+            let (handle, trace) = (executor.handle(), trace.clone());
+            executor.spawn(async move {
+                for _ in 0..5 {
+                    trace.borrow_mut().push(id);
+                    handle.yield_now().await;
+                }
+            });
+        }
+        executor.run().unwrap();
+        Rc::try_unwrap(trace).unwrap().into_inner()
+    }
+
+    #[test]
+    fn seeds_replay() {
+        for seed in 0..10 {
+            assert_eq!(trace(seed), trace(seed));
+        }
+        let mut traces: Vec<_> = (0..10).map(trace).collect();
+        traces.dedup();
+        assert!(traces.len() > 1);
+    }
+
+    #[test]
+    fn virtual_time() {
+        let executor = Executor::new(0);
+        let woken = Rc::new(RefCell::new(vec![]));
+        for secs in [30, 10, 20, 10] {
+            let (handle, woken) = (executor.handle(), woken.clone());
+            executor.spawn(async move {
+                handle.sleep(Duration::from_secs(secs)).await;
+                woken.borrow_mut().push((secs, handle.now()));
+            });
+        }
+
+        let handle = executor.handle();
+        let hour = executor.block_on(async move {
+            handle.sleep(Duration::from_secs(3600)).await;
+            handle.now()
+        });
+        assert_eq!(hour, Ok(Duration::from_secs(3600)));
+        let secs = |s| (s, Duration::from_secs(s));
+        assert_eq!(*woken.borrow(), [secs(10), secs(10), secs(20), secs(30)]);
+    }
+
+    #[test]
+    fn join_handles() {
+        let executor = Executor::new(0);
+        let handle = executor.handle();
+        let sum = executor.block_on(async move {
+            let tasks: Vec<_> = (0..4)
+                .map(|i| handle.spawn(async move { i * 10 }))
+                .collect();
+            let mut sum = 0;
+            for task in tasks {
+                sum += task.await;
+            }
+            sum
+        });
+        assert_eq!(sum, Ok(60));
+    }
+
+    #[test]
+    fn reports_tasks_never_woken() {
+        let executor = Executor::new(0);
+        let handle = executor.handle();
+        executor.spawn(async move {
+            handle.sleep(Duration::from_secs(5)).await;
+            pending::<()>().await;
+        });
+        executor.spawn(async {});
+        assert_eq!(
+            executor.run(),
+            Err(Deadlock {
+                blocked: 1,
+                at: Duration::from_secs(5)
+            })
+        );
+    }
+}
@@ -0,0 +1,295 @@
+//! Linearizability checking in the style of Wing and Gong: a concurrent history is linearizable
+//! if its operations can be put in a sequential order that respects real time (an operation that
+//! returned before another was invoked comes first) and in which a sequential model returns what
+//! every operation actually returned.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    hash::Hash,
+    mem,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+/// A sequential specification.
+pub trait Model: Clone + Eq + Hash {
+    type Op;
+    type Ret: PartialEq;
+
+    /// Apply `op`, or return `None` if it can't take effect in this state, like a `down` on a
+    /// semaphore without permits, which waits instead.
+    fn step(&mut self, op: &Self::Op) -> Option<Self::Ret>;
+}
+
+/// One completed operation, with the logical times it was invoked and returned at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event<Op, Ret> {
+    pub op: Op,
+    pub ret: Ret,
+    pub invoke: u64,
+    pub response: u64,
+}
+
+/// Collects the history of a concurrent run.
+///
+/// The clock is a `std` atomic even under loom: it only has to order events, and loom runs one
+/// thread at a time.
+pub struct Recorder<Op, Ret> {
+    clock: AtomicU64,
+    events: Mutex<Vec<Event<Op, Ret>>>,
+}
+
+impl<Op, Ret> Recorder<Op, Ret> {
+    pub fn new() -> Self {
+        Self {
+            clock: AtomicU64::new(0),
+            events: Mutex::new(vec![]),
+        }
+    }
+
+    /// Mark the invocation of an operation, to be completed by [`Self::respond`].
+    pub fn invoke(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::SeqCst)
+    }
+
+    pub fn respond(&self, invoke: u64, op: Op, ret: Ret) {
+        let response = self.clock.fetch_add(1, Ordering::SeqCst);
+        self.events.lock().unwrap().push(Event {
+            op,
+            ret,
+            invoke,
+            response,
+        });
+    }
+
+    /// Run `f` as the operation `op`, recording what it returns.
+    pub fn record(&self, op: Op, f: impl FnOnce() -> Ret) -> Ret
+    where
+        Ret: Clone,
+    {
+        let invoke = self.invoke();
+        let ret = f();
+        self.respond(invoke, op, ret.clone());
+        ret
+    }
+
+    /// Remove and return the events recorded so far.
+    pub fn take(&self) -> Vec<Event<Op, Ret>> {
+        mem::take(&mut self.events.lock().unwrap())
+    }
+}
+
+impl<Op, Ret> Default for Recorder<Op, Ret> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Search for a linearization of `history` starting from `init`, returning the order of the
+/// events in it.
+///
+/// The search is exponential in the worst case; states already shown to be dead ends are
+/// remembered, which keeps histories of a few hundred operations tractable.
+pub fn check<M: Model>(init: M, history: &[Event<M::Op, M::Ret>]) -> Option<Vec<usize>> {
+    let mut search = Search {
+        history,
+        done: vec![0; history.len().div_ceil(64)],
+        order: vec![],
+        dead: HashSet::new(),
+    };
+    search.run(&init).then_some(search.order)
+}
+
+struct Search<'a, M: Model> {
+    history: &'a [Event<M::Op, M::Ret>],
+    /// Bitset of the events in `order`.
+    done: Vec<u64>,
+    order: Vec<usize>,
+    dead: HashSet<(Vec<u64>, M)>,
+}
+
+impl<M: Model> Search<'_, M> {
+    fn is_done(&self, i: usize) -> bool {
+        self.done[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    fn flip(&mut self, i: usize) {
+        self.done[i / 64] ^= 1 << (i % 64);
+    }
+
+    fn run(&mut self, state: &M) -> bool {
+        if self.order.len() == self.history.len() {
+            return true;
+        }
+        if self.dead.contains(&(self.done.clone(), state.clone())) {
+            return false;
+        }
+
+        // Whatever comes next must have been invoked before every pending event returned.
+        let horizon = (0..self.history.len())
+            .filter(|&i| !self.is_done(i))
+            .map(|i| self.history[i].response)
+            .min()
+            .unwrap();
+
+        for i in 0..self.history.len() {
+            let event = &self.history[i];
+            if self.is_done(i) || event.invoke > horizon {
+                continue;
+            }
+
+            let mut next = state.clone();
+            if next.step(&event.op).as_ref() != Some(&event.ret) {
+                continue;
+            }
+
+            self.flip(i);
+            self.order.push(i);
+            if self.run(&next) {
+                return true;
+            }
+            self.order.pop();
+            self.flip(i);
+        }
+
+        self.dead.insert((self.done.clone(), state.clone()));
+        false
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CollectionOp<T> {
+    Push(T),
+    Pop,
+}
+
+/// A LIFO stack. `Push` returns `None`, `Pop` the popped element.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct StackModel<T>(pub Vec<T>);
+
+impl<T: Clone + Eq + Hash> Model for StackModel<T> {
+    type Op = CollectionOp<T>;
+    type Ret = Option<T>;
+
+    fn step(&mut self, op: &Self::Op) -> Option<Self::Ret> {
+        Some(match op {
+            CollectionOp::Push(val) => {
+                self.0.push(val.clone());
+                None
+            }
+            CollectionOp::Pop => self.0.pop(),
+        })
+    }
+}
+
+/// A FIFO queue. `Push` returns `None`, `Pop` the popped element.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct QueueModel<T>(pub VecDeque<T>);
+
+impl<T: Clone + Eq + Hash> Model for QueueModel<T> {
+    type Op = CollectionOp<T>;
+    type Ret = Option<T>;
+
+    fn step(&mut self, op: &Self::Op) -> Option<Self::Ret> {
+        Some(match op {
+            CollectionOp::Push(val) => {
+                self.0.push_back(val.clone());
+                None
+            }
+            CollectionOp::Pop => self.0.pop_front(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SemaphoreOp {
+    Down,
+    Up,
+}
+
+/// A counting semaphore holding its available permits. A lock is one with a single permit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SemaphoreModel(pub usize);
+
+impl Model for SemaphoreModel {
+    type Op = SemaphoreOp;
+    type Ret = ();
+
+    fn step(&mut self, op: &Self::Op) -> Option<Self::Ret> {
+        match op {
+            SemaphoreOp::Down => self.0 = self.0.checked_sub(1)?,
+            SemaphoreOp::Up => self.0 += 1,
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check, CollectionOp::*, Event, QueueModel, SemaphoreModel, SemaphoreOp, StackModel};
+
+    fn event<Op, Ret>(op: Op, ret: Ret, invoke: u64, response: u64) -> Event<Op, Ret> {
+        Event {
+            op,
+            ret,
+            invoke,
+            response,
+        }
+    }
+
+    #[test]
+    fn overlapping_operations_reorder() {
+        // push(1) || push(2), then pop -> 1: a stack needs push(2) to go first, a queue push(1).
+        let history = [
+            event(Push(1), None, 0, 3),
+            event(Push(2), None, 1, 2),
+            event(Pop, Some(1), 4, 5),
+        ];
+        assert_eq!(check(StackModel::default(), &history), Some(vec![1, 0, 2]));
+        assert_eq!(check(QueueModel::default(), &history), Some(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn real_time_order_is_kept() {
+        // push(1) returned before push(2) started, so a stack must pop 2 first.
+        let history = [
+            event(Push(1), None, 0, 1),
+            event(Push(2), None, 2, 3),
+            event(Pop, Some(1), 4, 5),
+        ];
+        assert_eq!(check(StackModel::default(), &history), None);
+        assert!(check(QueueModel::default(), &history).is_some());
+    }
+
+    #[test]
+    fn pop_on_empty_must_fit_somewhere() {
+        // A pop saw nothing while a push overlapped it, so it must go before the push.
+        let history = [event(Push(1), None, 0, 3), event(Pop, None, 1, 2), event(Pop, None, 4, 5)];
+        assert_eq!(check(QueueModel::default(), &history), None);
+        assert!(check(QueueModel::default(), &history[..2]).is_some());
+    }
+
+    #[test]
+    fn blocking_operations_wait_for_permits() {
+        use SemaphoreOp::*;
+
+        // The second down returned before the first holder's up was even invoked.
+        let history = [
+            event(Down, (), 0, 1),
+            event(Down, (), 2, 3),
+            event(Up, (), 4, 5),
+        ];
+        assert_eq!(check(SemaphoreModel(1), &history), None);
+        assert!(check(SemaphoreModel(2), &history).is_some());
+
+        let history = [
+            event(Down, (), 0, 1),
+            event(Down, (), 2, 7),
+            event(Up, (), 3, 4),
+            event(Up, (), 8, 9),
+        ];
+        assert_eq!(check(SemaphoreModel(1), &history), Some(vec![0, 2, 1, 3]));
+    }
+}
@@ -88,3 +88,19 @@ impl Tag for Waker {
         Tagged::new(self)
     }
 }
+
+impl<T: Tag + Clone> Clone for Tagged<T>
+where
+    T::Tag: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            tag: self.tag.clone(),
+            wrapped: self.wrapped.clone(),
+        }
+    }
+}
+
+// The tag of a waker only identifies it and is never dereferenced.
+unsafe impl Send for Tagged<Waker> {}
+unsafe impl Sync for Tagged<Waker> {}